@@ -12,6 +12,8 @@ BUILD_COMMAND="nix build"
 PRIVATE_KEY="${BUILD_SIGNER_KEY:-}"
 OUTPUT_DIR="proofs"
 BUILD_LOG=""
+TEST_LOG=""
+CLAIMS=()
 
 # Parse command line arguments
 usage() {
@@ -24,6 +26,8 @@ Options:
     -k, --key PATH           Path to private key (or set BUILD_SIGNER_KEY env var)
     -o, --output-dir DIR     Output directory for proof (default: proofs/)
     -l, --log PATH           Save build log to this path
+    -t, --test-log PATH      Record this test output in the signed statement
+        --claim PREDICATE    Attest a predicate (repeatable), e.g. "tests-passed" or "coverage >= 80"
     -h, --help               Show this help message
 
 Environment variables:
@@ -56,6 +60,14 @@ while [[ $# -gt 0 ]]; do
             BUILD_LOG="$2"
             shift 2
             ;;
+        -t|--test-log)
+            TEST_LOG="$2"
+            shift 2
+            ;;
+        --claim)
+            CLAIMS+=("$2")
+            shift 2
+            ;;
         -h|--help)
             usage
             exit 0
@@ -154,6 +166,7 @@ BUILD_SIGNER_ARGS=(
     --build-command "$BUILD_COMMAND"
     --private-key "$PRIVATE_KEY"
     --out "$PROOF_FILE"
+    --out-path "$BUILD_RESULT"
 )
 
 if [ -n "$DRV_HASH" ]; then
@@ -164,6 +177,14 @@ if [ -n "$BUILD_LOG_HASH" ]; then
     BUILD_SIGNER_ARGS+=(--build-log-hash "$BUILD_LOG_HASH")
 fi
 
+if [ -n "$TEST_LOG" ]; then
+    BUILD_SIGNER_ARGS+=(--test-log "$TEST_LOG")
+fi
+
+for claim in "${CLAIMS[@]}"; do
+    BUILD_SIGNER_ARGS+=(--claim "$claim")
+done
+
 # Check if build-signer is in PATH
 if ! command -v build-signer &> /dev/null; then
     echo "Error: build-signer not found in PATH"
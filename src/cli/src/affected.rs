@@ -0,0 +1,754 @@
+//! `one affected`: map the files changed since a git ref to the flake checks, examples and
+//! packages they can influence.
+//!
+//! The dependency map is derived from the Nix sources: `lib/default.nix` names every library
+//! function and its file, each file's argument set names the functions it uses, and a check or
+//! example is affected when its definition mentions an affected function or one of its own
+//! source paths changed. Rules in `one.toml` (`[affected]`) take precedence:
+//!
+//! ```toml
+//! [affected]
+//! "docs/**" = ["package:book"]
+//! "ci/*.sh" = ["all"]
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt, fs,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    examples::{self, glob_match},
+    runner::{Cmd, Output, Runner},
+    DEFAULT_EXAMPLE_DIR,
+};
+
+const LIB_DIR: &str = "src/lang/rust/lib";
+const CHECKS_DIR: &str = "src/lang/rust/checks";
+const CRYPTO_DIR: &str = "src/crypto";
+
+const PKGS_DIR: &str = "src/lang/rust/pkgs";
+
+/// Packages built from `src/crypto` (see `src/crypto/pkgs/default.nix`)
+pub const CRYPTO_PACKAGES: &[&str] = &[
+    "build-signer",
+    "build-verifier",
+    "report-status",
+    "zk-prover",
+];
+
+/// The mdbook, built from `docs`, the examples and the top-level markdown files
+const BOOK: &str = "book";
+const BOOK_SOURCES: &[&str] = &[
+    "docs/*",
+    "README.md",
+    "CHANGELOG.md",
+    "src/lang/rust/examples/README.md",
+];
+
+/// Changes here can't affect any flake output
+const IGNORED: &[&str] = &[
+    "*.md",
+    "src/cli/*",
+    "one.toml",
+    "LICENSE",
+    "proofs/*",
+    "prover_keys/*",
+    ".gitignore",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    /// A check from `src/lang/rust/checks` (exposed as `nixpkgs-<name>` and
+    /// `nixpkgs-latest-<name>`)
+    Check(String),
+    Example(String),
+    Package(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Check(name) => write!(f, "check:{name}"),
+            Target::Example(name) => write!(f, "example:{name}"),
+            Target::Package(name) => write!(f, "package:{name}"),
+        }
+    }
+}
+
+impl Target {
+    /// Parse a declared target (`check:NAME`, `example:NAME`, `package:NAME`)
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.split_once(':') {
+            Some(("check", name)) => Target::Check(name.to_string()),
+            Some(("example", name)) => Target::Example(name.to_string()),
+            Some(("package", name)) => Target::Package(name.to_string()),
+            _ => bail!(
+                "invalid target {s:?} (expected check:NAME, example:NAME, package:NAME or all)"
+            ),
+        })
+    }
+}
+
+/// What a single changed file affects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Impact {
+    Ignored,
+    Targets(BTreeSet<Target>),
+    /// Anything could be affected (e.g. `flake.nix` or the reference lock file)
+    All,
+}
+
+/// A check's or example's dependencies
+#[derive(Debug, Default)]
+struct Uses {
+    /// Library functions mentioned
+    functions: BTreeSet<String>,
+    /// Repository-relative paths it is built from
+    paths: Vec<String>,
+    /// Other checks it builds on (`self.<check>`)
+    checks: BTreeSet<String>,
+}
+
+/// Dependencies derived from the repository's Nix sources
+#[derive(Debug, Default)]
+pub struct DepMap {
+    /// Library function -> its file (repository-relative)
+    functions: BTreeMap<String, String>,
+    /// Library function -> functions it takes as arguments
+    function_deps: BTreeMap<String, BTreeSet<String>>,
+    /// Library file -> its contents, to find files it references (hooks, scripts)
+    lib_texts: BTreeMap<String, String>,
+    checks: BTreeMap<String, Uses>,
+    examples: BTreeMap<String, Uses>,
+    /// Declared `(glob, targets)` rules; `None` means "all"
+    rules: Vec<(String, Option<Vec<Target>>)>,
+}
+
+impl DepMap {
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut map = Self::default();
+
+        let lib_default = read(root, &format!("{LIB_DIR}/default.nix"))?;
+        for (name, file) in call_packages(&lib_default) {
+            let rel = normalize(&format!("{LIB_DIR}/{file}"));
+            let file_path = if root.join(&rel).is_dir() {
+                format!("{rel}/default.nix")
+            } else {
+                rel.clone()
+            };
+            if let Ok(text) = fs::read_to_string(root.join(&file_path)) {
+                map.function_deps
+                    .insert(name.clone(), argument_names(&text));
+                map.lib_texts.insert(rel.clone(), text);
+            }
+            map.functions.insert(name, rel);
+        }
+
+        let checks = read(root, &format!("{CHECKS_DIR}/default.nix"))?;
+        for (name, definition) in top_level_attrs(&checks) {
+            let uses = map.uses(root, CHECKS_DIR, &definition);
+            map.checks.insert(name, uses);
+        }
+
+        for example in examples::discover(&root.join(DEFAULT_EXAMPLE_DIR))? {
+            let dir = format!("{DEFAULT_EXAMPLE_DIR}/{}", example.name);
+            let text = read(root, &format!("{dir}/flake.nix"))?;
+            let mut uses = map.uses(root, &dir, &text);
+            uses.paths.push(dir);
+            map.examples.insert(example.name, uses);
+        }
+        Ok(map)
+    }
+
+    /// Add declared rules (`affected.<glob>` in one.toml)
+    pub fn declare(&mut self, glob: &str, targets: &[String]) -> Result<()> {
+        let targets = if targets.iter().any(|t| t == "all") {
+            None
+        } else {
+            Some(
+                targets
+                    .iter()
+                    .map(|t| Target::parse(t))
+                    .collect::<Result<_>>()?,
+            )
+        };
+        self.rules.push((glob.to_string(), targets));
+        Ok(())
+    }
+
+    fn uses(&self, root: &Path, dir: &str, definition: &str) -> Uses {
+        let mut text = definition.to_string();
+        let mut paths = Vec::new();
+        for path in local_paths(definition) {
+            let rel = normalize(&format!("{dir}/{path}"));
+            // Called files (`callPackage ./cargoAudit.nix`) use library functions too
+            let nix = if rel.ends_with(".nix") {
+                rel.clone()
+            } else {
+                format!("{rel}/default.nix")
+            };
+            if let Ok(called) = fs::read_to_string(root.join(nix)) {
+                text.push_str(&called);
+            }
+            paths.push(rel);
+        }
+
+        Uses {
+            functions: self
+                .functions
+                .keys()
+                .filter(|f| contains_word(&text, f))
+                .cloned()
+                .collect(),
+            paths,
+            checks: words_after(&text, "self.").into_iter().collect(),
+        }
+    }
+
+    /// What changing `file` (repository-relative) affects
+    pub fn impact(&self, file: &str) -> Impact {
+        for (glob, targets) in &self.rules {
+            if glob_match(glob, file) {
+                return match targets {
+                    None => Impact::All,
+                    Some(targets) => Impact::Targets(targets.iter().cloned().collect()),
+                };
+            }
+        }
+        if BOOK_SOURCES.iter().any(|glob| glob_match(glob, file)) {
+            return Impact::Targets([Target::Package(BOOK.to_string())].into());
+        }
+        // Before IGNORED: an example's own README is part of the example
+        if let Some((example, _)) = self
+            .examples
+            .iter()
+            .find(|(_, uses)| uses.paths.iter().any(|p| is_under(file, p)))
+        {
+            return Impact::Targets(
+                [
+                    Target::Example(example.clone()),
+                    Target::Package(BOOK.to_string()),
+                ]
+                .into(),
+            );
+        }
+        if IGNORED.iter().any(|glob| glob_match(glob, file)) {
+            return Impact::Ignored;
+        }
+        if is_under(file, CRYPTO_DIR) {
+            return Impact::Targets(
+                CRYPTO_PACKAGES
+                    .iter()
+                    .map(|p| Target::Package(p.to_string()))
+                    .collect(),
+            );
+        }
+
+        let mut targets = BTreeSet::new();
+        if file == format!("{CHECKS_DIR}/default.nix") {
+            targets.extend(self.checks.keys().cloned().map(Target::Check));
+        } else if is_under(file, CHECKS_DIR) {
+            let direct = self
+                .checks
+                .iter()
+                .filter(|(_, uses)| uses.paths.iter().any(|p| is_under(file, p)))
+                .map(|(name, _)| name.clone());
+            targets.extend(
+                self.dependent_checks(direct.collect())
+                    .into_iter()
+                    .map(Target::Check),
+            );
+        } else if file == format!("{LIB_DIR}/default.nix")
+            || file == format!("{PKGS_DIR}/default.nix")
+        {
+            return Impact::All;
+        } else if let Some(functions) = self.changed_functions(file) {
+            if let Some(package) = file
+                .strip_prefix(PKGS_DIR)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .and_then(|rest| rest.split_once('/'))
+            {
+                targets.insert(Target::Package(package.0.to_string()));
+            }
+            let functions = self.dependent_functions(functions);
+            let uses_any = |uses: &Uses| !uses.functions.is_disjoint(&functions);
+            let checks = self
+                .checks
+                .iter()
+                .filter(|(_, uses)| uses_any(uses))
+                .map(|(name, _)| name.clone())
+                .collect();
+            targets.extend(self.dependent_checks(checks).into_iter().map(Target::Check));
+            targets.extend(
+                self.examples
+                    .iter()
+                    .filter(|(_, uses)| uses_any(uses))
+                    .map(|(name, _)| Target::Example(name.clone())),
+            );
+        } else {
+            return Impact::All;
+        }
+        Impact::Targets(targets)
+    }
+
+    /// Library functions defined in or referencing `file`, or `None` if it isn't part of the
+    /// library
+    fn changed_functions(&self, file: &str) -> Option<BTreeSet<String>> {
+        let base = file.rsplit('/').next().unwrap_or(file);
+        let changed: BTreeSet<_> = self
+            .functions
+            .iter()
+            .filter(|(_, path)| {
+                is_under(file, path)
+                    || self
+                        .lib_texts
+                        .get(*path)
+                        .is_some_and(|text| contains_word(text, base))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        (!changed.is_empty() || is_under(file, LIB_DIR)).then_some(changed)
+    }
+
+    /// `functions` and everything taking one of them as an argument
+    fn dependent_functions(&self, mut functions: BTreeSet<String>) -> BTreeSet<String> {
+        loop {
+            let more: Vec<_> = self
+                .function_deps
+                .iter()
+                .filter(|(name, deps)| !functions.contains(*name) && !deps.is_disjoint(&functions))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if more.is_empty() {
+                return functions;
+            }
+            functions.extend(more);
+        }
+    }
+
+    /// `checks` and every check building on one of them (`self.<check>`)
+    fn dependent_checks(&self, mut checks: BTreeSet<String>) -> BTreeSet<String> {
+        loop {
+            let more: Vec<_> = self
+                .checks
+                .iter()
+                .filter(|(name, uses)| !checks.contains(*name) && !uses.checks.is_disjoint(&checks))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if more.is_empty() {
+                return checks;
+            }
+            checks.extend(more);
+        }
+    }
+}
+
+/// The combined impact of a set of changed files
+#[derive(Debug, Default)]
+pub struct Selection {
+    pub all: bool,
+    pub targets: BTreeSet<Target>,
+    pub files: Vec<(String, Impact)>,
+}
+
+impl Selection {
+    pub fn new(map: &DepMap, files: Vec<String>) -> Self {
+        let mut selection = Self::default();
+        for file in files {
+            let impact = map.impact(&file);
+            match &impact {
+                Impact::All => selection.all = true,
+                Impact::Targets(targets) => selection.targets.extend(targets.iter().cloned()),
+                Impact::Ignored => {}
+            }
+            selection.files.push((file, impact));
+        }
+        selection
+    }
+
+    pub fn checks(&self) -> Vec<&str> {
+        self.names(|t| match t {
+            Target::Check(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn examples(&self) -> Vec<&str> {
+        self.names(|t| match t {
+            Target::Example(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn packages(&self) -> Vec<&str> {
+        self.names(|t| match t {
+            Target::Package(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    fn names<'a>(&'a self, pick: impl Fn(&'a Target) -> Option<&'a String>) -> Vec<&'a str> {
+        self.targets
+            .iter()
+            .filter_map(pick)
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn print(&self, explain: bool) {
+        if explain {
+            for (file, impact) in &self.files {
+                let impact = match impact {
+                    Impact::Ignored => "nothing".to_string(),
+                    Impact::All => "everything".to_string(),
+                    Impact::Targets(targets) if targets.is_empty() => "nothing".to_string(),
+                    Impact::Targets(targets) => targets
+                        .iter()
+                        .map(Target::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                println!("{file}: {impact}");
+            }
+            println!();
+        }
+
+        println!("{} changed file(s)", self.files.len());
+        if self.all {
+            println!("everything is affected");
+            return;
+        }
+        for (label, names) in [
+            ("checks", self.checks()),
+            ("examples", self.examples()),
+            ("packages", self.packages()),
+        ] {
+            if !names.is_empty() {
+                println!("{label}: {}", names.join(" "));
+            }
+        }
+    }
+}
+
+/// Files changed since `since` (committed or not) plus untracked ones
+pub fn changed_files(runner: &dyn Runner, root: &Path, since: &str) -> Result<Vec<String>> {
+    let git = |args: &[&str]| -> Result<String> {
+        let args = args.iter().map(OsString::from).collect();
+        let stdout = runner
+            .capture(&Cmd::new("git", args, root), Output::Inherit)?
+            .unwrap_or_default();
+        String::from_utf8(stdout).context("git output is not valid UTF-8")
+    };
+
+    let mut files: BTreeSet<String> = git(&["diff", "--name-only", since])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    files.extend(
+        git(&["ls-files", "--others", "--exclude-standard"])?
+            .lines()
+            .map(str::to_string),
+    );
+    Ok(files.into_iter().collect())
+}
+
+fn read(root: &Path, rel: &str) -> Result<String> {
+    fs::read_to_string(root.join(rel)).with_context(|| format!("failed to read {rel}"))
+}
+
+fn is_under(file: &str, path: &str) -> bool {
+    file == path
+        || file
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Resolve `.` and `..` components of a `/`-separated relative path
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-')
+}
+
+/// Whether `word` occurs in `text` delimited by non-identifier characters
+fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + word.len()..].chars().next();
+        !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+    })
+}
+
+/// Identifiers following `prefix` (e.g. the `x` in `self.x`)
+fn words_after(text: &str, prefix: &str) -> Vec<String> {
+    text.match_indices(prefix)
+        .filter(|(at, _)| !text[..*at].chars().next_back().is_some_and(is_ident_char))
+        .map(|(at, _)| {
+            text[at + prefix.len()..]
+                .chars()
+                .take_while(|&c| is_ident_char(c))
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// `name = callPackage ./file.nix` bindings
+fn call_packages(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, rest) = line.trim().split_once(" = callPackage ")?;
+            let path = rest
+                .split_whitespace()
+                .next()?
+                .strip_prefix("./")
+                .or_else(|| {
+                    rest.split_whitespace()
+                        .next()
+                        .filter(|p| p.starts_with("../"))
+                })?;
+            Some((name.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+/// Names in the leading `{ a, b, ... }:` argument set of a Nix file
+fn argument_names(text: &str) -> BTreeSet<String> {
+    let Some(end) = text.find("}:") else {
+        return BTreeSet::new();
+    };
+    let start = text[..end].find('{').map_or(0, |i| i + 1);
+    text[start..end]
+        .split(',')
+        .filter_map(|arg| {
+            let name = arg.split('?').next()?.trim();
+            (!name.is_empty() && name != "..." && name.chars().all(is_ident_char))
+                .then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Relative paths (`./simple`, `./cargoAudit.nix`) mentioned in a definition
+fn local_paths(text: &str) -> Vec<String> {
+    let mut paths: Vec<String> = text
+        .match_indices("./")
+        .filter(|(at, _)| !text[..*at].ends_with('.'))
+        .filter(|(at, _)| !text[..*at].chars().next_back().is_some_and(is_ident_char))
+        .map(|(at, _)| {
+            text[at + 2..]
+                .chars()
+                .take_while(|&c| is_ident_char(c) || matches!(c, '.' | '/'))
+                .collect::<String>()
+        })
+        .map(|path| path.trim_end_matches(['.', '/']).to_string())
+        .filter(|path| !path.is_empty())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Attributes of the attribute set following the first `in {` (the checks scope), with their
+/// definitions
+fn top_level_attrs(text: &str) -> Vec<(String, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(start) = lines
+        .windows(2)
+        .position(|w| w[0].trim() == "in" && w[1].trim() == "{")
+    else {
+        return Vec::new();
+    };
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let body = &lines[start + 2..];
+    let Some(level) = body
+        .iter()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| indent(l))
+    else {
+        return Vec::new();
+    };
+
+    let mut attrs: Vec<(String, String)> = Vec::new();
+    for line in body {
+        if !line.trim().is_empty() && indent(line) < level {
+            break;
+        }
+        let trimmed = line.trim();
+        if indent(line) == level && !trimmed.starts_with('#') {
+            if let Some((name, _)) = trimmed.split_once(" =") {
+                if name.chars().all(is_ident_char) {
+                    attrs.push((name.to_string(), String::new()));
+                }
+            }
+        }
+        if let Some((_, definition)) = attrs.last_mut() {
+            definition.push_str(line);
+            definition.push('\n');
+        }
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+    }
+
+    fn targets(map: &DepMap, file: &str) -> BTreeSet<Target> {
+        match map.impact(file) {
+            Impact::Targets(targets) => targets,
+            other => panic!("{file}: {other:?}"),
+        }
+    }
+
+    fn check(name: &str) -> Target {
+        Target::Check(name.to_string())
+    }
+
+    #[test]
+    fn parses_nix_snippets() {
+        assert_eq!(
+            argument_names("{\n  clippy,\n  mkCargoDerivation,\n  lib ? null,\n}:\n\n{ x }: x"),
+            ["clippy", "lib", "mkCargoDerivation"]
+                .map(String::from)
+                .into()
+        );
+        assert_eq!(
+            call_packages("      cargoFmt = callPackage ./cargoFmt.nix { };\n      utils = callPackage ../pkgs/utils { };"),
+            [
+                ("cargoFmt".to_string(), "cargoFmt.nix".to_string()),
+                ("utils".to_string(), "../pkgs/utils".to_string())
+            ]
+        );
+        assert_eq!(
+            local_paths("src = ./simple;\nx = callPackage ./cargoAudit.nix { };\ny = ../../x;"),
+            ["cargoAudit.nix", "simple"]
+        );
+        assert!(contains_word("myLib.cargoFmt {", "cargoFmt"));
+        assert!(!contains_word("myLib.cargoFmtThenClippy", "cargoFmt"));
+        assert_eq!(
+            normalize("src/lang/rust/lib/../pkgs/x"),
+            "src/lang/rust/pkgs/x"
+        );
+    }
+
+    #[test]
+    fn derives_checks_from_the_repository() {
+        let map = DepMap::load(&root()).unwrap();
+        assert!(map.checks.len() > 50);
+        assert!(map.checks.contains_key("windowsCross"));
+        // Let-bindings of the scope are not checks
+        assert!(!map.checks.contains_key("myLibLlvmTools"));
+        assert!(!map.checks.contains_key("callPackage"));
+    }
+
+    #[test]
+    fn maps_changed_files() {
+        let map = DepMap::load(&root()).unwrap();
+
+        let clippy = targets(&map, "src/lang/rust/checks/clippy/Cargo.toml");
+        assert_eq!(clippy, [check("clippy")].into());
+
+        // `cargoClippyThenBuild` and `cargoFmtThenClippy` use cargoClippy, `cargoFmt` doesn't
+        let lib = targets(&map, "src/lang/rust/lib/cargoClippy.nix");
+        assert!(lib.contains(&check("cargoClippyThenBuild")), "{lib:?}");
+        assert!(lib.contains(&check("cargoFmtThenClippy")));
+        assert!(!lib.contains(&check("cargoFmt")));
+        assert!(lib.contains(&Target::Example("quick-start".into())));
+
+        // Hooks reach checks through the functions that use them
+        let hook = targets(
+            &map,
+            "src/lang/rust/lib/setupHooks/installCargoArtifactsHook.sh",
+        );
+        assert!(hook.contains(&check("simple")), "{hook:?}");
+
+        // `cargoFmtThenClippy` builds on the `cargoFmt` check
+        let fmt = targets(&map, "src/lang/rust/lib/cargoFmt.nix");
+        assert!(fmt.contains(&check("cargoFmtThenClippy")));
+
+        assert_eq!(
+            targets(&map, "src/lang/rust/examples/trunk/index.html"),
+            [
+                Target::Example("trunk".into()),
+                Target::Package("book".into())
+            ]
+            .into()
+        );
+        // An example's own markdown is part of the example, not ignored
+        assert_eq!(
+            targets(&map, "src/lang/rust/examples/trunk/README.md"),
+            [
+                Target::Example("trunk".into()),
+                Target::Package("book".into())
+            ]
+            .into()
+        );
+        assert_eq!(
+            targets(&map, "src/crypto/build-signer/src/main.rs").len(),
+            4
+        );
+        assert_eq!(
+            targets(&map, "README.md"),
+            [Target::Package("book".into())].into()
+        );
+        assert_eq!(map.impact("src/lang/rust/lib/README.md"), Impact::Ignored);
+        assert_eq!(map.impact("src/cli/src/main.rs"), Impact::Ignored);
+
+        let utils = targets(&map, "src/lang/rust/pkgs/one-for-all-utils/src/main.rs");
+        assert!(
+            utils.contains(&Target::Package("one-for-all-utils".into())),
+            "{utils:?}"
+        );
+        assert!(utils.contains(&check("simple")));
+        assert_eq!(map.impact("flake.nix"), Impact::All);
+        assert_eq!(map.impact("src/lang/rust/test/flake.lock"), Impact::All);
+    }
+
+    #[test]
+    fn declared_rules_win() {
+        let mut map = DepMap::load(&root()).unwrap();
+        map.declare("docs/*", &["package:book".into()]).unwrap();
+        map.declare("ci/*", &["all".into()]).unwrap();
+        assert!(map.declare("x", &["bogus".into()]).is_err());
+
+        assert_eq!(
+            targets(&map, "docs/src/intro.md"),
+            [Target::Package("book".into())].into()
+        );
+        assert_eq!(map.impact("ci/check-example.sh"), Impact::All);
+
+        let selection = Selection::new(
+            &map,
+            vec![
+                "docs/a.md".into(),
+                "src/lang/rust/checks/clippy/src/main.rs".into(),
+            ],
+        );
+        assert!(!selection.all);
+        assert_eq!(selection.checks(), ["clippy"]);
+        assert_eq!(selection.packages(), ["book"]);
+    }
+}
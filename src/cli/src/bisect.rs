@@ -0,0 +1,393 @@
+//! `one bisect`: find the commit that broke a check by driving `git bisect` in a separate
+//! worktree, so the working tree (and any uncommitted changes) stays untouched.
+//!
+//! Each step evaluates the check's derivation first: a commit where it doesn't evaluate can't
+//! say anything about the check, so it is skipped rather than marked bad.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+
+use crate::{
+    fast_check, report,
+    runner::{self, Cmd, Output, Runner},
+};
+
+/// Where the bisect worktree is checked out (relative to the repository root)
+pub const WORKTREE_DIR: &str = ".one/bisect";
+
+/// More steps than any real history needs; guards against a confused `git bisect`
+const MAX_STEPS: usize = 100;
+
+#[derive(Args)]
+pub struct BisectArgs {
+    /// Check to bisect (`nixpkgs-clippy`), or a full flake attribute
+    #[arg(long, value_name = "NAME")]
+    pub check: String,
+    /// A revision where the check passes
+    #[arg(long, value_name = "REF")]
+    pub good: String,
+    /// A revision where the check fails
+    #[arg(long, value_name = "REF", default_value = "HEAD")]
+    pub bad: String,
+    /// Extra arguments forwarded to `nix eval` and `nix build`
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    pub nix_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    /// The check doesn't evaluate; with the error
+    Skip(String),
+}
+
+impl Verdict {
+    fn command(&self) -> &'static str {
+        match self {
+            Verdict::Good => "good",
+            Verdict::Bad => "bad",
+            Verdict::Skip(_) => "skip",
+        }
+    }
+}
+
+/// What `git bisect good|bad|skip` concluded
+#[derive(Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Another commit is checked out
+    Continue,
+    /// The full hash of the first bad commit
+    Found(String),
+    /// Only skipped commits are left; the first bad commit is one of these
+    Ambiguous(Vec<String>),
+}
+
+pub fn parse_progress(output: &str) -> Progress {
+    if let Some(line) = output
+        .lines()
+        .find(|line| line.ends_with(" is the first bad commit"))
+    {
+        return Progress::Found(line.split_whitespace().next().unwrap_or("").to_string());
+    }
+    if output.contains("only 'skip'ped commits left to test") {
+        let candidates = output
+            .lines()
+            .filter(|line| {
+                let word = line.split_whitespace().next().unwrap_or("");
+                word.len() == 40 && word.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+            .map(|line| line.split_whitespace().next().unwrap_or("").to_string())
+            .collect();
+        return Progress::Ambiguous(candidates);
+    }
+    Progress::Continue
+}
+
+struct Bisect<'a> {
+    runner: &'a dyn Runner,
+    worktree: PathBuf,
+    attr: String,
+    nix_args: Vec<OsString>,
+    /// Full hash -> subject of every tested commit
+    subjects: BTreeMap<String, String>,
+    logs: BTreeMap<String, String>,
+}
+
+impl Bisect<'_> {
+    /// Run a command in the worktree; `None` with `--dry-run`
+    fn capture(&self, program: &str, args: &[&str]) -> Result<Option<String>> {
+        let args = args.iter().map(OsString::from).collect();
+        let cmd = Cmd::new(program, args, &self.worktree);
+        match self.runner.capture(&cmd, Output::Inherit)? {
+            Some(stdout) => {
+                Ok(Some(String::from_utf8(stdout).with_context(|| {
+                    format!("{} printed invalid UTF-8", cmd.display())
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The checked-out commit's hash and subject
+    fn head(&mut self) -> Result<Option<String>> {
+        let Some(line) = self.capture("git", &["log", "-1", "--format=%H %s"])? else {
+            return Ok(None);
+        };
+        let (commit, subject) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        self.subjects
+            .insert(commit.to_string(), subject.to_string());
+        Ok(Some(commit.to_string()))
+    }
+
+    /// Build the check at the checked-out commit
+    fn test(&mut self, commit: &str) -> Result<Verdict> {
+        let mut installable = self.worktree.as_os_str().to_owned();
+        installable.push(format!("#{}.drvPath", self.attr));
+        let mut args = vec![
+            OsString::from("eval"),
+            OsString::from("--raw"),
+            OsString::from("--accept-flake-config"),
+            installable,
+        ];
+        args.extend(self.nix_args.iter().cloned());
+        let drv_path = match self
+            .runner
+            .capture(&Cmd::new("nix", args, &self.worktree), Output::Inherit)
+        {
+            Ok(stdout) => String::from_utf8_lossy(&stdout.unwrap_or_default())
+                .trim()
+                .to_string(),
+            Err(err) => {
+                runner::check_interrupted()?;
+                return Ok(Verdict::Skip(format!("{err:#}")));
+            }
+        };
+
+        let mut args = vec![
+            OsString::from("build"),
+            OsString::from("--no-link"),
+            OsString::from("--print-build-logs"),
+            OsString::from(format!("{drv_path}^*")),
+        ];
+        args.extend(self.nix_args.iter().cloned());
+        match self
+            .runner
+            .run(&Cmd::new("nix", args, &self.worktree), Output::Inherit)
+        {
+            Ok(()) => Ok(Verdict::Good),
+            Err(_) => {
+                runner::check_interrupted()?;
+                let log = self
+                    .capture("nix", &["log", &drv_path])
+                    .ok()
+                    .flatten()
+                    .map(|log| report::tail(&log, report::LOG_TAIL_LINES))
+                    .unwrap_or_default();
+                self.logs.insert(commit.to_string(), log);
+                Ok(Verdict::Bad)
+            }
+        }
+    }
+
+    fn describe(&self, commit: &str) -> String {
+        let subject = self.subjects.get(commit).map_or("", String::as_str);
+        format!("{} {subject}", &commit[..commit.len().min(12)])
+    }
+
+    /// Test the checked-out endpoint; `false` with `--dry-run`
+    fn check_endpoint(&mut self, expected: Verdict) -> Result<bool> {
+        let Some(commit) = self.head()? else {
+            return Ok(false);
+        };
+        match self.test(&commit)? {
+            Verdict::Skip(error) => bail!(
+                "{} doesn't evaluate at {}: {error}",
+                self.attr,
+                self.describe(&commit)
+            ),
+            verdict if verdict == expected => println!(
+                "{}: {} (as expected)",
+                self.describe(&commit),
+                verdict.command()
+            ),
+            Verdict::Good => bail!(
+                "{} passes at {}, nothing to bisect",
+                self.attr,
+                self.describe(&commit)
+            ),
+            Verdict::Bad => bail!(
+                "{} already fails at {}, pass an older --good",
+                self.attr,
+                self.describe(&commit)
+            ),
+        }
+        Ok(true)
+    }
+
+    fn run(&mut self, good: &str, bad: &str) -> Result<()> {
+        // `git bisect` trusts its endpoints; a bad one that passes or a good one that fails
+        // would blame a random commit. The worktree starts out at `bad`.
+        if !self.check_endpoint(Verdict::Bad)? {
+            return Ok(());
+        }
+        self.capture("git", &["checkout", "--quiet", "--detach", good])?;
+        self.check_endpoint(Verdict::Good)?;
+
+        let mut progress = self.capture("git", &["bisect", "start", bad, good])?;
+        for step in 1..=MAX_STEPS {
+            match parse_progress(progress.as_deref().unwrap_or("")) {
+                Progress::Continue => {}
+                Progress::Found(commit) => return self.found(&commit),
+                Progress::Ambiguous(candidates) => {
+                    println!("\nthe first bad commit could be any of:");
+                    for commit in &candidates {
+                        println!("  {commit}");
+                    }
+                    bail!("couldn't narrow down the first bad commit: the others don't evaluate");
+                }
+            }
+
+            let Some(commit) = self.head()? else {
+                return Ok(());
+            };
+            let verdict = self.test(&commit)?;
+            match &verdict {
+                Verdict::Skip(error) => {
+                    let error = error.lines().last().unwrap_or("");
+                    println!("[{step}] {}: skip ({error})", self.describe(&commit))
+                }
+                verdict => println!("[{step}] {}: {}", self.describe(&commit), verdict.command()),
+            }
+            progress = self.capture("git", &["bisect", verdict.command()])?;
+        }
+        bail!("gave up after {MAX_STEPS} steps")
+    }
+
+    fn found(&mut self, commit: &str) -> Result<()> {
+        if !self.subjects.contains_key(commit) {
+            if let Some(subject) = self.capture("git", &["log", "-1", "--format=%s", commit])? {
+                self.subjects
+                    .insert(commit.to_string(), subject.trim().to_string());
+            }
+        }
+        println!("\nfirst bad commit: {}", self.describe(commit));
+        match self.logs.get(commit) {
+            Some(log) if !log.is_empty() => {
+                println!(
+                    "\nlog of {} at {}:",
+                    self.attr,
+                    &commit[..commit.len().min(12)]
+                );
+                for line in log.lines() {
+                    println!("  {line}");
+                }
+            }
+            _ => println!("(no build log available)"),
+        }
+        Ok(())
+    }
+}
+
+/// The commit `rev` points at
+fn resolve(runner: &dyn Runner, root: &Path, rev: &str) -> Result<Option<String>> {
+    let args = ["rev-parse", "--verify", &format!("{rev}^{{commit}}")]
+        .map(OsString::from)
+        .to_vec();
+    match runner.capture(&Cmd::new("git", args, root), Output::Inherit)? {
+        Some(stdout) => Ok(Some(
+            String::from_utf8(stdout)
+                .context("git output is not valid UTF-8")?
+                .trim()
+                .to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// `git worktree <args> <worktree> [rev]`
+fn worktree(
+    runner: &dyn Runner,
+    root: &Path,
+    args: &[&str],
+    path: &Path,
+    rev: Option<&str>,
+) -> Result<()> {
+    let mut args: Vec<_> = ["worktree"]
+        .iter()
+        .chain(args)
+        .map(OsString::from)
+        .collect();
+    args.push(path.as_os_str().to_owned());
+    args.extend(rev.map(OsString::from));
+    runner.run(&Cmd::new("git", args, root), Output::Inherit)
+}
+
+pub fn run(runner: &dyn Runner, root: &Path, args: BisectArgs) -> Result<()> {
+    runner::trap_interrupts();
+    let attr = if args.check.contains('.') {
+        args.check.trim_start_matches('#').to_string()
+    } else {
+        let system = fast_check::current_system(runner, root, Output::Inherit)?;
+        format!("checks.{system}.{}", args.check)
+    };
+
+    // Resolved here: in the worktree, `HEAD` would mean something else
+    let (Some(good), Some(bad)) = (
+        resolve(runner, root, &args.good)?,
+        resolve(runner, root, &args.bad)?,
+    ) else {
+        // --dry-run
+        return Ok(());
+    };
+
+    let path = root.join(WORKTREE_DIR);
+    if path.exists() {
+        println!("removing the worktree of an interrupted bisect");
+        worktree(runner, root, &["remove", "--force"], &path, None)?;
+    }
+    worktree(runner, root, &["add", "--detach"], &path, Some(&bad))?;
+
+    let mut bisect = Bisect {
+        runner,
+        worktree: path.clone(),
+        attr,
+        nix_args: args.nix_args.into_iter().map(OsString::from).collect(),
+        subjects: BTreeMap::new(),
+        logs: BTreeMap::new(),
+    };
+    let result = bisect.run(&good, &bad);
+
+    // Clean up even after a failure or Ctrl-C; the bisect state lives in the worktree
+    let reset = bisect.capture("git", &["bisect", "reset"]);
+    let removed = worktree(runner, root, &["remove", "--force"], &path, None);
+    for cleanup in [reset.map(drop), removed] {
+        if let Err(err) = cleanup {
+            eprintln!("warning: failed to clean up {}: {err:#}", path.display());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bisect_progress() {
+        assert_eq!(
+            parse_progress(
+                "Bisecting: 3 revisions left to test after this (roughly 2 steps)\n\
+                 [1b2c3d4e5f60718293a4b5c6d7e8f90123456789] Bump nixpkgs\n"
+            ),
+            Progress::Continue
+        );
+        assert_eq!(
+            parse_progress(
+                "1b2c3d4e5f60718293a4b5c6d7e8f90123456789 is the first bad commit\n\
+                 commit 1b2c3d4e5f60718293a4b5c6d7e8f90123456789\n\
+                 Author: A <a@example.com>\n"
+            ),
+            Progress::Found("1b2c3d4e5f60718293a4b5c6d7e8f90123456789".into())
+        );
+        assert_eq!(
+            parse_progress(
+                "There are only 'skip'ped commits left to test.\n\
+                 The first bad commit could be any of:\n\
+                 1b2c3d4e5f60718293a4b5c6d7e8f90123456789\n\
+                 aaaabbbbccccddddeeeeffff0000111122223333\n\
+                 We cannot bisect more!\n"
+            ),
+            Progress::Ambiguous(vec![
+                "1b2c3d4e5f60718293a4b5c6d7e8f90123456789".into(),
+                "aaaabbbbccccddddeeeeffff0000111122223333".into(),
+            ])
+        );
+    }
+}
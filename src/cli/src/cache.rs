@@ -0,0 +1,692 @@
+//! `one cache`: a Nix binary cache over a local directory, so a team can share build outputs
+//! over a LAN without an external service.
+//!
+//! `one cache push` writes the layout `nix copy --to file://...` would (`nix-cache-info`,
+//! `<hash>.narinfo` per store path and `nar/<file hash>.nar.zst`), signing each narinfo with
+//! a Nix secret key. `one cache serve` serves that directory with the HTTP protocol
+//! substituters speak.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use build_proof::nar::{dump, nix_base32, NIX_BASE32_ALPHABET};
+use clap::{Args, Subcommand, ValueHint};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    fast_check,
+    runner::{Cmd, Output, Runner},
+};
+
+pub const DEFAULT_DIR: &str = ".one/cache";
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:5000";
+
+const STORE_DIR: &str = "/nix/store";
+
+/// Substituters are tried by ascending priority; cache.nixos.org has 40
+const PRIORITY: u32 = 30;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Serve the cache directory as a Nix binary cache over HTTP
+    Serve(ServeArgs),
+    /// Build an attribute and add its runtime closure to the cache directory
+    Push(PushArgs),
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address to listen on; use `0.0.0.0:5000` to share over the LAN [default: 127.0.0.1:5000]
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+    /// Cache directory, relative to the repository root [default: .one/cache]
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct PushArgs {
+    /// Flake attribute to build; a bare name means `checks.<system>.<name>`
+    pub attr: String,
+    /// Cache directory, relative to the repository root [default: .one/cache]
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub dir: Option<PathBuf>,
+    /// Nix secret key to sign narinfos with (`nix key generate-secret --key-name NAME`)
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub secret_key_file: Option<PathBuf>,
+    /// Read store paths from the chroot store at DIR (nix's `--store DIR`) instead of `/`
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub store: Option<PathBuf>,
+}
+
+/// A Nix signing key, `<name>:<base64 of the 64-byte secret key>`
+pub struct SecretKey {
+    pub name: String,
+    key: SigningKey,
+}
+
+impl SecretKey {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let (name, encoded) = contents
+            .trim()
+            .split_once(':')
+            .context("expected `<name>:<base64 key>` (from `nix key generate-secret`)")?;
+        let bytes = BASE64
+            .decode(encoded)
+            .context("the secret key is not valid base64")?;
+        let keypair: [u8; 64] = bytes.as_slice().try_into().map_err(|_| {
+            anyhow::anyhow!("expected a 64-byte ed25519 key, got {} bytes", bytes.len())
+        })?;
+        let key = SigningKey::from_keypair_bytes(&keypair)
+            .context("the secret key doesn't match its public half")?;
+        Ok(Self {
+            name: name.to_string(),
+            key,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("invalid secret key {}", path.display()))
+    }
+
+    /// `<name>:<base64>`, what clients put in `trusted-public-keys`
+    pub fn public_key(&self) -> String {
+        format!(
+            "{}:{}",
+            self.name,
+            BASE64.encode(self.key.verifying_key().to_bytes())
+        )
+    }
+
+    /// `<name>:<base64 signature>`
+    pub fn sign(&self, fingerprint: &str) -> String {
+        let signature = self.key.sign(fingerprint.as_bytes());
+        format!("{}:{}", self.name, BASE64.encode(signature.to_bytes()))
+    }
+}
+
+/// Everything a `.narinfo` describes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NarInfo {
+    pub store_path: String,
+    /// `nar/<file hash>.nar.zst`
+    pub url: String,
+    pub file_hash: String,
+    pub file_size: u64,
+    pub nar_hash: String,
+    pub nar_size: u64,
+    /// Full store paths, sorted
+    pub references: Vec<String>,
+    pub deriver: Option<String>,
+    pub sigs: Vec<String>,
+}
+
+impl NarInfo {
+    /// What Nix signs: `1;<path>;<nar hash>;<nar size>;<comma-separated references>`
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "1;{};{};{};{}",
+            self.store_path,
+            self.nar_hash,
+            self.nar_size,
+            self.references.join(",")
+        )
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "StorePath: {}\nURL: {}\nCompression: zstd\nFileHash: {}\nFileSize: {}\n\
+             NarHash: {}\nNarSize: {}\nReferences: {}\n",
+            self.store_path,
+            self.url,
+            self.file_hash,
+            self.file_size,
+            self.nar_hash,
+            self.nar_size,
+            self.references
+                .iter()
+                .map(|r| base_name(r))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        if let Some(deriver) = &self.deriver {
+            text.push_str(&format!("Deriver: {}\n", base_name(deriver)));
+        }
+        for sig in &self.sigs {
+            text.push_str(&format!("Sig: {sig}\n"));
+        }
+        text
+    }
+}
+
+fn base_name(store_path: &str) -> &str {
+    store_path.rsplit('/').next().unwrap_or(store_path)
+}
+
+/// The hash part of a store path, which names its narinfo
+pub fn hash_part(store_path: &str) -> Result<&str> {
+    let name = base_name(store_path);
+    match name.split_once('-') {
+        Some((hash, _))
+            if hash.len() == 32 && hash.bytes().all(|b| NIX_BASE32_ALPHABET.contains(&b)) =>
+        {
+            Ok(hash)
+        }
+        _ => bail!("not a store path: {store_path}"),
+    }
+}
+
+/// Counts and hashes what passes through
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    /// `sha256:<base32>` and the byte count
+    fn finish(self) -> (W, String, u64) {
+        let hash = format!("sha256:{}", nix_base32(&self.hasher.finalize()));
+        (self.inner, hash, self.len)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// One entry of `nix path-info --json`
+#[derive(Debug, Deserialize)]
+struct PathInfo {
+    /// Only in the array form of older nix versions
+    path: Option<String>,
+    #[serde(default)]
+    references: Vec<String>,
+    deriver: Option<String>,
+}
+
+/// `nix path-info --json` output: an array before nix 2.19, an object keyed by path since
+fn parse_path_info(json: &str) -> Result<Vec<(String, PathInfo)>> {
+    let value: serde_json::Value =
+        serde_json::from_str(json).context("failed to parse `nix path-info --json` output")?;
+    let infos = match value {
+        serde_json::Value::Array(items) => items
+            .into_iter()
+            .map(|item| {
+                let info: PathInfo = serde_json::from_value(item)?;
+                let path = info
+                    .path
+                    .clone()
+                    .context("path-info entry without a path")?;
+                Ok((path, info))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        serde_json::Value::Object(items) => items
+            .into_iter()
+            .map(|(path, item)| {
+                if item.is_null() {
+                    bail!("{path} is not valid in the store");
+                }
+                Ok((path, serde_json::from_value(item)?))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => bail!("unexpected `nix path-info --json` output"),
+    };
+    Ok(infos)
+}
+
+/// Adds store paths to a cache directory
+pub struct Cache {
+    dir: PathBuf,
+    /// Where `/nix/store` is found (`/` unless it's a chroot store)
+    store_root: PathBuf,
+    key: Option<SecretKey>,
+}
+
+impl Cache {
+    pub fn new(dir: &Path, store_root: Option<&Path>, key: Option<SecretKey>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            store_root: store_root.unwrap_or(Path::new("/")).to_path_buf(),
+            key,
+        }
+    }
+
+    fn init(&self) -> Result<()> {
+        fs::create_dir_all(self.dir.join("nar"))
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let info = self.dir.join("nix-cache-info");
+        if !info.exists() {
+            fs::write(
+                &info,
+                format!("StoreDir: {STORE_DIR}\nWantMassQuery: 1\nPriority: {PRIORITY}\n"),
+            )
+            .with_context(|| format!("failed to write {}", info.display()))?;
+        }
+        Ok(())
+    }
+
+    fn narinfo_path(&self, store_path: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.narinfo", hash_part(store_path)?)))
+    }
+
+    pub fn contains(&self, store_path: &str) -> Result<bool> {
+        Ok(self.narinfo_path(store_path)?.exists())
+    }
+
+    /// Compress the NAR of `store_path` into `nar/` and write its (signed) narinfo; the
+    /// narinfo goes last so the cache never advertises a missing NAR
+    pub fn add(
+        &self,
+        store_path: &str,
+        references: &[String],
+        deriver: Option<&str>,
+    ) -> Result<NarInfo> {
+        self.init()?;
+        let source = self.store_root.join(store_path.trim_start_matches('/'));
+        let partial = self
+            .dir
+            .join("nar")
+            .join(format!("{}.nar.zst.partial", hash_part(store_path)?));
+
+        let file = File::create(&partial)
+            .with_context(|| format!("failed to create {}", partial.display()))?;
+        let compressed = HashingWriter::new(BufWriter::new(file));
+        let encoder = zstd::Encoder::new(compressed, ZSTD_LEVEL)?;
+        let mut nar = HashingWriter::new(encoder);
+        dump(&source, &mut nar)?;
+        let (encoder, nar_hash, nar_size) = nar.finish();
+        let (file, file_hash, file_size) = encoder.finish()?.finish();
+        file.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+
+        let url = format!("nar/{}.nar.zst", file_hash.trim_start_matches("sha256:"));
+        fs::rename(&partial, self.dir.join(&url))
+            .with_context(|| format!("failed to write {}", self.dir.join(&url).display()))?;
+
+        let mut references = references.to_vec();
+        references.sort();
+        let mut info = NarInfo {
+            store_path: store_path.to_string(),
+            url,
+            file_hash,
+            file_size,
+            nar_hash,
+            nar_size,
+            references,
+            deriver: deriver.map(str::to_string),
+            sigs: Vec::new(),
+        };
+        if let Some(key) = &self.key {
+            info.sigs.push(key.sign(&info.fingerprint()));
+        }
+        let path = self.narinfo_path(store_path)?;
+        fs::write(&path, info.render())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(info)
+    }
+}
+
+/// `one cache push`: build `attr` and add its runtime closure to `dir`
+pub fn push(
+    runner: &dyn Runner,
+    root: &Path,
+    dir: &Path,
+    args: &PushArgs,
+    key: Option<SecretKey>,
+) -> Result<()> {
+    let attr = if args.attr.contains('.') {
+        args.attr.trim_start_matches('#').to_string()
+    } else {
+        let system = fast_check::current_system(runner, root, Output::Inherit)?;
+        format!("checks.{system}.{}", args.attr)
+    };
+    let store_args: Vec<OsString> = match &args.store {
+        Some(store) => vec![OsString::from("--store"), store.as_os_str().to_owned()],
+        None => Vec::new(),
+    };
+
+    let mut installable = root.as_os_str().to_owned();
+    installable.push(format!("#{attr}"));
+    let mut build_args = vec![
+        OsString::from("build"),
+        OsString::from("--no-link"),
+        OsString::from("--print-out-paths"),
+        OsString::from("--accept-flake-config"),
+    ];
+    build_args.extend(store_args.iter().cloned());
+    build_args.push(installable);
+    let Some(stdout) = runner.capture(&Cmd::new("nix", build_args, root), Output::Inherit)? else {
+        // --dry-run
+        return Ok(());
+    };
+    let outputs: Vec<_> = String::from_utf8(stdout)
+        .context("`nix build` output is not valid UTF-8")?
+        .split_whitespace()
+        .map(OsString::from)
+        .collect();
+    if outputs.is_empty() {
+        bail!("`nix build` printed no output paths for {attr}");
+    }
+
+    let mut info_args = vec![
+        OsString::from("path-info"),
+        OsString::from("--recursive"),
+        OsString::from("--json"),
+    ];
+    info_args.extend(store_args);
+    info_args.extend(outputs);
+    let Some(stdout) = runner.capture(&Cmd::new("nix", info_args, root), Output::Inherit)? else {
+        return Ok(());
+    };
+    let infos = parse_path_info(&String::from_utf8_lossy(&stdout))?;
+
+    if key.is_none() {
+        println!(
+            "warning: pushing unsigned narinfos; clients need them signed by a key in \
+             `trusted-public-keys` (see --secret-key-file)"
+        );
+    }
+    let cache = Cache::new(dir, args.store.as_deref(), key);
+    let (mut pushed, mut cached, mut bytes) = (0, 0, 0);
+    for (path, info) in &infos {
+        if cache.contains(path)? {
+            cached += 1;
+            continue;
+        }
+        let narinfo = cache.add(path, &info.references, info.deriver.as_deref())?;
+        println!("pushed {path}");
+        pushed += 1;
+        bytes += narinfo.file_size;
+    }
+    println!(
+        "{pushed} paths pushed ({:.1} MiB), {cached} already cached, in {}",
+        bytes as f64 / (1024.0 * 1024.0),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Serves a cache directory over HTTP
+pub struct Server {
+    http: tiny_http::Server,
+    dir: PathBuf,
+}
+
+impl Server {
+    pub fn bind(listen: &str, dir: &Path) -> Result<Self> {
+        let http = tiny_http::Server::http(listen)
+            .map_err(|err| anyhow::anyhow!("failed to listen on {listen}: {err}"))?;
+        Ok(Self {
+            http,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// The bound address (useful with port 0)
+    pub fn addr(&self) -> String {
+        self.http.server_addr().to_string()
+    }
+
+    pub fn run(&self) -> Result<()> {
+        loop {
+            self.handle_next()?;
+        }
+    }
+
+    pub fn handle_next(&self) -> Result<()> {
+        let request = self.http.recv()?;
+        let (status, file, content_type) = match self.resolve(request.method(), request.url()) {
+            Some((file, content_type)) => match File::open(&file) {
+                Ok(file) => (200, Some(file), content_type),
+                Err(_) => (404, None, "text/plain"),
+            },
+            None => (404, None, "text/plain"),
+        };
+        let header = tiny_http::Header::from_bytes("Content-Type", content_type)
+            .expect("static header is valid");
+        let result = match file {
+            Some(file) => request.respond(
+                tiny_http::Response::from_file(file)
+                    .with_status_code(status)
+                    .with_header(header),
+            ),
+            None => request.respond(
+                tiny_http::Response::from_string("not found\n")
+                    .with_status_code(status)
+                    .with_header(header),
+            ),
+        };
+        // A client hanging up mid-response is its problem, not the server's
+        if let Err(err) = result {
+            eprintln!("failed to respond: {err}");
+        }
+        Ok(())
+    }
+
+    /// The file behind a request path, and its content type; only the binary cache layout is
+    /// served, so nothing outside the directory can be reached
+    fn resolve(&self, method: &tiny_http::Method, url: &str) -> Option<(PathBuf, &'static str)> {
+        if !matches!(method, tiny_http::Method::Get | tiny_http::Method::Head) {
+            return None;
+        }
+        let path = url.split('?').next().unwrap_or(url).trim_start_matches('/');
+        let base32 = |s: &str| !s.is_empty() && s.bytes().all(|b| NIX_BASE32_ALPHABET.contains(&b));
+        if path == "nix-cache-info" {
+            return Some((self.dir.join(path), "text/x-nix-cache-info"));
+        }
+        if let Some(hash) = path.strip_suffix(".narinfo") {
+            return (hash.len() == 32 && base32(hash))
+                .then(|| (self.dir.join(path), "text/x-nix-narinfo"));
+        }
+        let nar = path.strip_prefix("nar/")?;
+        base32(nar.strip_suffix(".nar.zst")?)
+            .then(|| (self.dir.join("nar").join(nar), "application/x-nix-nar"))
+    }
+}
+
+/// `one cache serve`
+pub fn serve(dir: &Path, listen: &str, public_key: Option<String>) -> Result<()> {
+    if !dir.join("nix-cache-info").exists() {
+        println!(
+            "warning: {} has no nix-cache-info yet; run `one cache push <attr>` first",
+            dir.display()
+        );
+    }
+    let server = Server::bind(listen, dir)?;
+    let addr = server.addr();
+    println!("serving {} on http://{addr}", dir.display());
+    // Clients on other machines need a reachable address
+    let url = match addr.strip_prefix("0.0.0.0:") {
+        Some(port) => format!("http://<this host>:{port}"),
+        None => format!("http://{addr}"),
+    };
+    println!("use it with: --option extra-substituters {url}");
+    match public_key {
+        Some(public_key) => {
+            println!("             --option extra-trusted-public-keys {public_key}")
+        }
+        None => println!("(no cache.secret-key-file configured, so no public key to trust)"),
+    }
+    server.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        thread,
+    };
+
+    use super::*;
+
+    /// A `nix key generate-secret` style key with a fixed seed
+    fn secret_key() -> String {
+        let keypair = SigningKey::from_bytes(&[7; 32]).to_keypair_bytes();
+        format!("test-cache-1:{}", BASE64.encode(keypair))
+    }
+
+    fn nar(path: &Path) -> Vec<u8> {
+        let mut out = Vec::new();
+        dump(path, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn signs_narinfos_like_nix() {
+        let key = SecretKey::parse(&secret_key()).unwrap();
+        assert_eq!(
+            key.public_key(),
+            "test-cache-1:6kpsY+KcUgq+9VB7Ey7F+ZVHdq6+vnuSQh7qaRRG0iw="
+        );
+        let info = NarInfo {
+            store_path: "/nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12".into(),
+            url: "nar/1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar.zst".into(),
+            file_hash: "sha256:1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3".into(),
+            file_size: 50088,
+            nar_hash: "sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73".into(),
+            nar_size: 226560,
+            references: vec![
+                "/nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12".into(),
+                "/nix/store/9y8pmvk8gdwwznmkzxa6pwyah52xy3nk-glibc-2.40".into(),
+            ],
+            deriver: Some("/nix/store/ib3sh3pcz10wsmavxvkdbayhqivbghlq-hello-2.12.drv".into()),
+            sigs: Vec::new(),
+        };
+        assert_eq!(
+            info.fingerprint(),
+            "1;/nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12;\
+             sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73;226560;\
+             /nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12,\
+             /nix/store/9y8pmvk8gdwwznmkzxa6pwyah52xy3nk-glibc-2.40"
+        );
+        let sig = key.sign(&info.fingerprint());
+        let (name, signature) = sig.split_once(':').unwrap();
+        assert_eq!(name, "test-cache-1");
+        let signature =
+            ed25519_dalek::Signature::from_slice(&BASE64.decode(signature).unwrap()).unwrap();
+        key.key
+            .verifying_key()
+            .verify_strict(info.fingerprint().as_bytes(), &signature)
+            .unwrap();
+
+        let rendered = info.render();
+        assert!(rendered.contains(
+            "References: 0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12 \
+             9y8pmvk8gdwwznmkzxa6pwyah52xy3nk-glibc-2.40\n"
+        ));
+        assert!(rendered.contains("Deriver: ib3sh3pcz10wsmavxvkdbayhqivbghlq-hello-2.12.drv\n"));
+        assert!(SecretKey::parse("no-colon").is_err());
+        assert!(SecretKey::parse("name:aGVsbG8=").is_err());
+    }
+
+    #[test]
+    fn parses_both_path_info_formats() {
+        let old = r#"[{"path":"/nix/store/a","references":["/nix/store/b"],"deriver":"/nix/store/a.drv"}]"#;
+        let new = r#"{"/nix/store/a":{"references":["/nix/store/b"],"deriver":null}}"#;
+        for json in [old, new] {
+            let infos = parse_path_info(json).unwrap();
+            assert_eq!(infos[0].0, "/nix/store/a");
+            assert_eq!(infos[0].1.references, ["/nix/store/b"]);
+        }
+        assert!(parse_path_info(r#"{"/nix/store/a":null}"#).is_err());
+    }
+
+    fn get(addr: &str, path: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.0\r\nHost: {addr}\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..split]).into_owned();
+        (head, response[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn serves_pushed_paths() {
+        let store_dir = tempfile::tempdir().unwrap();
+        let store = store_dir.path();
+        let store_path = "/nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12";
+        let source = store.join(store_path.trim_start_matches('/'));
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("greeting"), "hello\n").unwrap();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let dir = cache_dir.path();
+        let cache = Cache::new(
+            dir,
+            Some(store),
+            Some(SecretKey::parse(&secret_key()).unwrap()),
+        );
+        assert!(!cache.contains(store_path).unwrap());
+        let info = cache.add(store_path, &[], None).unwrap();
+        assert!(cache.contains(store_path).unwrap());
+        assert_eq!(info.nar_hash, build_proof::nar::nar_hash(&source).unwrap());
+
+        let server = Server::bind("127.0.0.1:0", dir).unwrap();
+        let addr = server.addr();
+        let requests = 5;
+        let handle = thread::spawn(move || {
+            for _ in 0..requests {
+                server.handle_next().unwrap();
+            }
+        });
+
+        let (head, body) = get(&addr, "/nix-cache-info");
+        assert!(
+            head.starts_with("HTTP/1.0 200") || head.starts_with("HTTP/1.1 200"),
+            "{head}"
+        );
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "StoreDir: /nix/store\nWantMassQuery: 1\nPriority: 30\n"
+        );
+
+        let (head, body) = get(&addr, "/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs.narinfo");
+        assert!(head.contains("text/x-nix-narinfo"), "{head}");
+        let narinfo = String::from_utf8(body).unwrap();
+        assert_eq!(narinfo, info.render());
+        assert!(narinfo.contains("Sig: test-cache-1:"));
+
+        let (_, body) = get(&addr, &format!("/{}", info.url));
+        assert_eq!(zstd::decode_all(&body[..]).unwrap(), nar(&source));
+
+        let (head, _) = get(&addr, "/00000000000000000000000000000000.narinfo");
+        assert!(head.contains(" 404"), "{head}");
+        let (head, _) = get(&addr, "/../../etc/passwd");
+        assert!(head.contains(" 404"), "{head}");
+
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,489 @@
+//! `one.toml`: per-subcommand defaults, named profiles and example aliases.
+//!
+//! Layers, from lowest to highest precedence:
+//!
+//! 1. built-in defaults
+//! 2. the user config (`$ONE_USER_CONFIG`, else `$XDG_CONFIG_HOME/one/one.toml`, else
+//!    `~/.config/one/one.toml`)
+//! 3. `one.toml` in the repository root
+//! 4. the selected profile (`--profile NAME`), user then repository `[profiles.NAME]`
+//!
+//! Command-line flags override all of them; configured `nix-args` are passed before the ones
+//! given with `--nix-arg`.
+//!
+//! ```toml
+//! [nix]
+//! extra-args = ["--option", "max-jobs", "8"]
+//!
+//! [test]
+//! jobs = 8
+//!
+//! [aliases]
+//! qs = "quick-start"
+//!
+//! [affected]
+//! "docs/*" = ["all"]
+//!
+//! [profiles.ci.check]
+//! fast = true
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use toml::{Table, Value};
+
+pub const FILE_NAME: &str = "one.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    String,
+    Strings,
+    Bool,
+    Integer,
+}
+
+/// Every supported key (`section.key`), excluding `aliases.*` and `affected.*`
+const KEYS: &[(&str, Kind)] = &[
+    ("nix.extra-args", Kind::Strings),
+    ("build.attribute", Kind::String),
+    ("build.nix-args", Kind::Strings),
+    ("build.progress", Kind::Bool),
+    ("check.verbose", Kind::Bool),
+    ("check.no-build", Kind::Bool),
+    ("check.fast", Kind::Bool),
+    ("check.batch-size", Kind::Integer),
+    ("check.allow-write-lock", Kind::Bool),
+    ("check.progress", Kind::Bool),
+    ("check.nix-args", Kind::Strings),
+    ("ci.extra-args", Kind::Strings),
+    ("test.nixpkgs-from", Kind::String),
+    ("test.jobs", Kind::Integer),
+    ("test.nix-args", Kind::Strings),
+    ("develop.attribute", Kind::String),
+    ("develop.nix-args", Kind::Strings),
+    ("watch.debounce", Kind::String),
+    ("history.record", Kind::Bool),
+    ("history.threshold", Kind::Integer),
+    ("cache.dir", Kind::String),
+    ("cache.listen", Kind::String),
+    ("cache.secret-key-file", Kind::String),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Profile { name: String, path: PathBuf },
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Profile { name, path } => write!(f, "profile {name:?} ({})", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub value: Value,
+    pub source: Source,
+}
+
+/// The merged configuration, keyed by `section.key`
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: BTreeMap<String, Entry>,
+    /// Every file that was looked at, and whether it existed
+    files: Vec<(PathBuf, bool)>,
+}
+
+/// Where the user config lives, if a location can be determined
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("ONE_USER_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("one").join(FILE_NAME))
+}
+
+impl Config {
+    /// Load the user and repository configs and apply `profile`
+    pub fn load(root: &Path, profile: Option<&str>) -> Result<Self> {
+        let mut paths = Vec::new();
+        paths.extend(user_config_path());
+        paths.push(root.join(FILE_NAME));
+
+        let mut files = Vec::new();
+        let mut layers = Vec::new();
+        for path in paths {
+            match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    let table: Table = toml::from_str(&contents)
+                        .with_context(|| format!("failed to parse {}", path.display()))?;
+                    layers.push((path.clone(), table));
+                    files.push((path, true));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => files.push((path, false)),
+                Err(err) => {
+                    return Err(err).with_context(|| format!("failed to read {}", path.display()))
+                }
+            }
+        }
+
+        let mut config = Self::from_layers(layers, profile)?;
+        config.files = files;
+        Ok(config)
+    }
+
+    /// Merge parsed config files (lowest precedence first) on top of the defaults
+    pub fn from_layers(layers: Vec<(PathBuf, Table)>, profile: Option<&str>) -> Result<Self> {
+        let mut config = Self::default();
+        for (key, value) in defaults() {
+            config.entries.insert(
+                key.to_string(),
+                Entry {
+                    value,
+                    source: Source::Default,
+                },
+            );
+        }
+
+        let mut profiles = Vec::new();
+        for (path, mut table) in layers {
+            if let Some(value) = table.remove("profiles") {
+                let Value::Table(named) = value else {
+                    bail!("{}: `profiles` must be a table", path.display());
+                };
+                for (name, value) in named {
+                    let Value::Table(table) = value else {
+                        bail!("{}: profile {name:?} must be a table", path.display());
+                    };
+                    profiles.push((name, path.clone(), table));
+                }
+            }
+            config.merge(table, Source::File(path))?;
+        }
+
+        // Profiles are validated even when not selected, so typos show up early
+        let mut selected = false;
+        for (name, path, table) in profiles {
+            let is_selected = profile == Some(name.as_str());
+            let source = Source::Profile { name, path };
+            if is_selected {
+                selected = true;
+                config.merge(table, source)?;
+            } else {
+                Self::default().merge(table, source)?;
+            }
+        }
+        if let Some(profile) = profile {
+            if !selected {
+                bail!("profile {profile:?} is not defined in any {FILE_NAME}");
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn merge(&mut self, table: Table, source: Source) -> Result<()> {
+        for (section, value) in table {
+            let Value::Table(keys) = value else {
+                bail!("{source}: `{section}` must be a table");
+            };
+            for (key, value) in keys {
+                let full = format!("{section}.{key}");
+                let kind = if section == "aliases" {
+                    Kind::String
+                } else if section == "affected" {
+                    Kind::Strings
+                } else {
+                    KEYS.iter()
+                        .find(|(known, _)| *known == full)
+                        .map(|(_, kind)| *kind)
+                        .with_context(|| format!("{source}: unknown key `{full}`"))?
+                };
+                check_kind(&value, kind).with_context(|| format!("{source}: `{full}`"))?;
+                self.entries.insert(
+                    full,
+                    Entry {
+                        value,
+                        source: source.clone(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .and_then(|e| e.value.as_str())
+            .map(str::to_string)
+    }
+
+    pub fn strings(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .and_then(|e| e.value.as_array())
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn bool(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.value.as_bool())
+            .unwrap_or(false)
+    }
+
+    pub fn usize(&self, key: &str) -> Option<usize> {
+        self.get(key)
+            .and_then(|e| e.value.as_integer())
+            .and_then(|n| usize::try_from(n).ok())
+    }
+
+    /// Arguments for `<section>`'s nix invocation: `nix.extra-args`, then
+    /// `<section>.nix-args`, then the ones from the command line
+    pub fn nix_args(&self, section: &str, cli: Vec<String>) -> Vec<String> {
+        let mut args = self.strings("nix.extra-args");
+        args.extend(self.strings(&format!("{section}.nix-args")));
+        args.extend(cli);
+        args
+    }
+
+    /// Resolve an example alias, or return the name unchanged
+    pub fn example(&self, name: &str) -> String {
+        self.string(&format!("aliases.{name}"))
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// `[affected]` rules (glob -> targets), for `one affected`
+    pub fn affected_rules(&self) -> Vec<(String, Vec<String>)> {
+        self.entries
+            .keys()
+            .filter_map(|key| key.strip_prefix("affected."))
+            .map(|glob| (glob.to_string(), self.strings(&format!("affected.{glob}"))))
+            .collect()
+    }
+
+    /// `one config show`: the effective values and where each came from
+    pub fn show(&self) -> String {
+        let mut out = String::new();
+        for (path, found) in &self.files {
+            let status = if *found { "loaded" } else { "not found" };
+            out.push_str(&format!("# {} ({status})\n", path.display()));
+        }
+
+        let rendered: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (format!("{key} = {}", entry.value), &entry.source))
+            .collect();
+        let width = rendered
+            .iter()
+            .map(|(line, _)| line.len())
+            .max()
+            .unwrap_or(0);
+        for (line, source) in rendered {
+            out.push_str(&format!("{line:width$}  # {source}\n"));
+        }
+        out
+    }
+}
+
+fn check_kind(value: &Value, kind: Kind) -> Result<()> {
+    let ok = match kind {
+        Kind::String => value.is_str(),
+        Kind::Strings => value
+            .as_array()
+            .is_some_and(|values| values.iter().all(Value::is_str)),
+        Kind::Bool => value.is_bool(),
+        Kind::Integer => value.as_integer().is_some_and(|n| n >= 0),
+    };
+    if !ok {
+        bail!(
+            "expected {}",
+            match kind {
+                Kind::String => "a string",
+                Kind::Strings => "an array of strings",
+                Kind::Bool => "a boolean",
+                Kind::Integer => "a non-negative integer",
+            }
+        );
+    }
+    Ok(())
+}
+
+fn defaults() -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "check.batch-size",
+            Value::Integer(crate::fast_check::DEFAULT_BATCH_SIZE as i64),
+        ),
+        (
+            "test.nixpkgs-from",
+            Value::String(crate::DEFAULT_NIXPKGS_FROM.to_string()),
+        ),
+        (
+            "test.jobs",
+            Value::Integer(crate::examples::DEFAULT_JOBS as i64),
+        ),
+        ("history.record", Value::Boolean(true)),
+        (
+            "history.threshold",
+            Value::Integer(crate::history::DEFAULT_THRESHOLD as i64),
+        ),
+        (
+            "cache.dir",
+            Value::String(crate::cache::DEFAULT_DIR.to_string()),
+        ),
+        (
+            "cache.listen",
+            Value::String(crate::cache::DEFAULT_LISTEN.to_string()),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(toml: &str) -> Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn layers() -> Vec<(PathBuf, Table)> {
+        vec![
+            (
+                PathBuf::from("user.toml"),
+                table(
+                    r#"
+                    [test]
+                    jobs = 2
+                    nix-args = ["--keep-going"]
+
+                    [aliases]
+                    qs = "quick-start-simple"
+
+                    [profiles.ci.test]
+                    jobs = 1
+                    "#,
+                ),
+            ),
+            (
+                PathBuf::from("repo.toml"),
+                table(
+                    r#"
+                    [nix]
+                    extra-args = ["--option", "sandbox", "true"]
+
+                    [aliases]
+                    qs = "quick-start"
+
+                    [affected]
+                    "ci/*.sh" = ["all"]
+
+                    [profiles.ci.check]
+                    fast = true
+                    batch-size = 4
+                    "#,
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn later_layers_win_and_sources_are_tracked() {
+        let config = Config::from_layers(layers(), None).unwrap();
+        assert_eq!(config.usize("test.jobs"), Some(2));
+        assert_eq!(
+            config.get("test.jobs").unwrap().source,
+            Source::File("user.toml".into())
+        );
+        assert_eq!(config.example("qs"), "quick-start");
+        assert_eq!(config.example("trunk"), "trunk");
+        assert_eq!(
+            config.affected_rules(),
+            [("ci/*.sh".to_string(), vec!["all".to_string()])]
+        );
+        assert_eq!(config.usize("check.batch-size"), Some(16));
+        assert_eq!(
+            config.get("check.batch-size").unwrap().source,
+            Source::Default
+        );
+        assert!(!config.bool("check.fast"));
+    }
+
+    #[test]
+    fn profiles_override_files() {
+        let config = Config::from_layers(layers(), Some("ci")).unwrap();
+        assert!(config.bool("check.fast"));
+        assert_eq!(config.usize("check.batch-size"), Some(4));
+        assert_eq!(config.usize("test.jobs"), Some(1));
+        assert_eq!(
+            config.get("test.jobs").unwrap().source,
+            Source::Profile {
+                name: "ci".into(),
+                path: "user.toml".into()
+            }
+        );
+
+        assert!(Config::from_layers(layers(), Some("nope")).is_err());
+    }
+
+    #[test]
+    fn nix_args_are_layered() {
+        let config = Config::from_layers(layers(), None).unwrap();
+        assert_eq!(
+            config.nix_args("test", vec!["-L".into()]),
+            ["--option", "sandbox", "true", "--keep-going", "-L"]
+        );
+        assert_eq!(
+            config.nix_args("build", vec![]),
+            ["--option", "sandbox", "true"]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_wrong_types_are_rejected() {
+        let err = |toml: &str| {
+            Config::from_layers(vec![("one.toml".into(), table(toml))], None)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(err("[test]\njbos = 2"), "one.toml: unknown key `test.jbos`");
+        assert_eq!(err("[test]\njobs = \"2\""), "one.toml: `test.jobs`");
+        assert_eq!(
+            err("[profiles.ci.chek]\nfast = true"),
+            "profile \"ci\" (one.toml): unknown key `chek.fast`"
+        );
+    }
+
+    #[test]
+    fn show_lists_values_with_sources() {
+        let config = Config::from_layers(layers(), Some("ci")).unwrap();
+        let shown = config.show();
+        assert!(
+            shown.contains("check.fast = true") && shown.contains("# profile \"ci\" (repo.toml)"),
+            "{shown}"
+        );
+        assert!(shown.contains("# default"), "{shown}");
+    }
+}
@@ -0,0 +1,599 @@
+//! `one doctor`: check the local setup for the problems new contributors usually hit and
+//! print how to fix them.
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use crate::{
+    flake_lock::{self, FlakeLock},
+    runner::{Cmd, Output, Runner},
+    store,
+};
+
+/// Oldest nix with `nix config show`
+pub const MIN_NIX_VERSION: (u32, u32) = (2, 19);
+
+/// Environment variable holding the build signing key (as in `scripts/sign-build.sh`)
+pub const SIGNING_KEY_ENV: &str = "BUILD_SIGNER_KEY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Ok => "ok",
+            Level::Warn => "warn",
+            Level::Fail => "FAIL",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub check: &'static str,
+    pub level: Level,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    pub(crate) fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            level: Level::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    pub(crate) fn warn(
+        check: &'static str,
+        message: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            check,
+            level: Level::Warn,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    pub(crate) fn fail(
+        check: &'static str,
+        message: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            check,
+            level: Level::Fail,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run every check; `nixpkgs_from` is the `DIR#INPUT` that `one test` pins nixpkgs with
+pub fn run(runner: &dyn Runner, root: &Path, nixpkgs_from: &str) -> Result<Vec<Finding>> {
+    let version = Cmd::new("nix", vec![OsString::from("--version")], root);
+    let mut findings = match runner.capture(&version, Output::Inherit) {
+        Ok(Some(stdout)) => {
+            let mut findings = vec![check_nix_version(&String::from_utf8_lossy(&stdout))];
+            findings.extend(read_nix_config(runner, root));
+            findings
+        }
+        Ok(None) => read_nix_config(runner, root),
+        Err(_) => vec![Finding::fail(
+            "nix",
+            "nix is not installed (or not on PATH)",
+            "install nix from https://nixos.org/download",
+        )],
+    };
+
+    findings.extend(check_tools(env::var_os("PATH").unwrap_or_default()));
+    findings.extend(check_inputs(root, nixpkgs_from));
+    findings.push(check_crypto_lock(root));
+    findings.extend(check_signing_key(
+        root,
+        env::var_os(SIGNING_KEY_ENV).map(PathBuf::from).as_deref(),
+    ));
+    Ok(findings)
+}
+
+/// `nix config show`, checked with [`check_nix_config`]
+fn read_nix_config(runner: &dyn Runner, root: &Path) -> Vec<Finding> {
+    let show = Cmd::new(
+        "nix",
+        ["config", "show", "--json"].map(OsString::from).to_vec(),
+        root,
+    );
+    match runner.capture(&show, Output::Inherit) {
+        Ok(Some(stdout)) => match serde_json::from_slice(&stdout) {
+            Ok(config) => check_nix_config(&config, &current_user()),
+            Err(err) => vec![Finding::warn(
+                "nix config",
+                format!("failed to parse `nix config show --json`: {err}"),
+                format!(
+                    "upgrade nix to {}.{} or later",
+                    MIN_NIX_VERSION.0, MIN_NIX_VERSION.1
+                ),
+            )],
+        },
+        Ok(None) => vec![],
+        // Usually because the new CLI itself is disabled
+        Err(err) => vec![Finding::fail(
+            "experimental features",
+            format!("`nix config show` failed, is nix-command enabled? ({err:#})"),
+            "add `experimental-features = nix-command flakes` to ~/.config/nix/nix.conf",
+        )],
+    }
+}
+
+/// Print the findings and fail if any check failed
+pub fn report(findings: &[Finding]) -> Result<()> {
+    let width = findings.iter().map(|f| f.check.len()).max().unwrap_or(0);
+    for finding in findings {
+        println!(
+            "{:4}  {:width$}  {}",
+            finding.level.to_string(),
+            finding.check,
+            finding.message
+        );
+        if let Some(fix) = &finding.fix {
+            println!("{:4}  {:width$}  fix: {fix}", "", "");
+        }
+    }
+
+    let count = |level| findings.iter().filter(|f| f.level == level).count();
+    println!();
+    match (count(Level::Fail), count(Level::Warn)) {
+        (0, 0) => println!("everything looks good"),
+        (0, warnings) => println!("{warnings} warning(s)"),
+        (failed, warnings) => bail!("{failed} problem(s) and {warnings} warning(s) found"),
+    }
+    Ok(())
+}
+
+/// `nix (Nix) 2.24.9` -> `(2, 24)`
+pub fn parse_nix_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().last()?;
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+fn check_nix_version(output: &str) -> Finding {
+    let output = output.trim();
+    match parse_nix_version(output) {
+        Some(version) if version >= MIN_NIX_VERSION => Finding::ok("nix version", output),
+        Some(_) => Finding::warn(
+            "nix version",
+            format!(
+                "{output} is older than {}.{}",
+                MIN_NIX_VERSION.0, MIN_NIX_VERSION.1
+            ),
+            "upgrade nix (e.g. `nix upgrade-nix`)",
+        ),
+        None => Finding::warn(
+            "nix version",
+            format!("unrecognized `nix --version` output: {output}"),
+            "make sure `nix` on PATH is the real nix",
+        ),
+    }
+}
+
+fn setting<'a>(config: &'a Value, name: &str) -> Option<&'a Value> {
+    config.get(name).and_then(|s| s.get("value"))
+}
+
+fn strings(value: Option<&Value>) -> Vec<&str> {
+    match value {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        // Older nix versions render lists as space-separated strings
+        Some(Value::String(s)) => s.split_whitespace().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Check `nix config show --json` output for `user`
+pub fn check_nix_config(config: &Value, user: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let features = strings(setting(config, "experimental-features"));
+    let missing: Vec<_> = ["nix-command", "flakes"]
+        .into_iter()
+        .filter(|f| !features.contains(f))
+        .collect();
+    findings.push(if missing.is_empty() {
+        Finding::ok(
+            "experimental features",
+            "nix-command and flakes are enabled",
+        )
+    } else {
+        Finding::fail(
+            "experimental features",
+            format!("{} not enabled", missing.join(" and ")),
+            "add `experimental-features = nix-command flakes` to ~/.config/nix/nix.conf",
+        )
+    });
+
+    let accept = setting(config, "accept-flake-config").and_then(Value::as_bool);
+    let trusted = strings(setting(config, "trusted-users"));
+    let groups: Vec<_> = trusted.iter().filter(|u| u.starts_with('@')).collect();
+    findings.push(if trusted.contains(&user) || trusted.contains(&"*") {
+        Finding::ok("flake config", format!("{user} is a trusted user"))
+    } else if groups.is_empty() {
+        Finding::warn(
+            "flake config",
+            format!(
+                "{user} is not a trusted user, so `--accept-flake-config` cannot enable the \
+                 flake's substituters{}",
+                if accept == Some(true) {
+                    " (even with accept-flake-config = true)"
+                } else {
+                    ""
+                }
+            ),
+            format!(
+                "add `trusted-users = root {user}` to /etc/nix/nix.conf and restart nix-daemon"
+            ),
+        )
+    } else {
+        let groups: Vec<_> = groups.iter().map(|g| g.to_string()).collect();
+        Finding::warn(
+            "flake config",
+            format!(
+                "{user} is trusted only if in {}; otherwise the flake's substituters are ignored",
+                groups.join(", ")
+            ),
+            format!(
+                "add `trusted-users = root {user}` to /etc/nix/nix.conf and restart nix-daemon"
+            ),
+        )
+    });
+
+    findings
+}
+
+/// Look up an executable on `path` (a `PATH`-style list)
+pub fn which(name: &str, path: &OsString) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn check_tools(path: OsString) -> Vec<Finding> {
+    let tools: [(&'static str, bool, &str); 4] = [
+        ("git", true, "install git"),
+        (
+            "nix-eval-jobs",
+            false,
+            "needed by `one check --fast` and `one test`: `nix profile install nixpkgs#nix-eval-jobs`",
+        ),
+        (
+            "build-signer",
+            false,
+            "needed to sign builds: `cargo install --path src/crypto/build-signer`",
+        ),
+        (
+            "build-verifier",
+            false,
+            "needed to verify proofs: `cargo install --path src/crypto/build-verifier`",
+        ),
+    ];
+    tools
+        .into_iter()
+        .map(|(tool, required, fix)| match which(tool, &path) {
+            Some(found) => Finding::ok(tool, found.display().to_string()),
+            None if required => Finding::fail(tool, "not found on PATH", fix),
+            None => Finding::warn(tool, "not found on PATH", fix),
+        })
+        .collect()
+}
+
+/// Every git-like input of the reference lock has a revision, and `nixpkgs_from` resolves
+fn check_inputs(root: &Path, nixpkgs_from: &str) -> Vec<Finding> {
+    let (dir, _) = nixpkgs_from.split_once('#').unwrap_or((nixpkgs_from, ""));
+    let lock_path = root.join(dir).join("flake.lock");
+    let lock = match FlakeLock::load(&lock_path) {
+        Ok(lock) => lock,
+        Err(err) => {
+            return vec![Finding::fail(
+                "flake inputs",
+                format!("{err:#}"),
+                format!("run `nix flake lock {dir}` and commit the lock file"),
+            )]
+        }
+    };
+
+    let unpinned: Vec<_> = lock
+        .nodes
+        .iter()
+        .filter(|(_, node)| {
+            node.locked.as_ref().is_some_and(|locked| {
+                matches!(locked.kind.as_str(), "github" | "gitlab" | "git") && locked.rev.is_none()
+            })
+        })
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    let mut findings = vec![if unpinned.is_empty() {
+        Finding::ok(
+            "flake inputs",
+            format!("{} pins every input", lock_path.display()),
+        )
+    } else {
+        Finding::warn(
+            "flake inputs",
+            format!("inputs without a locked revision: {}", unpinned.join(", ")),
+            format!("run `nix flake lock {dir}`"),
+        )
+    }];
+
+    findings.push(match flake_lock::resolve_pinned_input(root, nixpkgs_from) {
+        Ok(pinned) => Finding::ok("nixpkgs pin", pinned),
+        Err(err) => Finding::fail(
+            "nixpkgs pin",
+            format!("{err:#}"),
+            "set `test.nixpkgs-from` in one.toml (or pass `--nixpkgs-from`) to a locked input",
+        ),
+    });
+    findings
+}
+
+fn check_crypto_lock(root: &Path) -> Finding {
+    let lock = root.join("src/crypto/Cargo.lock");
+    if lock.exists() {
+        Finding::ok("crypto Cargo.lock", lock.display().to_string())
+    } else {
+        Finding::fail(
+            "crypto Cargo.lock",
+            "missing; the crypto packages are built with `cargoLock.lockFile = ../Cargo.lock`",
+            "cargo generate-lockfile --manifest-path src/crypto/Cargo.toml",
+        )
+    }
+}
+
+/// The key in `BUILD_SIGNER_KEY` is a readable 32-byte key, private to its owner and trusted
+fn check_signing_key(root: &Path, key: Option<&Path>) -> Vec<Finding> {
+    let Some(path) = key else {
+        return vec![Finding::warn(
+            "signing key",
+            format!("{SIGNING_KEY_ENV} is not set, so builds can't be signed"),
+            format!("./scripts/generate-keypair.sh <name> and export {SIGNING_KEY_ENV}=<name>.key"),
+        )];
+    };
+
+    let key = match store::read_signing_key(path) {
+        Ok(key) => key,
+        Err(err) => {
+            return vec![Finding::fail(
+                "signing key",
+                format!("{err:#}"),
+                format!("point {SIGNING_KEY_ENV} at a key from ./scripts/generate-keypair.sh"),
+            )]
+        }
+    };
+
+    let mut findings = Vec::new();
+    if let Some(mode) = world_accessible(path) {
+        findings.push(Finding::warn(
+            "signing key",
+            format!("{} is accessible by others (mode {mode:o})", path.display()),
+            format!("chmod 600 {}", path.display()),
+        ));
+    }
+
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    let trusted = root.join("prover_keys/trusted.txt");
+    match fs::read_to_string(&trusted) {
+        Ok(contents)
+            if !contents
+                .lines()
+                .any(|line| line.split('#').next().unwrap_or("").trim() == public_key) =>
+        {
+            findings.push(Finding::warn(
+                "signing key",
+                format!("public key {public_key} is not in {}", trusted.display()),
+                format!("echo {public_key} >> prover_keys/trusted.txt"),
+            ));
+        }
+        _ => {}
+    }
+
+    if findings.is_empty() {
+        findings.push(Finding::ok(
+            "signing key",
+            format!("{} (public key {public_key})", path.display()),
+        ));
+    }
+    findings
+}
+
+#[cfg(unix)]
+fn world_accessible(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+fn world_accessible(_path: &Path) -> Option<u32> {
+    None
+}
+
+fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_else(|_| "<user>".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn levels(findings: &[Finding]) -> Vec<(&str, Level)> {
+        findings.iter().map(|f| (f.check, f.level)).collect()
+    }
+
+    #[test]
+    fn nix_versions() {
+        assert_eq!(parse_nix_version("nix (Nix) 2.24.9"), Some((2, 24)));
+        assert_eq!(
+            parse_nix_version("nix (Nix) 2.25.0pre20241101_dirty"),
+            Some((2, 25))
+        );
+        assert_eq!(
+            parse_nix_version("nix (Lix, like Nix) 2.91.1"),
+            Some((2, 91))
+        );
+        assert_eq!(parse_nix_version("garbage"), None);
+        assert_eq!(check_nix_version("nix (Nix) 2.3.16").level, Level::Warn);
+    }
+
+    #[test]
+    fn nix_config() {
+        let config = json!({
+            "experimental-features": {"value": ["flakes", "nix-command"]},
+            "accept-flake-config": {"value": false},
+            "trusted-users": {"value": ["root", "dev"]},
+        });
+        assert_eq!(
+            levels(&check_nix_config(&config, "dev")),
+            [
+                ("experimental features", Level::Ok),
+                ("flake config", Level::Ok)
+            ]
+        );
+
+        let config = json!({
+            "experimental-features": {"value": "nix-command"},
+            "trusted-users": {"value": ["root"]},
+        });
+        let findings = check_nix_config(&config, "dev");
+        assert_eq!(
+            levels(&findings),
+            [
+                ("experimental features", Level::Fail),
+                ("flake config", Level::Warn)
+            ]
+        );
+        assert_eq!(findings[0].message, "flakes not enabled");
+        assert_eq!(
+            findings[1].fix.as_deref(),
+            Some("add `trusted-users = root dev` to /etc/nix/nix.conf and restart nix-daemon")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tools_are_found_on_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for (tool, mode) in [("git", 0o755), ("nix-eval-jobs", 0o644)] {
+            fs::write(dir.join(tool), "").unwrap();
+            fs::set_permissions(dir.join(tool), fs::Permissions::from_mode(mode)).unwrap();
+        }
+        assert_eq!(
+            levels(&check_tools(dir.as_os_str().to_owned())),
+            [
+                ("git", Level::Ok),
+                ("nix-eval-jobs", Level::Warn),
+                ("build-signer", Level::Warn),
+                ("build-verifier", Level::Warn),
+            ]
+        );
+    }
+
+    #[test]
+    fn inputs_of_the_repository_are_pinned() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        assert_eq!(
+            levels(&check_inputs(&root, "src/lang/rust/test#nixpkgs")),
+            [("flake inputs", Level::Ok), ("nixpkgs pin", Level::Ok)]
+        );
+        assert_eq!(
+            levels(&check_inputs(&root, "src/lang/rust/test#nope")),
+            [("flake inputs", Level::Ok), ("nixpkgs pin", Level::Fail)]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signing_key_permissions_and_trust() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let key = root.join("dev.key");
+        fs::write(&key, [7u8; 32]).unwrap();
+        fs::set_permissions(&key, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::create_dir_all(root.join("prover_keys")).unwrap();
+        fs::write(root.join("prover_keys/trusted.txt"), "# nobody yet\n").unwrap();
+
+        let findings = check_signing_key(root, Some(&key));
+        assert_eq!(
+            levels(&findings),
+            [("signing key", Level::Warn), ("signing key", Level::Warn)]
+        );
+        assert_eq!(
+            findings[0].fix.as_deref(),
+            Some(format!("chmod 600 {}", key.display()).as_str())
+        );
+
+        fs::set_permissions(&key, fs::Permissions::from_mode(0o600)).unwrap();
+        let public_key = hex::encode(
+            store::read_signing_key(&key)
+                .unwrap()
+                .verifying_key()
+                .to_bytes(),
+        );
+        fs::write(
+            root.join("prover_keys/trusted.txt"),
+            format!("{public_key}  # dev\n"),
+        )
+        .unwrap();
+        assert_eq!(
+            levels(&check_signing_key(root, Some(&key))),
+            [("signing key", Level::Ok)]
+        );
+
+        fs::write(&key, "short").unwrap();
+        assert_eq!(
+            levels(&check_signing_key(root, Some(&key))),
+            [("signing key", Level::Fail)]
+        );
+        assert_eq!(
+            levels(&check_signing_key(root, None)),
+            [("signing key", Level::Warn)]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Native port of `ci/check-example.sh`: check an example exactly the way CI does.
+//!
+//! The example gets the test suite's `flake.lock` (staged with `git add -N --force`, since
+//! nix-eval-jobs has no `--reference-lock-file`), nixpkgs pinned to the revision in that lock,
+//! a fast build of uncached checks and finally an eval-only `nix flake check`.
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+
+use crate::{
+    fast_check,
+    runner::{self, Cmd, Output, Runner},
+};
+
+/// Lock file shared by every example check
+pub const REFERENCE_LOCK_DIR: &str = "src/lang/rust/test";
+
+/// Serializes git index updates when examples are checked in parallel
+static GIT_INDEX: Mutex<()> = Mutex::new(());
+
+/// The reference `flake.lock` copied into an example and staged as intent-to-add
+///
+/// Dropping the guard (also after Ctrl-C, see [`runner::trap_interrupts`]) removes it again.
+pub struct StagedLock<'a> {
+    runner: &'a dyn Runner,
+    output: Output<'a>,
+    root: PathBuf,
+    path: PathBuf,
+}
+
+impl<'a> StagedLock<'a> {
+    pub fn stage(
+        runner: &'a dyn Runner,
+        root: &Path,
+        example: &Path,
+        output: Output<'a>,
+    ) -> Result<Self> {
+        let source = root.join(REFERENCE_LOCK_DIR).join("flake.lock");
+        let path = example.join("flake.lock");
+        let cp = Cmd::new(
+            "cp",
+            vec![source.into_os_string(), path.clone().into_os_string()],
+            root,
+        );
+        runner.run(&cp, output)?;
+
+        let staged = Self {
+            runner,
+            output,
+            root: root.to_path_buf(),
+            path,
+        };
+        // NB: the lock is normally gitignored, but nix-eval-jobs makes up its own unless the
+        // file is known to git
+        staged.git(&["add", "-N", "--force"])?;
+        Ok(staged)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<()> {
+        let _index = GIT_INDEX.lock().unwrap_or_else(|err| err.into_inner());
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.push(self.path.clone().into_os_string());
+        self.runner
+            .run(&Cmd::new("git", args, &self.root), self.output)
+    }
+}
+
+impl Drop for StagedLock<'_> {
+    fn drop(&mut self) {
+        if self.git(&["rm", "-f", "--quiet"]).is_err() {
+            // Not staged (or git is unhappy); at least don't leave the copy behind
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Arguments shared by every step: the example flake and its input overrides
+pub fn common_args(root: &Path, example: &Path, nixpkgs: &str) -> Vec<OsString> {
+    vec![
+        example.as_os_str().to_owned(),
+        OsString::from("--override-input"),
+        OsString::from("one-for-all"),
+        OsString::from(format!("path:{}", root.display())),
+        OsString::from("--override-input"),
+        OsString::from("nixpkgs"),
+        OsString::from(nixpkgs),
+    ]
+}
+
+/// Run the CI steps for `example` with nixpkgs pinned to `nixpkgs`
+pub fn check(
+    runner: &dyn Runner,
+    root: &Path,
+    example: &Path,
+    nixpkgs: &str,
+    nix_args: &[String],
+    output: Output,
+) -> Result<()> {
+    runner::trap_interrupts();
+    let common = common_args(root, example, nixpkgs);
+    let nix_args: Vec<OsString> = nix_args.iter().map(OsString::from).collect();
+
+    let _lock = StagedLock::stage(runner, root, example, output)?;
+    runner::check_interrupted()?;
+
+    let report = fast_check::run(
+        runner,
+        &common[0],
+        &common[1..],
+        &nix_args,
+        fast_check::DEFAULT_BATCH_SIZE,
+        &root.join(fast_check::GC_ROOTS_DIR),
+        root,
+        output,
+    )?;
+    report.print(output)?;
+    report.into_result()?;
+
+    // Evaluate the rest of the flake, but don't build anything
+    let mut args = vec![
+        OsString::from("flake"),
+        OsString::from("check"),
+        OsString::from("--no-build"),
+        OsString::from("--no-write-lock-file"),
+        OsString::from("--accept-flake-config"),
+        OsString::from("--print-build-logs"),
+    ];
+    args.extend(common);
+    args.extend(nix_args);
+    runner.run(&Cmd::new("nix", args, root), output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::runner::System;
+
+    /// A throwaway git repository with the reference lock and one example
+    fn repo() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(REFERENCE_LOCK_DIR)).unwrap();
+        fs::create_dir_all(root.join("example")).unwrap();
+        fs::write(root.join(REFERENCE_LOCK_DIR).join("flake.lock"), "{}").unwrap();
+        fs::write(root.join(".gitignore"), "flake.lock\n").unwrap();
+
+        let status = Command::new("git")
+            .args(["init", "--quiet"])
+            .current_dir(root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        dir
+    }
+
+    fn staged_files(root: &Path) -> String {
+        let output = Command::new("git")
+            .args(["ls-files"])
+            .current_dir(root)
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn lock_is_staged_and_removed_on_drop() {
+        let dir = repo();
+        let root = dir.path();
+        let example = root.join("example");
+
+        let lock = StagedLock::stage(&System, root, &example, Output::Inherit).unwrap();
+        assert!(example.join("flake.lock").exists());
+        assert_eq!(staged_files(root), "example/flake.lock\n");
+
+        drop(lock);
+        assert!(!example.join("flake.lock").exists());
+        assert_eq!(staged_files(root), "");
+    }
+}
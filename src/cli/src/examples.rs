@@ -0,0 +1,317 @@
+//! `one test --all`: discover the example flakes and check them in parallel.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::runner::{self, Output};
+
+/// Where per-example logs go (relative to the repository root)
+pub const DEFAULT_LOG_DIR: &str = ".one/logs/test";
+
+/// Examples checked at once by `one test --all`
+pub const DEFAULT_JOBS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Example {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Examples are the direct subdirectories of `dir` that contain a `flake.nix`, sorted by name
+pub fn discover(dir: &Path) -> Result<Vec<Example>> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read example directory {}", dir.display()))?;
+
+    let mut examples = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let path = entry.path();
+        if !path.join("flake.nix").is_file() {
+            continue;
+        }
+        examples.push(Example {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path,
+        });
+    }
+    examples.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(examples)
+}
+
+/// Keep examples matching any `only` glob (all if empty) and no `exclude` glob
+pub fn select(examples: Vec<Example>, only: &[String], exclude: &[String]) -> Vec<Example> {
+    examples
+        .into_iter()
+        .filter(|example| only.is_empty() || only.iter().any(|g| glob_match(g, &example.name)))
+        .filter(|example| !exclude.iter().any(|g| glob_match(g, &example.name)))
+        .collect()
+}
+
+/// Shell-style glob matching supporting `*` and `?`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name index it was tried at
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star + 1;
+                    n = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub passed: bool,
+    pub duration: Duration,
+    pub log: PathBuf,
+}
+
+/// Run `check` for every example with at most `jobs` running at once
+///
+/// Output of each check is captured to `<log_dir>/<name>.log`. Outcomes are returned in the
+/// order of `examples`; after Ctrl-C no further examples are started.
+pub fn run_all(
+    examples: &[Example],
+    jobs: usize,
+    log_dir: &Path,
+    check: impl Fn(&Example, Output) -> Result<()> + Sync,
+) -> Result<Vec<Outcome>> {
+    fs::create_dir_all(log_dir)
+        .with_context(|| format!("failed to create log directory {}", log_dir.display()))?;
+
+    let queue = Mutex::new(examples.iter().enumerate().collect::<VecDeque<_>>());
+    let outcomes = Mutex::new(Vec::with_capacity(examples.len()));
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, examples.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    loop {
+                        runner::check_interrupted()?;
+                        let Some((index, example)) = queue.lock().unwrap().pop_front() else {
+                            return Ok(());
+                        };
+                        let log = log_dir.join(format!("{}.log", example.name));
+                        println!("> {} (log: {})", example.name, log.display());
+
+                        let started = Instant::now();
+                        let passed = run_logged(example, &log, &check)?;
+                        let outcome = Outcome {
+                            name: example.name.clone(),
+                            passed,
+                            duration: started.elapsed(),
+                            log,
+                        };
+                        println!(
+                            "  {} {} ({})",
+                            if passed { "pass" } else { "FAIL" },
+                            outcome.name,
+                            format_duration(outcome.duration)
+                        );
+                        outcomes.lock().unwrap().push((index, outcome));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("test worker panicked"))
+    })?;
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(index, _)| *index);
+    Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+}
+
+fn run_logged(
+    example: &Example,
+    log: &Path,
+    check: impl Fn(&Example, Output) -> Result<()>,
+) -> Result<bool> {
+    let mut file =
+        File::create(log).with_context(|| format!("failed to create log {}", log.display()))?;
+
+    match check(example, Output::Log(&file)) {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            writeln!(file, "error: {err:#}")
+                .with_context(|| format!("failed to write log {}", log.display()))?;
+            Ok(false)
+        }
+    }
+}
+
+/// Print the pass/fail/duration matrix and fail if any example failed
+pub fn report(outcomes: &[Outcome]) -> Result<()> {
+    let width = outcomes
+        .iter()
+        .map(|o| o.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("EXAMPLE".len());
+
+    println!();
+    println!(
+        "{:width$}  {:6}  {:>8}  LOG",
+        "EXAMPLE", "RESULT", "DURATION"
+    );
+    for outcome in outcomes {
+        println!(
+            "{:width$}  {:6}  {:>8}  {}",
+            outcome.name,
+            if outcome.passed { "pass" } else { "FAIL" },
+            format_duration(outcome.duration),
+            outcome.log.display()
+        );
+    }
+
+    let failed = outcomes.iter().filter(|o| !o.passed).count();
+    println!();
+    if failed > 0 {
+        bail!("{} of {} examples failed", failed, outcomes.len());
+    }
+    println!("all {} examples passed", outcomes.len());
+    Ok(())
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Cmd, Runner, System};
+
+    fn example(name: &str) -> Example {
+        Example {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+        }
+    }
+
+    fn names(examples: &[Example]) -> Vec<&str> {
+        examples.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("quick-start*", "quick-start"));
+        assert!(glob_match("quick-start*", "quick-start-workspace"));
+        assert!(glob_match("cross-*", "cross-musl"));
+        assert!(glob_match("*-workspace", "trunk-workspace"));
+        assert!(glob_match("*s*q*", "sqlx"));
+        assert!(glob_match("tr?nk", "trunk"));
+        assert!(!glob_match("tr?nk", "trunk-workspace"));
+        assert!(!glob_match("cross-*", "custom-toolchain"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn select_applies_only_then_exclude() {
+        let all = vec![
+            example("cross-musl"),
+            example("cross-windows"),
+            example("quick-start"),
+            example("trunk"),
+        ];
+
+        assert_eq!(names(&select(all.clone(), &[], &[])).len(), 4);
+        assert_eq!(
+            names(&select(all.clone(), &["cross-*".into()], &[])),
+            ["cross-musl", "cross-windows"]
+        );
+        assert_eq!(
+            names(&select(
+                all.clone(),
+                &["cross-*".into(), "trunk".into()],
+                &["*windows".into()]
+            )),
+            ["cross-musl", "trunk"]
+        );
+        assert_eq!(
+            names(&select(all, &[], &["cross-*".into()])),
+            ["quick-start", "trunk"]
+        );
+    }
+
+    #[test]
+    fn discover_requires_flake_nix() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for name in ["b", "a", "no-flake"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("a/flake.nix"), "{}").unwrap();
+        fs::write(dir.join("b/flake.nix"), "{}").unwrap();
+        fs::write(dir.join("README.md"), "").unwrap();
+
+        assert_eq!(names(&discover(dir).unwrap()), ["a", "b"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_all_captures_logs_and_keeps_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("logs");
+        let examples = vec![example("ok-1"), example("bad"), example("ok-2")];
+
+        let outcomes = run_all(&examples, 2, &dir, |e, output| {
+            let script = format!("echo {}; test {} != bad", e.name, e.name);
+            let cmd = Cmd::new("sh", vec!["-c".into(), script.into()], Path::new("."));
+            System.run(&cmd, output)
+        })
+        .unwrap();
+
+        assert_eq!(
+            outcomes
+                .iter()
+                .map(|o| (o.name.as_str(), o.passed))
+                .collect::<Vec<_>>(),
+            [("ok-1", true), ("bad", false), ("ok-2", true)]
+        );
+        let log = fs::read_to_string(dir.join("bad.log")).unwrap();
+        assert!(log.contains("\nbad\n"), "{log}");
+        assert!(log.contains("error: sh -c"), "{log}");
+        assert!(report(&outcomes).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! `one check --fast`, a native port of `ci/fast-flake-check.sh`: evaluate a flake's checks
+//! with `nix-eval-jobs` and build only the ones that aren't already cached.
+//!
+//! nix-eval-jobs output is streamed and uncached derivations are built in batches as soon as
+//! a batch is full, so the first builds start before evaluation has finished. Each batch is
+//! built synchronously while reading the stream: nix-eval-jobs only keeps evaluating until
+//! its output pipe fills up, and the next line is read once the build returns.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::runner::{self, Cmd, Output, Runner};
+
+/// Where nix-eval-jobs keeps its GC roots (relative to the repository root)
+pub const GC_ROOTS_DIR: &str = ".one/gcroots";
+
+/// Uncached derivations handed to a single `nix build`
+pub const DEFAULT_BATCH_SIZE: usize = 16;
+
+/// One line of `nix-eval-jobs --check-cache-status` output
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvalJob {
+    pub attr: String,
+    pub drv_path: Option<String>,
+    #[serde(default)]
+    pub is_cached: bool,
+    pub error: Option<String>,
+}
+
+impl EvalJob {
+    pub fn parse(line: &str) -> Result<Self> {
+        serde_json::from_str(line)
+            .with_context(|| format!("failed to parse nix-eval-jobs output: {line}"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Cached,
+    Built,
+    Failed,
+    EvalFailed(String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Cached => f.write_str("cached"),
+            Status::Built => f.write_str("built"),
+            Status::Failed => f.write_str("FAILED"),
+            Status::EvalFailed(_) => f.write_str("EVAL FAILED"),
+        }
+    }
+}
+
+/// Outcome of every evaluated check, in evaluation order
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub checks: Vec<(String, Status)>,
+}
+
+impl Report {
+    pub fn count(&self, matches: impl Fn(&Status) -> bool) -> usize {
+        self.checks.iter().filter(|(_, s)| matches(s)).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|s| matches!(s, Status::Failed | Status::EvalFailed(_)))
+    }
+
+    pub fn print(&self, output: Output) -> Result<()> {
+        if self.checks.is_empty() {
+            return Ok(());
+        }
+        let width = self.checks.iter().map(|(a, _)| a.len()).max().unwrap_or(0);
+        output.println("")?;
+        for (attr, status) in &self.checks {
+            match status {
+                Status::EvalFailed(error) => {
+                    output.println(&format!("{attr:width$}  {status}: {}", error.trim()))?
+                }
+                _ => output.println(&format!("{attr:width$}  {status}"))?,
+            }
+        }
+        output.println(&format!(
+            "{} cached, {} built, {} failed",
+            self.count(|s| *s == Status::Cached),
+            self.count(|s| *s == Status::Built),
+            self.failed()
+        ))
+    }
+
+    pub fn into_result(self) -> Result<()> {
+        match self.failed() {
+            0 => Ok(()),
+            failed => bail!("{} of {} checks failed", failed, self.checks.len()),
+        }
+    }
+}
+
+/// Classifies evaluated jobs and builds the uncached ones in batches of `batch_size`
+///
+/// `build` is given the installables of a batch (`<drv>^*`) and reports whether the build
+/// succeeded. A failed batch is retried one derivation at a time to find the culprits; the
+/// derivations that did build are valid by then, so the retry is cheap.
+pub struct Builder<F> {
+    batch_size: usize,
+    build: F,
+    pending: Vec<(usize, String)>,
+    report: Report,
+}
+
+impl<F: FnMut(&[OsString]) -> Result<bool>> Builder<F> {
+    pub fn new(batch_size: usize, build: F) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            build,
+            pending: Vec::new(),
+            report: Report::default(),
+        }
+    }
+
+    pub fn push(&mut self, job: EvalJob) -> Result<()> {
+        let status = if let Some(error) = job.error {
+            Status::EvalFailed(error)
+        } else if job.is_cached {
+            Status::Cached
+        } else {
+            let Some(drv_path) = job.drv_path else {
+                bail!("nix-eval-jobs returned no derivation for {}", job.attr);
+            };
+            self.pending.push((self.report.checks.len(), drv_path));
+            // Updated once the batch is built
+            Status::Failed
+        };
+        self.report.checks.push((job.attr, status));
+
+        if self.pending.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let batch = std::mem::take(&mut self.pending);
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let installables: Vec<_> = batch.iter().map(|(_, drv)| installable(drv)).collect();
+        let built = (self.build)(&installables)?;
+        for (index, drv) in &batch {
+            let ok = built || (batch.len() > 1 && (self.build)(&[installable(drv)])?);
+            self.report.checks[*index].1 = if ok { Status::Built } else { Status::Failed };
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Report> {
+        self.flush()?;
+        Ok(self.report)
+    }
+}
+
+fn installable(drv_path: &str) -> OsString {
+    OsString::from(format!("{drv_path}^*"))
+}
+
+/// `builtins.currentSystem`, or a placeholder when nothing is executed (`--dry-run`)
+pub fn current_system(runner: &dyn Runner, cwd: &Path, output: Output) -> Result<String> {
+    let args = [
+        "eval",
+        "--raw",
+        "--impure",
+        "--expr",
+        "builtins.currentSystem",
+    ]
+    .map(OsString::from)
+    .to_vec();
+    match runner.capture(&Cmd::new("nix", args, cwd), output)? {
+        Some(stdout) => {
+            String::from_utf8(stdout).context("`builtins.currentSystem` is not valid UTF-8")
+        }
+        None => Ok("<system>".to_string()),
+    }
+}
+
+/// `nix-eval-jobs` evaluating `flake`'s `checks.<system>` and their cache status
+pub fn eval_jobs_cmd(
+    flake: &OsStr,
+    system: &str,
+    eval_args: &[OsString],
+    gc_roots_dir: &Path,
+    cwd: &Path,
+) -> Cmd {
+    let mut installable = flake.to_owned();
+    installable.push(format!("#checks.{system}"));
+
+    let mut args = vec![
+        OsString::from("--gc-roots-dir"),
+        gc_roots_dir.as_os_str().to_owned(),
+        OsString::from("--check-cache-status"),
+        OsString::from("--flake"),
+        installable,
+    ];
+    args.extend(eval_args.iter().cloned());
+    Cmd::new("nix-eval-jobs", args, cwd)
+}
+
+/// Build every uncached `checks.<system>` derivation of `flake`
+///
+/// `eval_args` go to nix-eval-jobs (e.g. `--override-input`), `build_args` to `nix build`.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    runner: &dyn Runner,
+    flake: &OsStr,
+    eval_args: &[OsString],
+    build_args: &[OsString],
+    batch_size: usize,
+    gc_roots_dir: &Path,
+    cwd: &Path,
+    output: Output,
+) -> Result<Report> {
+    let system = current_system(runner, cwd, output)?;
+    let eval_jobs = eval_jobs_cmd(flake, &system, eval_args, gc_roots_dir, cwd);
+
+    let mut builder = Builder::new(batch_size, |installables: &[OsString]| {
+        let mut args = vec![
+            OsString::from("build"),
+            OsString::from("--no-link"),
+            OsString::from("--print-build-logs"),
+        ];
+        args.extend(installables.iter().cloned());
+        args.extend(build_args.iter().cloned());
+
+        match runner.run(&Cmd::new("nix", args, cwd), output) {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                runner::check_interrupted()?;
+                output.println(&format!("build failed: {err:#}"))?;
+                Ok(false)
+            }
+        }
+    });
+
+    runner.stream(&eval_jobs, output, &mut |line| {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        builder.push(EvalJob::parse(line)?)
+    })?;
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Recorded `nix-eval-jobs --check-cache-status` output for the repository's checks
+    const RECORDED: &str = include_str!("../testdata/nix-eval-jobs.jsonl");
+
+    fn jobs() -> Vec<EvalJob> {
+        RECORDED
+            .lines()
+            .map(|l| EvalJob::parse(l).unwrap())
+            .collect()
+    }
+
+    /// Build `jobs` with a fake `nix build` that fails for `failing` derivations
+    fn run_fake(batch_size: usize, failing: &[&str]) -> (Report, Vec<Vec<String>>) {
+        let mut builds = Vec::new();
+        let mut builder = Builder::new(batch_size, |installables: &[OsString]| {
+            let batch: Vec<String> = installables
+                .iter()
+                .map(|i| i.to_string_lossy().into_owned())
+                .collect();
+            let ok = !batch.iter().any(|i| failing.iter().any(|f| i.contains(f)));
+            builds.push(batch);
+            Ok(ok)
+        });
+        for job in jobs() {
+            builder.push(job).unwrap();
+        }
+        (builder.finish().unwrap(), builds)
+    }
+
+    fn statuses(report: &Report) -> Vec<(&str, &Status)> {
+        report.checks.iter().map(|(a, s)| (a.as_str(), s)).collect()
+    }
+
+    #[test]
+    fn parses_recorded_output() {
+        let jobs = jobs();
+        assert_eq!(jobs.len(), 5);
+        assert_eq!(jobs.iter().filter(|j| j.is_cached).count(), 2);
+        assert!(jobs.iter().any(|j| j.error.is_some()));
+    }
+
+    #[test]
+    fn only_uncached_checks_are_built_in_batches() {
+        let (report, builds) = run_fake(2, &[]);
+        assert_eq!(
+            builds,
+            [vec![
+                "/nix/store/3mq1r7h5qk3v9yd0w6a9cgkmr7m8rwl5-cargo-test.drv^*".to_string(),
+                "/nix/store/9b0lqg3pz4cd5a7w1y2x8n0m6k4j2h1g-cargo-doc.drv^*".to_string(),
+            ],]
+        );
+        assert_eq!(
+            statuses(&report),
+            [
+                ("cargoClippy", &Status::Cached),
+                ("cargoTest", &Status::Built),
+                ("cargoFmt", &Status::Cached),
+                ("cargoDoc", &Status::Built),
+                (
+                    "broken",
+                    &Status::EvalFailed("error: undefined variable 'crane'".into())
+                ),
+            ]
+        );
+        assert_eq!(report.failed(), 1);
+    }
+
+    #[test]
+    fn failed_batches_are_retried_individually() {
+        let (report, builds) = run_fake(8, &["cargo-doc"]);
+        assert_eq!(builds.len(), 3, "{builds:?}");
+        assert_eq!(report.checks[1].1, Status::Built);
+        assert_eq!(report.checks[3].1, Status::Failed);
+        assert!(report.into_result().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Minimal `flake.lock` reader, used to pin inputs the same way CI does.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct FlakeLock {
+    pub nodes: BTreeMap<String, Node>,
+    pub root: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Node {
+    #[serde(default)]
+    pub inputs: BTreeMap<String, InputRef>,
+    pub locked: Option<Locked>,
+}
+
+/// An input either names a node directly or `follows` a path of inputs from the root
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum InputRef {
+    Node(String),
+    Follows(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Locked {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+    pub rev: Option<String>,
+    pub url: Option<String>,
+    pub path: Option<String>,
+    /// Unix time of the locked commit
+    pub last_modified: Option<i64>,
+    pub nar_hash: Option<String>,
+}
+
+impl Locked {
+    /// What the input points at, without the revision: `github:NixOS/nixpkgs`,
+    /// `git+https://...`, `path:/src`
+    pub fn source(&self) -> String {
+        match (&self.owner, &self.repo, &self.url, &self.path) {
+            (Some(owner), Some(repo), _, _) => format!("{}:{owner}/{repo}", self.kind),
+            (_, _, Some(url), _) if self.kind == "git" => format!("git+{url}"),
+            (_, _, Some(url), _) => url.clone(),
+            (_, _, _, Some(path)) => format!("{}:{path}", self.kind),
+            _ => self.kind.clone(),
+        }
+    }
+}
+
+impl FlakeLock {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(serde_json::from_str(contents)?)
+    }
+
+    /// The node a top-level input resolves to
+    pub fn input_node(&self, input: &str) -> Result<&Node> {
+        self.follow(&[input.to_string()], 0)
+    }
+
+    fn follow(&self, path: &[String], depth: usize) -> Result<&Node> {
+        // `follows` chains are short in practice; this only guards against cycles
+        if depth > self.nodes.len() {
+            bail!("cycle while resolving input {:?}", path.join("/"));
+        }
+
+        let mut node = self.node(&self.root)?;
+        for input in path {
+            node = match node.inputs.get(input) {
+                Some(InputRef::Node(name)) => self.node(name)?,
+                Some(InputRef::Follows(path)) => self.follow(path, depth + 1)?,
+                None => bail!("flake.lock has no input {:?}", path.join("/")),
+            };
+        }
+        Ok(node)
+    }
+
+    pub fn node(&self, name: &str) -> Result<&Node> {
+        self.nodes
+            .get(name)
+            .ok_or_else(|| anyhow!("flake.lock references missing node {name:?}"))
+    }
+
+    /// Flake reference pinning `input` to its locked revision, e.g. `github:NixOS/nixpkgs/<rev>`
+    pub fn locked_ref(&self, input: &str) -> Result<String> {
+        let locked = self
+            .input_node(input)?
+            .locked
+            .as_ref()
+            .ok_or_else(|| anyhow!("input {input:?} is not locked"))?;
+
+        match (&locked.owner, &locked.repo, &locked.rev) {
+            (Some(owner), Some(repo), Some(rev)) => {
+                Ok(format!("{}:{}/{}/{}", locked.kind, owner, repo, rev))
+            }
+            _ => bail!(
+                "input {input:?} is locked as {:?}, expected an owner/repo/rev reference",
+                locked.kind
+            ),
+        }
+    }
+}
+
+/// Resolve a `<dir>#<input>` selector (dir relative to `root`) to the pinned flake reference
+pub fn resolve_pinned_input(root: &Path, selector: &str) -> Result<String> {
+    let (dir, input) = selector
+        .split_once('#')
+        .ok_or_else(|| anyhow!("expected `<dir>#<input>`, got {selector:?}"))?;
+
+    let lock_path = root.join(dir).join("flake.lock");
+    FlakeLock::load(&lock_path)?
+        .locked_ref(input)
+        .with_context(|| format!("failed to resolve {input:?} from {}", lock_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"{
+      "nodes": {
+        "crane": {
+          "inputs": { "nixpkgs": ["nixpkgs"] },
+          "locked": { "owner": "ipetkov", "repo": "crane", "rev": "c0ffee", "type": "github" }
+        },
+        "local": {
+          "locked": { "path": "/tmp/x", "type": "path" }
+        },
+        "nixpkgs": {
+          "locked": {
+            "lastModified": 1758029226,
+            "narHash": "sha256-TjqVmbpoCqWywY9xIZLTf6ANFvDCXdctCjoYuYPYdMI=",
+            "owner": "NixOS",
+            "repo": "nixpkgs",
+            "rev": "08b8f92ac6354983f5382124fef6006cade4a1c1",
+            "type": "github"
+          },
+          "original": { "owner": "NixOS", "ref": "nixpkgs-unstable", "repo": "nixpkgs", "type": "github" }
+        },
+        "root": {
+          "inputs": {
+            "crane": "crane",
+            "local": "local",
+            "nixpkgs": "nixpkgs",
+            "pkgs-for-crane": ["crane", "nixpkgs"]
+          }
+        }
+      },
+      "root": "root",
+      "version": 7
+    }"#;
+
+    fn lock() -> FlakeLock {
+        serde_json::from_str(LOCK).unwrap()
+    }
+
+    #[test]
+    fn locked_ref_matches_ref_from_lock() {
+        assert_eq!(
+            lock().locked_ref("nixpkgs").unwrap(),
+            "github:NixOS/nixpkgs/08b8f92ac6354983f5382124fef6006cade4a1c1"
+        );
+    }
+
+    #[test]
+    fn follows_are_resolved() {
+        assert_eq!(
+            lock().locked_ref("pkgs-for-crane").unwrap(),
+            "github:NixOS/nixpkgs/08b8f92ac6354983f5382124fef6006cade4a1c1"
+        );
+    }
+
+    #[test]
+    fn unsupported_inputs_are_errors() {
+        assert!(lock().locked_ref("missing").is_err());
+        assert!(lock().locked_ref("local").is_err());
+    }
+
+    #[test]
+    fn repository_test_lock_pins_nixpkgs() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let pinned = resolve_pinned_input(&root, "src/lang/rust/test#nixpkgs").unwrap();
+        assert!(pinned.starts_with("github:NixOS/nixpkgs/"), "{pinned}");
+    }
+}
@@ -0,0 +1,322 @@
+//! `one gc`: prune old artifacts from the local store according to retention rules.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueHint};
+
+use crate::store::{self, IndexEntry, Store};
+
+#[derive(Args)]
+pub struct GcArgs {
+    /// Artifact store directory (relative paths are resolved against the repository root)
+    #[arg(long, value_name = "DIR", default_value = store::DEFAULT_STORE_DIR, value_hint = ValueHint::DirPath)]
+    store: PathBuf,
+    /// Keep the N most recent artifacts of every package
+    #[arg(long, value_name = "N")]
+    keep_last: Option<usize>,
+    /// Keep every artifact that has at least one tag
+    #[arg(long)]
+    keep_tagged: bool,
+    /// Keep every artifact younger than this (e.g. `30days`, `2w`)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    keep_within: Option<Duration>,
+    /// Also delete `proofs/<commit>.json` for commits no longer referenced by the store
+    #[arg(long)]
+    proofs: bool,
+    /// Private key used to re-sign the index (required if the index is signed)
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    signing_key: Option<PathBuf>,
+}
+
+/// Retention rules; an artifact is kept if *any* rule retains it
+#[derive(Debug, Default)]
+pub struct Retention {
+    pub keep_last: Option<usize>,
+    pub keep_tagged: bool,
+    pub keep_within: Option<Duration>,
+}
+
+impl Retention {
+    fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && !self.keep_tagged && self.keep_within.is_none()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    pub keep: Vec<IndexEntry>,
+    pub remove: Vec<IndexEntry>,
+}
+
+impl Plan {
+    /// Commits referenced only by removed entries
+    fn orphaned_commits(&self) -> BTreeSet<&str> {
+        let kept: BTreeSet<_> = self.keep.iter().map(|e| e.commit.as_str()).collect();
+        self.remove
+            .iter()
+            .map(|e| e.commit.as_str())
+            .filter(|commit| !kept.contains(commit))
+            .collect()
+    }
+}
+
+/// Split `entries` into kept and removed sets, preserving index order within each
+pub fn plan(entries: &[IndexEntry], retention: &Retention, now: SystemTime) -> Result<Plan> {
+    let mut created = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let ts = humantime::parse_rfc3339(&entry.created).with_context(|| {
+            format!(
+                "invalid `created` timestamp {:?} for {}/{}",
+                entry.created, entry.package, entry.hash
+            )
+        })?;
+        created.push(ts);
+    }
+
+    let mut keep = vec![false; entries.len()];
+
+    if let Some(n) = retention.keep_last {
+        let mut by_package = BTreeMap::<&str, Vec<usize>>::new();
+        for (i, entry) in entries.iter().enumerate() {
+            by_package.entry(&entry.package).or_default().push(i);
+        }
+        for mut idxs in by_package.into_values() {
+            // Newest first; later index position wins ties since entries are appended
+            idxs.sort_by(|&a, &b| created[b].cmp(&created[a]).then(b.cmp(&a)));
+            idxs.into_iter().take(n).for_each(|i| keep[i] = true);
+        }
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if retention.keep_tagged && !entry.tags.is_empty() {
+            keep[i] = true;
+        }
+        if let Some(max_age) = retention.keep_within {
+            // Timestamps in the future count as young
+            let age = now.duration_since(created[i]).unwrap_or_default();
+            if age < max_age {
+                keep[i] = true;
+            }
+        }
+    }
+
+    let mut plan = Plan::default();
+    for (entry, keep) in entries.iter().cloned().zip(keep) {
+        if keep {
+            plan.keep.push(entry);
+        } else {
+            plan.remove.push(entry);
+        }
+    }
+    Ok(plan)
+}
+
+/// With `dry_run` (the global `--dry-run`), print what would be removed without touching anything
+pub fn run_gc(root: &Path, args: GcArgs, dry_run: bool) -> Result<()> {
+    let retention = Retention {
+        keep_last: args.keep_last,
+        keep_tagged: args.keep_tagged,
+        keep_within: args.keep_within,
+    };
+    if retention.is_empty() {
+        bail!("refusing to run without a retention rule (use --keep-last, --keep-tagged or --keep-within)");
+    }
+
+    let store = Store::open(root.join(&args.store));
+    let mut index = store.read_index()?;
+    index
+        .verify()
+        .with_context(|| format!("refusing to rewrite {}", store.index_path().display()))?;
+
+    // Resolve the key before touching anything so a signed index never ends up half-pruned
+    let signing_key = match (&args.signing_key, index.is_signed(), dry_run) {
+        (Some(path), _, _) => {
+            let key = store::read_signing_key(&root.join(path))?;
+            let public_key = hex::encode(key.verifying_key().to_bytes());
+            if index
+                .public_key
+                .as_ref()
+                .is_some_and(|pk| *pk != public_key)
+            {
+                bail!("--signing-key does not match the key that signed the index");
+            }
+            Some(key)
+        }
+        (None, true, false) => bail!("the store index is signed; pass --signing-key to re-sign it"),
+        (None, _, _) => None,
+    };
+
+    let plan = plan(&index.entries, &retention, SystemTime::now())?;
+    // Validate every path up front so a bad entry can't leave the index half-pruned
+    let dirs = plan
+        .remove
+        .iter()
+        .map(|entry| {
+            let dir = store.entry_dir(entry)?;
+            if !dir.starts_with(store.root()) {
+                bail!("refusing to remove {} outside of the store", dir.display());
+            }
+            Ok(dir)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let stale_proofs: Vec<_> = if args.proofs {
+        plan.orphaned_commits()
+            .into_iter()
+            .map(|commit| {
+                let commit = store::path_component("commit", commit)?;
+                Ok(root.join("proofs").join(format!("{commit}.json")))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let verb = if dry_run { "would remove" } else { "removing" };
+    for entry in &plan.remove {
+        println!(
+            "{verb} {}/{} (commit {}, created {})",
+            entry.package, entry.hash, entry.commit, entry.created
+        );
+    }
+    for proof in &stale_proofs {
+        println!("{verb} {}", proof.display());
+    }
+    println!(
+        "{} kept, {} {}",
+        plan.keep.len(),
+        plan.remove.len(),
+        if dry_run { "to remove" } else { "removed" }
+    );
+
+    if dry_run || plan.remove.is_empty() {
+        return Ok(());
+    }
+
+    // Rewrite the index first: a crash afterwards leaves unreferenced directories behind,
+    // never index entries pointing at deleted artifacts.
+    index.entries = plan.keep;
+    if let Some(key) = &signing_key {
+        index.sign(key)?;
+    }
+    store.write_index(&index)?;
+
+    for dir in &dirs {
+        if dir.exists() {
+            fs::remove_dir_all(dir)
+                .with_context(|| format!("failed to remove {}", dir.display()))?;
+        } else {
+            eprintln!("warning: {} was already missing", dir.display());
+        }
+        remove_empty_parent(dir, store.root());
+    }
+    for proof in &stale_proofs {
+        fs::remove_file(proof).with_context(|| format!("failed to remove {}", proof.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Drop the per-package directory once its last artifact is gone
+fn remove_empty_parent(dir: &Path, store_root: &Path) {
+    if let Some(parent) = dir.parent().filter(|p| p.starts_with(store_root)) {
+        // Fails (and is ignored) if the directory still has other artifacts
+        let _ = fs::remove_dir(parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn entry(package: &str, hash: &str, created: &str, tags: &[&str]) -> IndexEntry {
+        IndexEntry {
+            package: package.into(),
+            hash: hash.into(),
+            commit: format!("commit-{hash}"),
+            created: created.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    fn hashes(entries: &[IndexEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.hash.as_str()).collect()
+    }
+
+    fn fixture() -> Vec<IndexEntry> {
+        vec![
+            entry("foo", "f1", "2025-01-01T00:00:00Z", &["v1.0"]),
+            entry("foo", "f2", "2025-02-01T00:00:00Z", &[]),
+            entry("bar", "b1", "2025-01-15T00:00:00Z", &[]),
+            entry("foo", "f3", "2025-03-01T00:00:00Z", &[]),
+            entry("bar", "b2", "2025-03-10T00:00:00Z", &[]),
+        ]
+    }
+
+    fn now() -> SystemTime {
+        humantime::parse_rfc3339("2025-03-11T00:00:00Z").unwrap()
+    }
+
+    #[test]
+    fn keep_last_per_package() {
+        let retention = Retention {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+        let plan = plan(&fixture(), &retention, now()).unwrap();
+        assert_eq!(hashes(&plan.keep), ["f3", "b2"]);
+        assert_eq!(hashes(&plan.remove), ["f1", "f2", "b1"]);
+    }
+
+    #[test]
+    fn rules_are_additive() {
+        let retention = Retention {
+            keep_last: Some(1),
+            keep_tagged: true,
+            keep_within: Some(Duration::from_secs(45 * DAY)),
+        };
+        let plan = plan(&fixture(), &retention, now()).unwrap();
+        assert_eq!(hashes(&plan.keep), ["f1", "f2", "f3", "b2"]);
+        assert_eq!(hashes(&plan.remove), ["b1"]);
+    }
+
+    #[test]
+    fn keep_within_only() {
+        let retention = Retention {
+            keep_within: Some(Duration::from_secs(2 * DAY)),
+            ..Default::default()
+        };
+        let plan = plan(&fixture(), &retention, now()).unwrap();
+        assert_eq!(hashes(&plan.keep), ["b2"]);
+    }
+
+    #[test]
+    fn orphaned_commits_skip_shared_commits() {
+        let mut entries = fixture();
+        entries[0].commit = "shared".into();
+        entries[3].commit = "shared".into();
+        let retention = Retention {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+        let plan = plan(&entries, &retention, now()).unwrap();
+        let orphans: Vec<_> = plan.orphaned_commits().into_iter().collect();
+        assert_eq!(orphans, ["commit-b1", "commit-f2"]);
+    }
+
+    #[test]
+    fn invalid_timestamp_is_an_error() {
+        let entries = vec![entry("foo", "f1", "yesterday", &[])];
+        assert!(plan(&entries, &Retention::default(), now()).is_err());
+    }
+}
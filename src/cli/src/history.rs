@@ -0,0 +1,539 @@
+//! Build history: `one build`, `one check` and `one test` append one JSON line per result to
+//! `.one/history.jsonl`, and `one history` shows per-attribute trends and flags regressions.
+//!
+//! Every run is recorded as a whole; with `--progress` every derivation built is recorded too,
+//! so a slower `buildDepsOnly` shows up as its `*-deps` derivation getting slower.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    examples::{self, format_duration},
+    fast_check, progress,
+};
+
+/// Where the history goes (relative to the repository root)
+pub const HISTORY_FILE: &str = ".one/history.jsonl";
+
+/// Slowdown (in percent of the median) flagged as a regression
+pub const DEFAULT_THRESHOLD: usize = 50;
+
+/// Successful earlier runs needed before a run can count as a regression
+const MIN_SAMPLES: usize = 3;
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Only show attributes matching this glob (repeatable)
+    #[arg(value_name = "GLOB")]
+    pub attrs: Vec<String>,
+    /// Only show results of this command
+    #[arg(long, value_enum)]
+    pub command: Option<Command>,
+    /// Durations shown (and compared) per attribute
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub last: usize,
+    /// Slowdown against the median of the earlier runs that counts as a regression, in
+    /// percent [default: 50]
+    #[arg(long, value_name = "PERCENT")]
+    pub threshold: Option<usize>,
+    /// Only show regressions, and fail if there are any
+    #[arg(long)]
+    pub regressions: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Command {
+    Build,
+    Check,
+    Test,
+}
+
+/// What a record describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// A whole `nix build`/`nix flake check` invocation
+    Run,
+    /// A derivation built during a run (with `--progress`)
+    Derivation,
+    /// A check evaluated by `check --fast`
+    Check,
+    Example,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// Substituted from a binary cache instead of built
+    Cached,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// Unix time in seconds
+    pub time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub command: Command,
+    pub kind: Kind,
+    pub attr: String,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Paths substituted from binary caches during the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub out_paths: Vec<String>,
+}
+
+impl Record {
+    fn duration(&self) -> Option<Duration> {
+        self.duration_ms.map(Duration::from_millis)
+    }
+}
+
+/// Appends records to the history file (unless disabled by `history.record = false` or
+/// `--dry-run`); failing to record never fails the command
+pub struct Recorder {
+    file: Option<PathBuf>,
+    commit: Option<String>,
+}
+
+impl Recorder {
+    pub fn new(root: &Path, enabled: bool) -> Self {
+        Self {
+            file: enabled.then(|| root.join(HISTORY_FILE)),
+            commit: enabled.then(|| head_commit(root)).flatten(),
+        }
+    }
+
+    /// A record of the current time and commit
+    pub fn entry(
+        &self,
+        command: Command,
+        kind: Kind,
+        attr: &str,
+        outcome: Outcome,
+        duration: Option<Duration>,
+    ) -> Record {
+        Record {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            commit: self.commit.clone(),
+            command,
+            kind,
+            attr: attr.to_string(),
+            outcome,
+            duration_ms: duration.map(|d| d.as_millis() as u64),
+            downloaded: None,
+            out_paths: Vec::new(),
+        }
+    }
+
+    pub fn record(&self, records: &[Record]) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Err(err) = append(file, records) {
+            eprintln!("warning: failed to record build history: {err:#}");
+        }
+    }
+
+    /// Record a nix invocation and, if its progress was tracked, the derivations it built
+    pub fn record_run(
+        &self,
+        command: Command,
+        attr: &str,
+        result: &Result<()>,
+        duration: Duration,
+        tracker: Option<&progress::Tracker>,
+        out_paths: Vec<String>,
+    ) {
+        let mut run = self.entry(command, Kind::Run, attr, outcome(result), Some(duration));
+        run.out_paths = out_paths;
+        let mut records = Vec::new();
+        if let Some(tracker) = tracker {
+            run.downloaded = Some(tracker.downloaded);
+            records.extend(tracker.finished.iter().map(|build| {
+                let outcome = if build.failed {
+                    Outcome::Failed
+                } else {
+                    Outcome::Passed
+                };
+                self.entry(
+                    command,
+                    Kind::Derivation,
+                    &build.name,
+                    outcome,
+                    Some(build.duration),
+                )
+            }));
+        }
+        records.insert(0, run);
+        self.record(&records);
+    }
+
+    /// Record a `check --fast` run and the status of every check
+    pub fn record_checks(&self, report: &fast_check::Report, duration: Duration) {
+        let outcome = if report.failed() == 0 {
+            Outcome::Passed
+        } else {
+            Outcome::Failed
+        };
+        let mut records =
+            vec![self.entry(Command::Check, Kind::Run, "checks", outcome, Some(duration))];
+        records.extend(report.checks.iter().map(|(attr, status)| {
+            let outcome = match status {
+                fast_check::Status::Cached => Outcome::Cached,
+                fast_check::Status::Built => Outcome::Passed,
+                fast_check::Status::Failed | fast_check::Status::EvalFailed(_) => Outcome::Failed,
+            };
+            self.entry(Command::Check, Kind::Check, attr, outcome, None)
+        }));
+        self.record(&records);
+    }
+
+    pub fn record_examples(&self, outcomes: &[examples::Outcome]) {
+        let records: Vec<_> = outcomes
+            .iter()
+            .map(|o| {
+                let outcome = if o.passed {
+                    Outcome::Passed
+                } else {
+                    Outcome::Failed
+                };
+                self.entry(
+                    Command::Test,
+                    Kind::Example,
+                    &o.name,
+                    outcome,
+                    Some(o.duration),
+                )
+            })
+            .collect();
+        self.record(&records);
+    }
+}
+
+pub fn outcome(result: &Result<()>) -> Outcome {
+    if result.is_ok() {
+        Outcome::Passed
+    } else {
+        Outcome::Failed
+    }
+}
+
+fn append(file: &Path, records: &[Record]) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let mut lines = String::new();
+    for record in records {
+        lines.push_str(&serde_json::to_string(record)?);
+        lines.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .and_then(|mut f| f.write_all(lines.as_bytes()))
+        .with_context(|| format!("failed to write {}", file.display()))
+}
+
+/// `result`/`result-*` links in `root` created or updated since `started`
+pub fn fresh_out_links(root: &Path, started: SystemTime) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name == "result" || name.starts_with("result-")
+        })
+        .filter(|entry| {
+            fs::symlink_metadata(entry.path())
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified >= started)
+        })
+        .filter_map(|entry| fs::read_link(entry.path()).ok())
+        .map(|target| target.display().to_string())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The checked-out commit, read from `.git` directly
+fn head_commit(root: &Path) -> Option<String> {
+    let mut git_dir = root.join(".git");
+    if git_dir.is_file() {
+        // Worktrees and submodules: `gitdir: <path>`
+        let link = fs::read_to_string(&git_dir).ok()?;
+        git_dir = root.join(link.strip_prefix("gitdir:")?.trim());
+    }
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let Some(reference) = head.trim().strip_prefix("ref: ") else {
+        return Some(head.trim().to_string());
+    };
+    // Branches of worktrees live in the main repository
+    let common = fs::read_to_string(git_dir.join("commondir"))
+        .map(|dir| git_dir.join(dir.trim()))
+        .unwrap_or_else(|_| git_dir.clone());
+    for dir in [&git_dir, &common] {
+        if let Ok(commit) = fs::read_to_string(dir.join(reference)) {
+            return Some(commit.trim().to_string());
+        }
+    }
+    let packed = fs::read_to_string(common.join("packed-refs")).ok()?;
+    packed.lines().find_map(|line| {
+        let (commit, name) = line.split_once(' ')?;
+        (name == reference).then(|| commit.to_string())
+    })
+}
+
+pub fn load(root: &Path) -> Result<Vec<Record>> {
+    let file = root.join(HISTORY_FILE);
+    let text = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", file.display())),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid history record", file.display(), n + 1))
+        })
+        .collect()
+}
+
+/// Trend of one attribute
+#[derive(Debug, PartialEq)]
+pub struct Trend {
+    pub command: Command,
+    pub kind: Kind,
+    pub attr: String,
+    pub runs: usize,
+    pub last_outcome: Outcome,
+    /// Durations of the most recent successful runs, oldest first
+    pub durations: Vec<Duration>,
+    /// Median of `durations` without the last one
+    pub median: Option<Duration>,
+    /// Slowdown of the last duration against `median`, in percent
+    pub change: Option<i64>,
+    pub regression: bool,
+}
+
+/// Per-attribute trends over the last `last` successful runs, in first-seen order
+pub fn trends(records: &[Record], last: usize, threshold: usize) -> Vec<Trend> {
+    type Key<'a> = (Command, Kind, &'a str);
+    let mut groups: Vec<(Key, Vec<&Record>)> = Vec::new();
+    let mut index = BTreeMap::new();
+    for record in records {
+        let key = (record.command, record.kind, record.attr.as_str());
+        let at = *index.entry(key).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[at].1.push(record);
+    }
+
+    groups
+        .into_iter()
+        .map(|((command, kind, attr), records)| {
+            let mut durations: Vec<_> = records
+                .iter()
+                .filter(|r| r.outcome == Outcome::Passed)
+                .filter_map(|r| r.duration())
+                .collect();
+            durations.drain(..durations.len().saturating_sub(last.max(1)));
+
+            let earlier = &durations[..durations.len().saturating_sub(1)];
+            let median = median(earlier);
+            let change = match (median, durations.last()) {
+                (Some(median), Some(latest)) if !median.is_zero() => Some(
+                    ((latest.as_secs_f64() / median.as_secs_f64() - 1.0) * 100.0).round() as i64,
+                ),
+                _ => None,
+            };
+            Trend {
+                command,
+                kind,
+                attr: attr.to_string(),
+                runs: records.len(),
+                last_outcome: records.last().expect("groups are never empty").outcome,
+                regression: earlier.len() >= MIN_SAMPLES
+                    && change.is_some_and(|change| change > threshold as i64),
+                durations,
+                median,
+                change,
+            }
+        })
+        .collect()
+}
+
+fn median(durations: &[Duration]) -> Option<Duration> {
+    let mut sorted = durations.to_vec();
+    sorted.sort();
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[n / 2]),
+        n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2),
+    }
+}
+
+pub fn render(trends: &[Trend]) -> String {
+    let label = |t: &Trend| {
+        let kind = match t.kind {
+            Kind::Run => "run",
+            Kind::Derivation => "drv",
+            Kind::Check => "check",
+            Kind::Example => "example",
+        };
+        format!("{:?} {kind} {}", t.command, t.attr).to_lowercase()
+    };
+    let width = trends.iter().map(|t| label(t).len()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for trend in trends {
+        let outcome = match trend.last_outcome {
+            Outcome::Passed => "passed",
+            Outcome::Failed => "FAILED",
+            Outcome::Cached => "cached",
+        };
+        let median = trend.median.map_or("-".to_string(), format_duration);
+        let change = trend.change.map_or("-".to_string(), |c| format!("{c:+}%"));
+        let durations: Vec<_> = trend
+            .durations
+            .iter()
+            .map(|d| format_duration(*d))
+            .collect();
+        let line = format!(
+            "{:width$}  {:>4} runs  {outcome:6}  median {median:>7}  {change:>6}  {}{}",
+            label(trend),
+            trend.runs,
+            durations.join(" "),
+            if trend.regression { "  REGRESSION" } else { "" },
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+pub fn run(root: &Path, args: HistoryArgs, threshold: usize) -> Result<()> {
+    let records: Vec<_> = load(root)?
+        .into_iter()
+        .filter(|r| args.command.is_none_or(|c| c == r.command))
+        .filter(|r| {
+            args.attrs.is_empty() || args.attrs.iter().any(|g| examples::glob_match(g, &r.attr))
+        })
+        .collect();
+    if records.is_empty() {
+        println!("no recorded runs in {HISTORY_FILE}");
+        return Ok(());
+    }
+
+    let mut trends = trends(&records, args.last, threshold);
+    if args.regressions {
+        trends.retain(|t| t.regression);
+    }
+    print!("{}", render(&trends));
+
+    let regressions = trends.iter().filter(|t| t.regression).count();
+    if args.regressions && regressions > 0 {
+        bail!("{regressions} regression(s) beyond {threshold}%");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kind: Kind, attr: &str, outcome: Outcome, secs: u64) -> Record {
+        Record {
+            time: 0,
+            commit: None,
+            command: Command::Build,
+            kind,
+            attr: attr.to_string(),
+            outcome,
+            duration_ms: Some(secs * 1000),
+            downloaded: None,
+            out_paths: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let mut r = record(Kind::Run, "default", Outcome::Passed, 3);
+        r.out_paths = vec!["/nix/store/x-default".into()];
+        let line = serde_json::to_string(&r).unwrap();
+        assert_eq!(
+            line,
+            r#"{"time":0,"command":"build","kind":"run","attr":"default","outcome":"passed","duration_ms":3000,"out_paths":["/nix/store/x-default"]}"#
+        );
+        assert_eq!(serde_json::from_str::<Record>(&line).unwrap(), r);
+    }
+
+    #[test]
+    fn flags_regressions_against_the_median() {
+        let deps = |outcome, secs| record(Kind::Derivation, "app-deps-0.1.0", outcome, secs);
+        let records = vec![
+            deps(Outcome::Passed, 10),
+            record(Kind::Run, "default", Outcome::Passed, 30),
+            deps(Outcome::Passed, 12),
+            deps(Outcome::Failed, 1),
+            deps(Outcome::Passed, 11),
+            deps(Outcome::Passed, 24),
+        ];
+
+        let trends = trends(&records, 10, 50);
+        assert_eq!(trends.len(), 2);
+        let deps = &trends[0];
+        assert_eq!(deps.runs, 5);
+        assert_eq!(deps.median, Some(Duration::from_secs(11)));
+        assert_eq!(deps.change, Some(118));
+        assert!(deps.regression);
+        // Too few samples to judge
+        assert!(!trends[1].regression);
+
+        // Only the last 3 durations are compared: 12s 11s 24s
+        let trends = super::trends(&records, 3, 50);
+        assert_eq!(trends[0].durations.len(), 3);
+        assert!(!trends[0].regression);
+
+        assert_eq!(
+            render(&super::trends(&records, 10, 200)),
+            "\
+build drv app-deps-0.1.0     5 runs  passed  median   11.0s   +118%  10.0s 12.0s 11.0s 24.0s
+build run default            1 runs  passed  median       -       -  30.0s
+"
+        );
+    }
+
+    #[test]
+    fn reads_the_current_commit() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let commit = head_commit(&root).unwrap();
+        assert_eq!(commit.len(), 40, "{commit}");
+        assert!(commit.bytes().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,363 @@
+//! `one init`: scaffold a project from the bundled examples without network access.
+//!
+//! The example is copied as it is in the checkout, with the crate name, edition and (for
+//! templates using rust-overlay) toolchain substituted, and the `one-for-all` input pinned to
+//! the local checkout or a given ref.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, ValueHint};
+
+use crate::{doctor::SIGNING_KEY_ENV, examples, store, DEFAULT_EXAMPLE_DIR};
+
+/// The input line every template starts with
+const UPSTREAM_INPUT: &str = r#"one-for-all.url = "github:l1ne-company/one-for-all";"#;
+
+/// Never copied from an example
+const SKIPPED: &[&str] = &["target", "result", "flake.lock"];
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Example to start from (a directory under `src/lang/rust/examples`)
+    #[arg(value_name = "TEMPLATE")]
+    template: String,
+    /// Directory to create (defaults to the template name; must not exist or be empty)
+    #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+    dir: Option<PathBuf>,
+    /// Crate name (defaults to the directory name)
+    #[arg(long, value_name = "NAME")]
+    name: Option<String>,
+    /// Rust edition for every manifest that sets one
+    #[arg(long, value_name = "EDITION")]
+    edition: Option<String>,
+    /// rust-overlay toolchain: `stable`, `beta`, `nightly`, `1.79.0` or `nightly-2024-05-01`
+    #[arg(long, value_name = "CHANNEL")]
+    toolchain: Option<String>,
+    /// Pin `one-for-all` to this ref on GitHub instead of the local checkout
+    #[arg(long = "ref", value_name = "REF")]
+    git_ref: Option<String>,
+    /// Set up build signing: a `proofs/` directory and `prover_keys/trusted.txt`
+    #[arg(long)]
+    signing: bool,
+}
+
+/// A file of the new project, relative to its directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+    pub permissions: Option<fs::Permissions>,
+}
+
+impl File {
+    fn new(path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) -> Self {
+        Self {
+            path: path.into(),
+            contents: contents.into(),
+            permissions: None,
+        }
+    }
+}
+
+/// With `dry_run` (the global `--dry-run`), list the files without writing them
+pub fn run_init(root: &Path, args: InitArgs, dry_run: bool) -> Result<()> {
+    let template = root.join(DEFAULT_EXAMPLE_DIR).join(&args.template);
+    if !template.join("flake.nix").is_file() {
+        let available: Vec<_> = examples::discover(&root.join(DEFAULT_EXAMPLE_DIR))?
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        bail!(
+            "unknown template {:?} (available: {})",
+            args.template,
+            available.join(", ")
+        );
+    }
+
+    let dest = env::current_dir()
+        .context("failed to determine current directory")?
+        .join(args.dir.as_deref().unwrap_or(Path::new(&args.template)));
+    if dest.exists()
+        && fs::read_dir(&dest)
+            .with_context(|| format!("failed to read {}", dest.display()))?
+            .next()
+            .is_some()
+    {
+        bail!("{} already exists and is not empty", dest.display());
+    }
+
+    let name = match args.name {
+        Some(name) => name,
+        None => dest
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("cannot derive a crate name from {}", dest.display()))?
+            .to_string(),
+    };
+    let input = match &args.git_ref {
+        Some(git_ref) => format!("github:l1ne-company/one-for-all/{git_ref}"),
+        None => format!("path:{}", root.display()),
+    };
+    let toolchain = args.toolchain.as_deref().map(toolchain_expr).transpose()?;
+
+    let mut files = read_template(&template)?;
+    let old_name = package_name(&files).unwrap_or_else(|| args.template.clone());
+    for file in &mut files {
+        let Ok(text) = String::from_utf8(file.contents.clone()) else {
+            continue;
+        };
+        let mut text = rename_crate(&text, &old_name, &name);
+        if is_manifest(&file.path) {
+            if let Some(edition) = &args.edition {
+                text = set_edition(&text, edition);
+            }
+        }
+        if file.path == Path::new("flake.nix") {
+            text = pin_input(&text, &input)?;
+            if let Some(toolchain) = &toolchain {
+                text = set_toolchain(&text, toolchain)?;
+            }
+        }
+        file.contents = text.into_bytes();
+    }
+    if args.signing {
+        files.extend(signing_files()?);
+    }
+
+    let verb = if dry_run { "would create" } else { "creating" };
+    for file in &files {
+        println!("{verb} {}", dest.join(&file.path).display());
+        if dry_run {
+            continue;
+        }
+        let path = dest.join(&file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, &file.contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        if let Some(permissions) = &file.permissions {
+            fs::set_permissions(&path, permissions.clone())
+                .with_context(|| format!("failed to set permissions of {}", path.display()))?;
+        }
+    }
+
+    if !dry_run {
+        println!();
+        println!(
+            "next: cd {} && git init && git add -A && nix flake check",
+            dest.display()
+        );
+        println!("(flakes only see files known to git)");
+    }
+    Ok(())
+}
+
+/// Every file of `template`, except build results and lock files of the example itself
+pub fn read_template(template: &Path) -> Result<Vec<File>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel) = dirs.pop() {
+        let dir = template.join(&rel);
+        let entries =
+            fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+            let name = entry.file_name();
+            let name_str = name.to_string_lossy();
+            if SKIPPED
+                .iter()
+                .any(|s| name_str == *s || name_str.starts_with("result-"))
+            {
+                continue;
+            }
+
+            let path = rel.join(&name);
+            let metadata = entry
+                .metadata()
+                .with_context(|| format!("failed to read {}", entry.path().display()))?;
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(File {
+                    contents: fs::read(entry.path())
+                        .with_context(|| format!("failed to read {}", entry.path().display()))?,
+                    path,
+                    permissions: Some(metadata.permissions()),
+                });
+            }
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "Cargo.toml")
+}
+
+/// `package.name` of the top-level manifest, if it has a package
+fn package_name(files: &[File]) -> Option<String> {
+    let manifest = files.iter().find(|f| f.path == Path::new("Cargo.toml"))?;
+    let table: toml::Table = toml::from_str(std::str::from_utf8(&manifest.contents).ok()?).ok()?;
+    Some(table.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// Replace `old` as a whole word (`-`/`_` count as word characters), both in its kebab-case
+/// and snake_case spelling
+pub fn rename_crate(text: &str, old: &str, new: &str) -> String {
+    let text = replace_word(text, old, new);
+    let (old_snake, new_snake) = (old.replace('-', "_"), new.replace('-', "_"));
+    if old_snake == old {
+        return text;
+    }
+    replace_word(&text, &old_snake, &new_snake)
+}
+
+fn replace_word(text: &str, old: &str, new: &str) -> String {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(old) {
+        let before = match at {
+            0 => out.chars().next_back(),
+            _ => rest[..at].chars().next_back(),
+        };
+        let after = rest[at + old.len()..].chars().next();
+        out.push_str(&rest[..at]);
+        if before.is_some_and(is_word) || after.is_some_and(is_word) {
+            out.push_str(old);
+        } else {
+            out.push_str(new);
+        }
+        rest = &rest[at + old.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrite every `edition = "..."` line (not `edition.workspace = true`)
+pub fn set_edition(manifest: &str, edition: &str) -> String {
+    manifest
+        .split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim_start();
+            match trimmed.strip_prefix("edition") {
+                Some(rest) if rest.trim_start().starts_with('=') => {
+                    let indent = &line[..line.len() - trimmed.len()];
+                    let newline = if line.ends_with('\n') { "\n" } else { "" };
+                    format!("{indent}edition = \"{edition}\"{newline}")
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Point the template's `one-for-all` input at `input`
+pub fn pin_input(flake: &str, input: &str) -> Result<String> {
+    if !flake.contains(UPSTREAM_INPUT) {
+        bail!("flake.nix has no `{UPSTREAM_INPUT}` line to pin");
+    }
+    Ok(flake.replace(UPSTREAM_INPUT, &format!(r#"one-for-all.url = "{input}";"#)))
+}
+
+/// rust-overlay attribute path for a channel: `stable.latest`, `stable."1.79.0"`,
+/// `nightly."2024-05-01"`
+pub fn toolchain_expr(channel: &str) -> Result<String> {
+    Ok(match channel {
+        "stable" | "beta" | "nightly" => format!("{channel}.latest"),
+        _ => match channel.split_once('-') {
+            Some((kind @ ("beta" | "nightly"), date)) => format!("{kind}.\"{date}\""),
+            None if channel.split('.').all(|p| p.parse::<u32>().is_ok()) => {
+                format!("stable.\"{channel}\"")
+            }
+            _ => bail!("unrecognized toolchain {channel:?}"),
+        },
+    })
+}
+
+fn set_toolchain(flake: &str, expr: &str) -> Result<String> {
+    const SELECTED: &str = "rust-bin.stable.latest";
+    if !flake.contains(SELECTED) {
+        bail!("--toolchain needs a template that selects a rust-overlay toolchain (e.g. custom-toolchain)");
+    }
+    Ok(flake.replace(SELECTED, &format!("rust-bin.{expr}")))
+}
+
+/// `proofs/` and `prover_keys/trusted.txt`, trusting the key in `BUILD_SIGNER_KEY` if set
+fn signing_files() -> Result<Vec<File>> {
+    let mut trusted = String::from("# Public keys (hex) trusted to sign builds, one per line\n");
+    if let Some(key) = env::var_os(SIGNING_KEY_ENV) {
+        let key = store::read_signing_key(Path::new(&key))?;
+        trusted.push_str(&hex::encode(key.verifying_key().to_bytes()));
+        trusted.push('\n');
+    }
+    Ok(vec![
+        File::new("proofs/.gitkeep", ""),
+        File::new("prover_keys/trusted.txt", trusted),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_names_are_replaced_as_words() {
+        let text =
+            "name = \"quick-start\"\nuse quick_start::x;\nquick-start-simple\nmy-quick-start\n";
+        assert_eq!(
+            rename_crate(text, "quick-start", "hello-world"),
+            "name = \"hello-world\"\nuse hello_world::x;\nquick-start-simple\nmy-quick-start\n"
+        );
+        assert_eq!(rename_crate("bin/trunk", "trunk", "app"), "bin/app");
+    }
+
+    #[test]
+    fn editions() {
+        let manifest = "[package]\nedition = \"2021\"\n\n[workspace.package]\n  edition=\"2018\"\n\n[x]\nedition.workspace = true\n";
+        assert_eq!(
+            set_edition(manifest, "2024"),
+            "[package]\nedition = \"2024\"\n\n[workspace.package]\n  edition = \"2024\"\n\n[x]\nedition.workspace = true\n"
+        );
+    }
+
+    #[test]
+    fn toolchains() {
+        assert_eq!(toolchain_expr("stable").unwrap(), "stable.latest");
+        assert_eq!(toolchain_expr("1.79.0").unwrap(), "stable.\"1.79.0\"");
+        assert_eq!(
+            toolchain_expr("nightly-2024-05-01").unwrap(),
+            "nightly.\"2024-05-01\""
+        );
+        assert!(toolchain_expr("latest").is_err());
+    }
+
+    #[test]
+    fn templates_are_pinned_and_renamed() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let template = root.join(DEFAULT_EXAMPLE_DIR).join("custom-toolchain");
+        let files = read_template(&template).unwrap();
+        assert_eq!(package_name(&files).as_deref(), Some("custom-toolchain"));
+
+        let flake = files
+            .iter()
+            .find(|f| f.path == Path::new("flake.nix"))
+            .unwrap();
+        let flake = String::from_utf8(flake.contents.clone()).unwrap();
+        let flake = pin_input(&flake, "path:/src/one-for-all").unwrap();
+        let flake = set_toolchain(&flake, &toolchain_expr("1.79.0").unwrap()).unwrap();
+        let flake = rename_crate(&flake, "custom-toolchain", "wasm-app");
+
+        assert!(flake.contains(r#"one-for-all.url = "path:/src/one-for-all";"#));
+        assert!(flake.contains(r#"p.rust-bin.stable."1.79.0".default.override"#));
+        assert!(flake.contains("bin/wasm-app.wasm"), "{flake}");
+        assert!(set_toolchain("{ }", "stable.latest").is_err());
+    }
+}
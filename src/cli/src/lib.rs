@@ -0,0 +1,899 @@
+//! `one`: local helper wrapping the repository's Nix workflows.
+//!
+//! Every external command goes through a [`Runner`], so `--dry-run` and the integration
+//! tests (with [`runner::Fake`]) see exactly what would be executed.
+
+mod affected;
+mod bisect;
+mod cache;
+mod config;
+mod doctor;
+mod example_check;
+mod examples;
+mod fast_check;
+mod flake_lock;
+mod gc;
+mod history;
+mod init;
+mod lock;
+mod progress;
+mod report;
+pub mod runner;
+mod store;
+mod watch;
+mod why_rebuild;
+
+use std::{
+    collections::BTreeSet,
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueHint};
+
+use config::Config;
+use history::Recorder;
+use runner::{Cmd, Output, Runner};
+
+const DEFAULT_EXAMPLE_DIR: &str = "src/lang/rust/examples";
+const DEFAULT_NIXPKGS_FROM: &str = "src/lang/rust/test#nixpkgs";
+
+#[derive(Parser)]
+#[command(
+    name = "one",
+    version,
+    author,
+    about = "Local helper for one-for-all",
+    long_about = "Wrapper CLI around the repository's primary Nix workflows."
+)]
+pub struct Cli {
+    /// Path to the repository root (defaults to the current directory)
+    #[arg(
+        global = true,
+        short,
+        long,
+        default_value = ".",
+        value_hint = ValueHint::DirPath
+    )]
+    root: PathBuf,
+    /// Print the commands (and for `gc`, the removals) instead of executing them
+    #[arg(global = true, long)]
+    pub dry_run: bool,
+    /// Apply `[profiles.NAME]` from `one.toml` on top of the other settings
+    #[arg(global = true, long, value_name = "NAME")]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Build one of the flake outputs with `nix build`
+    Build(BuildArgs),
+    /// Run `nix flake check` for the repository
+    Check(CheckArgs),
+    /// Run the bundled CI helper (`nix run .#ci`)
+    Ci(CiArgs),
+    /// Run `nix flake check` for a template/example project
+    Test(TestArgs),
+    /// Enter the devshell or run a command inside it via `nix develop`
+    Develop(DevelopArgs),
+    /// Prune old artifacts from the local store according to retention rules
+    Gc(gc::GcArgs),
+    /// Create a project from one of the bundled examples (offline)
+    Init(init::InitArgs),
+    /// Re-run a build, the checks or an example test whenever relevant sources change
+    Watch(watch::WatchArgs),
+    /// Explain why an attribute's derivation differs between two revisions
+    WhyRebuild(why_rebuild::WhyRebuildArgs),
+    /// Find the commit that broke a check with `git bisect`, building it at each step
+    Bisect(bisect::BisectArgs),
+    /// Show timing trends of recorded builds, checks and tests, and flag regressions
+    History(history::HistoryArgs),
+    /// List the checks, examples and packages affected by the changes since a git ref
+    Affected(AffectedArgs),
+    /// Share build outputs over the LAN with a local Nix binary cache
+    #[command(subcommand)]
+    Cache(cache::CacheCommand),
+    /// Inspect, diff and check the pinned flake inputs in a `flake.lock`
+    #[command(subcommand)]
+    Lock(lock::LockCommand),
+    /// Check the local nix setup, tools, input pins and signing key, and suggest fixes
+    Doctor,
+    /// Inspect the `one.toml` configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration and where each value came from
+    Show,
+}
+
+#[derive(Args)]
+struct AffectedArgs {
+    /// Compare the working tree against this git ref
+    #[arg(long, value_name = "REF")]
+    since: String,
+    /// Show what each changed file affects
+    #[arg(long)]
+    explain: bool,
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Flake attribute to build (omit the leading `#`)
+    #[arg(short, long, value_name = "ATTR")]
+    attribute: Option<String>,
+    /// Show our own progress (active builds, counts, durations, failing logs) instead of
+    /// nix's output
+    #[arg(long)]
+    progress: bool,
+    /// Extra arguments forwarded to `nix build`
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    nix_args: Vec<String>,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Show build logs (`-L`)
+    #[arg(short, long)]
+    verbose: bool,
+    /// Pass `--no-build` to `nix flake check`
+    #[arg(long, conflicts_with = "fast")]
+    no_build: bool,
+    /// Only build checks missing from the binary caches (via `nix-eval-jobs`), reporting
+    /// which checks were cached, built or failed
+    #[arg(long)]
+    fast: bool,
+    /// Uncached checks built per `nix build` invocation with `--fast` [default: 16]
+    #[arg(long, value_name = "N")]
+    batch_size: Option<usize>,
+    /// Allow `nix flake check` to update the lockfile
+    #[arg(long)]
+    allow_write_lock: bool,
+    /// Only build the checks and packages affected by the changes since this git ref (see
+    /// `one affected`)
+    #[arg(long, value_name = "REF", conflicts_with_all = ["fast", "no_build"])]
+    since: Option<String>,
+    /// Show our own progress (active builds, counts, durations, failing logs) instead of
+    /// nix's output
+    #[arg(long, conflicts_with = "fast")]
+    progress: bool,
+    /// Build every check on its own and write the outcome, duration and failing log tail of
+    /// each to a JUnit (`.xml`) or JSON (`.json`) report
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["no_build", "since", "progress", "batch_size"]
+    )]
+    report: Option<PathBuf>,
+    /// Extra arguments forwarded to `nix flake check`
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    nix_args: Vec<String>,
+}
+
+#[derive(Args)]
+struct TestArgs {
+    /// Example/template name (relative to `src/lang/rust/examples`) or a custom path
+    #[arg(
+        value_name = "NAME_OR_PATH",
+        required_unless_present_any = ["all", "since"],
+        conflicts_with_all = ["all", "since"]
+    )]
+    example: Option<String>,
+    /// Check every example under `src/lang/rust/examples` and print a summary matrix
+    #[arg(long)]
+    all: bool,
+    /// Like `--all`, but only the examples affected by the changes since this git ref (see
+    /// `one affected`)
+    #[arg(long, value_name = "REF", conflicts_with = "all")]
+    since: Option<String>,
+    /// Only check examples whose name matches this glob (repeatable, with `--all` or `--since`)
+    #[arg(long, value_name = "GLOB")]
+    only: Vec<String>,
+    /// Skip examples whose name matches this glob (repeatable, with `--all` or `--since`)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Maximum number of examples checked at once [default: 4]
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Directory for per-example logs (relative paths are resolved against the repository root)
+    #[arg(long, value_name = "DIR", default_value = examples::DEFAULT_LOG_DIR, value_hint = ValueHint::DirPath)]
+    log_dir: PathBuf,
+    /// `<dir>#<input>` whose locked revision pins the example's nixpkgs (like `ci/ref-from-lock.sh`)
+    /// [default: src/lang/rust/test#nixpkgs]
+    #[arg(long, value_name = "DIR#INPUT")]
+    nixpkgs_from: Option<String>,
+    /// Extra arguments forwarded to `nix flake check`
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    nix_args: Vec<String>,
+}
+
+#[derive(Args)]
+struct DevelopArgs {
+    /// Devshell attribute to use (omit the leading `#`)
+    #[arg(short, long, value_name = "ATTR")]
+    attribute: Option<String>,
+    /// Additional flags forwarded to `nix develop`
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    nix_args: Vec<String>,
+    /// Command to execute inside the devshell
+    #[arg(long, value_name = "PROGRAM")]
+    command: Option<String>,
+    /// Arguments for `--command` (only valid when `--command` is set)
+    #[arg(last = true, value_name = "ARG", requires = "command")]
+    command_args: Vec<String>,
+}
+
+#[derive(Args)]
+struct CiArgs {
+    /// Extra arguments forwarded to `nix flake check` (after `--`)
+    #[arg(last = true, value_name = "ARG")]
+    extra_args: Vec<String>,
+}
+
+/// Run the parsed command line, executing external commands through `runner`
+pub fn run(cli: Cli, runner: &dyn Runner) -> Result<()> {
+    let root = canonicalize_root(&cli.root)?;
+    let config = Config::load(&root, cli.profile.as_deref())?;
+    let history = Recorder::new(&root, !cli.dry_run && config.bool("history.record"));
+
+    match cli.command {
+        Commands::Build(args) => run_build(runner, &root, &config, &history, args),
+        Commands::Check(args) => run_check(runner, &root, &config, &history, args, cli.dry_run),
+        Commands::Ci(args) => run_ci(runner, &root, &config, args),
+        Commands::Test(args) => run_tests(runner, &root, &config, &history, args),
+        Commands::Develop(args) => run_develop(runner, &root, &config, args),
+        Commands::Gc(args) => gc::run_gc(&root, args, cli.dry_run),
+        Commands::Init(args) => init::run_init(&root, args, cli.dry_run),
+        Commands::Watch(args) => run_watch(runner, &root, &config, &history, args),
+        Commands::History(args) => {
+            let threshold = args
+                .threshold
+                .or_else(|| config.usize("history.threshold"))
+                .unwrap_or(history::DEFAULT_THRESHOLD);
+            history::run(&root, args, threshold)
+        }
+        Commands::WhyRebuild(args) => why_rebuild::run(runner, &root, args),
+        Commands::Bisect(mut args) => {
+            args.nix_args = config.nix_args("check", args.nix_args);
+            bisect::run(runner, &root, args)
+        }
+        Commands::Affected(args) => {
+            let selection = affected_since(runner, &root, &config, &args.since)?;
+            selection.print(args.explain);
+            Ok(())
+        }
+        Commands::Cache(command) => run_cache(runner, &root, &config, command),
+        Commands::Lock(command) => {
+            let nixpkgs_from = config
+                .string("test.nixpkgs-from")
+                .unwrap_or_else(|| DEFAULT_NIXPKGS_FROM.to_string());
+            let (dir, _) = nixpkgs_from.split_once('#').unwrap_or((&nixpkgs_from, ""));
+            lock::run(runner, &root, dir, command)
+        }
+        Commands::Doctor => {
+            let nixpkgs_from = config
+                .string("test.nixpkgs-from")
+                .unwrap_or_else(|| DEFAULT_NIXPKGS_FROM.to_string());
+            doctor::report(&doctor::run(runner, &root, &nixpkgs_from)?)
+        }
+        Commands::Config(ConfigCommand::Show) => {
+            print!("{}", config.show());
+            Ok(())
+        }
+    }
+}
+
+fn canonicalize_root(root: &Path) -> Result<PathBuf> {
+    let path = if root.is_absolute() {
+        root.to_path_buf()
+    } else {
+        env::current_dir()
+            .context("failed to determine current directory")?
+            .join(root)
+    };
+
+    path.canonicalize()
+        .with_context(|| format!("failed to resolve repository root: {}", path.display()))
+}
+
+fn run_build(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    history: &Recorder,
+    args: BuildArgs,
+) -> Result<()> {
+    let attribute = args.attribute.or_else(|| config.string("build.attribute"));
+    let installable = build_installable(root, attribute.as_deref());
+    let nix_args = config.nix_args("build", args.nix_args);
+    let mut cmd_args = Vec::with_capacity(4 + nix_args.len());
+    cmd_args.push(oss("build"));
+    cmd_args.push(oss("--accept-flake-config"));
+    cmd_args.push(oss("--print-build-logs"));
+    cmd_args.extend(nix_args.into_iter().map(OsString::from));
+    cmd_args.push(installable);
+
+    let progress = args.progress || config.bool("build.progress");
+    let attr = attribute.as_deref().unwrap_or("default");
+    let cmd = Cmd::new("nix", cmd_args, root);
+    run_nix(
+        runner,
+        cmd,
+        progress,
+        history,
+        history::Command::Build,
+        attr,
+    )
+}
+
+fn run_check(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    history: &Recorder,
+    mut args: CheckArgs,
+    dry_run: bool,
+) -> Result<()> {
+    args.verbose |= config.bool("check.verbose");
+    args.allow_write_lock |= config.bool("check.allow-write-lock");
+    args.progress |= config.bool("check.progress");
+    // A configured mode never overrides the other one given on the command line
+    if !args.fast && !args.no_build && args.since.is_none() {
+        args.fast = config.bool("check.fast");
+        args.no_build = !args.fast && config.bool("check.no-build");
+    }
+    args.nix_args = config.nix_args("check", args.nix_args);
+
+    if let Some(path) = &args.report {
+        return run_reported_check(runner, root, history, path, args.nix_args, dry_run);
+    }
+    if args.fast {
+        let batch_size = args
+            .batch_size
+            .or_else(|| config.usize("check.batch-size"))
+            .unwrap_or(fast_check::DEFAULT_BATCH_SIZE);
+        return run_fast_check(runner, root, history, batch_size, args.nix_args);
+    }
+    if let Some(since) = &args.since {
+        let selection = affected_since(runner, root, config, since)?;
+        if !selection.all {
+            return build_affected(
+                runner,
+                root,
+                history,
+                &selection,
+                args.nix_args,
+                args.progress,
+            );
+        }
+        println!("everything is affected since {since}, running the full check");
+    }
+
+    let mut cmd_args = Vec::with_capacity(5 + args.nix_args.len());
+    cmd_args.push(oss("flake"));
+    cmd_args.push(oss("check"));
+    cmd_args.push(oss("--accept-flake-config"));
+    cmd_args.push(oss("--print-build-logs"));
+    if args.verbose {
+        cmd_args.push(oss("-L"));
+    }
+    if args.no_build {
+        cmd_args.push(oss("--no-build"));
+    }
+    if !args.allow_write_lock {
+        cmd_args.push(oss("--no-write-lock-file"));
+    }
+    cmd_args.extend(args.nix_args.into_iter().map(OsString::from));
+    cmd_args.push(OsString::from(root));
+
+    let cmd = Cmd::new("nix", cmd_args, root);
+    run_nix(
+        runner,
+        cmd,
+        args.progress,
+        history,
+        history::Command::Check,
+        "checks",
+    )
+}
+
+/// The checks, examples and packages affected by the changes since `since`
+fn affected_since(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    since: &str,
+) -> Result<affected::Selection> {
+    let mut map = affected::DepMap::load(root)?;
+    for (glob, targets) in config.affected_rules() {
+        map.declare(&glob, &targets)
+            .with_context(|| format!("invalid `affected.{glob}` rule"))?;
+    }
+    let files = affected::changed_files(runner, root, since)?;
+    Ok(affected::Selection::new(&map, files))
+}
+
+/// `one check --since`: build the affected checks (for both nixpkgs versions) and packages
+fn build_affected(
+    runner: &dyn Runner,
+    root: &Path,
+    history: &Recorder,
+    selection: &affected::Selection,
+    nix_args: Vec<String>,
+    progress: bool,
+) -> Result<()> {
+    let (checks, packages) = (selection.checks(), selection.packages());
+    if checks.is_empty() && packages.is_empty() {
+        println!("no checks or packages affected");
+        return Ok(());
+    }
+    let system = fast_check::current_system(runner, root, Output::Inherit)?;
+    // Some checks only exist on some systems, so only build what the flake provides here
+    let provided = check_names(runner, root, &system, &nix_args)?;
+    let mut attrs = Vec::new();
+    for check in checks {
+        let names: Vec<_> = ["nixpkgs", "nixpkgs-latest"]
+            .map(|prefix| format!("{prefix}-{check}"))
+            .into_iter()
+            .filter(|name| provided.as_ref().is_none_or(|p| p.contains(name)))
+            .collect();
+        if names.is_empty() {
+            println!("skipping check:{check} (not provided on {system})");
+        }
+        attrs.extend(names.iter().map(|name| format!("checks.{system}.{name}")));
+    }
+    if attrs.is_empty() && packages.is_empty() {
+        println!("no checks or packages affected on {system}");
+        return Ok(());
+    }
+
+    let mut cmd_args = vec![
+        oss("build"),
+        oss("--accept-flake-config"),
+        oss("--no-link"),
+        oss("--print-build-logs"),
+    ];
+    cmd_args.extend(nix_args.into_iter().map(OsString::from));
+    for attr in attrs {
+        cmd_args.push(build_installable(root, Some(&attr)));
+    }
+    for package in packages {
+        let attr = format!("packages.{system}.{package}");
+        cmd_args.push(build_installable(root, Some(&attr)));
+    }
+
+    let cmd = Cmd::new("nix", cmd_args, root);
+    run_nix(
+        runner,
+        cmd,
+        progress,
+        history,
+        history::Command::Check,
+        "affected",
+    )
+}
+
+/// Attribute names of `checks.<system>`, or `None` when nothing is actually run (`--dry-run`)
+fn check_names(
+    runner: &dyn Runner,
+    root: &Path,
+    system: &str,
+    nix_args: &[String],
+) -> Result<Option<BTreeSet<String>>> {
+    let mut args = vec![
+        oss("eval"),
+        oss("--accept-flake-config"),
+        oss("--json"),
+        build_installable(root, Some(&format!("checks.{system}"))),
+        oss("--apply"),
+        oss("builtins.attrNames"),
+    ];
+    args.extend(nix_args.iter().map(OsString::from));
+    let Some(stdout) = runner.capture(&Cmd::new("nix", args, root), Output::Inherit)? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&stdout)
+        .map(Some)
+        .with_context(|| format!("failed to parse the attribute names of checks.{system}"))
+}
+
+/// Run a nix command with its own output, or with `--log-format internal-json` rendered by
+/// [`progress`], and record it as `attr` in the build history
+fn run_nix(
+    runner: &dyn Runner,
+    mut cmd: Cmd,
+    progress: bool,
+    history: &Recorder,
+    command: history::Command,
+    attr: &str,
+) -> Result<()> {
+    let (started, since) = (Instant::now(), SystemTime::now());
+    let mut tracker = progress::Tracker::default();
+    let result = if progress {
+        // Right after the subcommand, ahead of the user's arguments
+        let at = cmd
+            .args
+            .len()
+            .min(if cmd.args[0] == "flake" { 2 } else { 1 });
+        cmd.args
+            .splice(at..at, progress::NIX_ARGS.map(OsString::from));
+        progress::run(runner, &cmd, &mut tracker)
+    } else {
+        runner.run(&cmd, Output::Inherit)
+    };
+
+    let out_paths = match (&result, command) {
+        (Ok(()), history::Command::Build) => history::fresh_out_links(&cmd.cwd, since),
+        _ => Vec::new(),
+    };
+    let tracker = progress.then_some(&tracker);
+    history.record_run(
+        command,
+        attr,
+        &result,
+        started.elapsed(),
+        tracker,
+        out_paths,
+    );
+    result
+}
+
+fn run_fast_check(
+    runner: &dyn Runner,
+    root: &Path,
+    history: &Recorder,
+    batch_size: usize,
+    nix_args: Vec<String>,
+) -> Result<()> {
+    runner::trap_interrupts();
+    let build_args: Vec<_> = nix_args.into_iter().map(OsString::from).collect();
+
+    let started = Instant::now();
+    let report = fast_check::run(
+        runner,
+        root.as_os_str(),
+        &[],
+        &build_args,
+        batch_size,
+        &root.join(fast_check::GC_ROOTS_DIR),
+        root,
+        Output::Inherit,
+    )?;
+    history.record_checks(&report, started.elapsed());
+    report.print(Output::Inherit)?;
+    report.into_result()
+}
+
+fn run_reported_check(
+    runner: &dyn Runner,
+    root: &Path,
+    history: &Recorder,
+    path: &Path,
+    nix_args: Vec<String>,
+    dry_run: bool,
+) -> Result<()> {
+    // Fail before spending an hour on the checks
+    let format = report::Format::from_path(path)?;
+    runner::trap_interrupts();
+    let build_args: Vec<_> = nix_args.into_iter().map(OsString::from).collect();
+
+    let started = Instant::now();
+    let report = report::run(
+        runner,
+        root.as_os_str(),
+        &build_args,
+        &root.join(fast_check::GC_ROOTS_DIR),
+        root,
+        Output::Inherit,
+    )?;
+    let summary = report.summary();
+    history.record_checks(&summary, started.elapsed());
+    summary.print(Output::Inherit)?;
+
+    if dry_run {
+        println!("would write {}", path.display());
+    } else {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, report.render(format)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        println!("wrote {}", path.display());
+    }
+    summary.into_result()
+}
+
+fn run_cache(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    command: cache::CacheCommand,
+) -> Result<()> {
+    let dir = |dir: Option<PathBuf>| {
+        root.join(dir.unwrap_or_else(|| {
+            PathBuf::from(
+                config
+                    .string("cache.dir")
+                    .unwrap_or_else(|| cache::DEFAULT_DIR.to_string()),
+            )
+        }))
+    };
+    let key_file = config
+        .string("cache.secret-key-file")
+        .map(|file| root.join(file));
+
+    match command {
+        cache::CacheCommand::Serve(args) => {
+            let listen = args
+                .listen
+                .or_else(|| config.string("cache.listen"))
+                .unwrap_or_else(|| cache::DEFAULT_LISTEN.to_string());
+            let public_key = key_file
+                .map(|file| cache::SecretKey::load(&file))
+                .transpose()?
+                .map(|key| key.public_key());
+            cache::serve(&dir(args.dir), &listen, public_key)
+        }
+        cache::CacheCommand::Push(args) => {
+            let key = args
+                .secret_key_file
+                .clone()
+                .or(key_file)
+                .map(|file| cache::SecretKey::load(&file))
+                .transpose()?;
+            cache::push(runner, root, &dir(args.dir.clone()), &args, key)
+        }
+    }
+}
+
+fn run_tests(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    history: &Recorder,
+    args: TestArgs,
+) -> Result<()> {
+    let TestArgs {
+        example,
+        all,
+        since,
+        only,
+        exclude,
+        jobs,
+        log_dir,
+        nixpkgs_from,
+        nix_args,
+    } = args;
+    let jobs = jobs
+        .or_else(|| config.usize("test.jobs"))
+        .unwrap_or(examples::DEFAULT_JOBS);
+    let nixpkgs_from = nixpkgs_from
+        .or_else(|| config.string("test.nixpkgs-from"))
+        .unwrap_or_else(|| DEFAULT_NIXPKGS_FROM.to_string());
+    let nix_args = config.nix_args("test", nix_args);
+
+    if all || since.is_some() {
+        let mut discovered = examples::discover(&root.join(DEFAULT_EXAMPLE_DIR))?;
+        if let Some(since) = &since {
+            let selection = affected_since(runner, root, config, since)?;
+            if !selection.all {
+                let affected = selection.examples();
+                discovered.retain(|example| affected.contains(&example.name.as_str()));
+                if discovered.is_empty() {
+                    println!("no examples affected since {since}");
+                    return Ok(());
+                }
+            }
+        }
+        let selected = examples::select(discovered, &only, &exclude);
+        if selected.is_empty() {
+            bail!("no examples match the given --only/--exclude filters");
+        }
+
+        let nixpkgs = flake_lock::resolve_pinned_input(root, &nixpkgs_from)?;
+        runner::trap_interrupts();
+        let outcomes =
+            examples::run_all(&selected, jobs, &root.join(log_dir), |example, output| {
+                example_check::check(runner, root, &example.path, &nixpkgs, &nix_args, output)
+            })?;
+        history.record_examples(&outcomes);
+        return examples::report(&outcomes);
+    }
+
+    if !only.is_empty() || !exclude.is_empty() {
+        bail!("--only and --exclude can only be used together with --all or --since");
+    }
+    let example = config.example(&example.expect("clap requires an example unless --all is set"));
+    let example_path = resolve_example_path(root, &example);
+    if !example_path.exists() {
+        return Err(anyhow!(
+            "example {:?} does not exist (resolved to {})",
+            example,
+            example_path.display()
+        ));
+    }
+
+    let nixpkgs = flake_lock::resolve_pinned_input(root, &nixpkgs_from)?;
+    println!("--- checking {}", example_path.display());
+    let started = Instant::now();
+    let result = example_check::check(
+        runner,
+        root,
+        &example_path,
+        &nixpkgs,
+        &nix_args,
+        Output::Inherit,
+    );
+    history.record(&[history.entry(
+        history::Command::Test,
+        history::Kind::Example,
+        &example,
+        history::outcome(&result),
+        Some(started.elapsed()),
+    )]);
+    result
+}
+
+fn run_watch(
+    runner: &dyn Runner,
+    root: &Path,
+    config: &Config,
+    history: &Recorder,
+    args: watch::WatchArgs,
+) -> Result<()> {
+    let debounce = match (args.debounce, config.string("watch.debounce")) {
+        (Some(debounce), _) => debounce,
+        (None, Some(configured)) => humantime::parse_duration(&configured)
+            .with_context(|| format!("invalid `watch.debounce` {configured:?}"))?,
+        (None, None) => watch::DEFAULT_DEBOUNCE,
+    };
+    let nix_args = args.nix_args;
+
+    if let Some(attr) = args.attr {
+        let filter = watch::Filter::new(root, None)?;
+        return watch::run(root, &filter, debounce, || {
+            let build = BuildArgs {
+                attribute: Some(attr.clone()),
+                progress: false,
+                nix_args: nix_args.clone(),
+            };
+            watch::Results::single(&attr, run_build(runner, root, config, history, build))
+        });
+    }
+
+    if let Some(example) = args.example {
+        let example_path = resolve_example_path(root, &config.example(&example));
+        let filter = watch::Filter::new(root, Some(&example_path))?;
+        return watch::run(root, &filter, debounce, || {
+            let test = TestArgs {
+                example: Some(example.clone()),
+                all: false,
+                since: None,
+                only: Vec::new(),
+                exclude: Vec::new(),
+                jobs: None,
+                log_dir: PathBuf::from(examples::DEFAULT_LOG_DIR),
+                nixpkgs_from: None,
+                nix_args: nix_args.clone(),
+            };
+            watch::Results::single(&example, run_tests(runner, root, config, history, test))
+        });
+    }
+
+    // Only the checks missing from the caches are rebuilt, as with `check --fast`
+    let batch_size = config
+        .usize("check.batch-size")
+        .unwrap_or(fast_check::DEFAULT_BATCH_SIZE);
+    let build_args: Vec<_> = config
+        .nix_args("check", nix_args)
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    let filter = watch::Filter::new(root, None)?;
+    watch::run(root, &filter, debounce, || {
+        let started = Instant::now();
+        let report = fast_check::run(
+            runner,
+            root.as_os_str(),
+            &[],
+            &build_args,
+            batch_size,
+            &root.join(fast_check::GC_ROOTS_DIR),
+            root,
+            Output::Inherit,
+        );
+        match report {
+            Ok(report) => {
+                history.record_checks(&report, started.elapsed());
+                watch::Results::from_report(&report)
+            }
+            Err(err) => watch::Results::single("checks", Err(err)),
+        }
+    })
+}
+
+fn run_develop(runner: &dyn Runner, root: &Path, config: &Config, args: DevelopArgs) -> Result<()> {
+    let attribute = args
+        .attribute
+        .or_else(|| config.string("develop.attribute"));
+    let installable = build_installable(root, attribute.as_deref());
+    let nix_args = config.nix_args("develop", args.nix_args);
+    let mut cmd_args = Vec::with_capacity(5 + nix_args.len() + args.command_args.len());
+    cmd_args.push(oss("develop"));
+    cmd_args.push(oss("--accept-flake-config"));
+    cmd_args.extend(nix_args.into_iter().map(OsString::from));
+    cmd_args.push(installable);
+    if let Some(command) = args.command {
+        cmd_args.push(oss("--command"));
+        cmd_args.push(command.into());
+        cmd_args.extend(args.command_args.into_iter().map(OsString::from));
+    }
+
+    runner.run(&Cmd::new("nix", cmd_args, root), Output::Inherit)
+}
+
+fn run_ci(runner: &dyn Runner, root: &Path, config: &Config, args: CiArgs) -> Result<()> {
+    let mut extra_args = config.strings("ci.extra-args");
+    extra_args.extend(args.extra_args);
+    let mut cmd_args = Vec::with_capacity(5 + extra_args.len());
+    cmd_args.push(oss("run"));
+    cmd_args.push(oss("--accept-flake-config"));
+    cmd_args.push(build_installable(root, Some("ci")));
+    if !extra_args.is_empty() {
+        cmd_args.push(oss("--"));
+        cmd_args.extend(extra_args.into_iter().map(OsString::from));
+    }
+
+    runner.run(&Cmd::new("nix", cmd_args, root), Output::Inherit)
+}
+
+fn build_installable(root: &Path, attribute: Option<&str>) -> OsString {
+    let mut installable = OsString::from(root);
+    if let Some(attr) = attribute {
+        installable.push("#");
+        installable.push(attr.trim_start_matches('#'));
+    }
+    installable
+}
+
+fn resolve_example_path(root: &Path, selector: &str) -> PathBuf {
+    let candidate = PathBuf::from(selector);
+    if candidate.is_absolute() {
+        return candidate;
+    }
+
+    if looks_like_relative_path(selector) {
+        return root.join(candidate);
+    }
+
+    root.join(DEFAULT_EXAMPLE_DIR).join(selector)
+}
+
+fn looks_like_relative_path(input: &str) -> bool {
+    if input.is_empty() {
+        return false;
+    }
+
+    let has_drive_prefix =
+        input.len() > 1 && input.as_bytes()[1] == b':' && input.as_bytes()[0].is_ascii_alphabetic();
+
+    has_drive_prefix
+        || input.starts_with(['.', '/', '\\'])
+        || (!input.contains(':')
+            && (input.contains('/')
+                || input.contains('\\')
+                || input.contains(std::path::MAIN_SEPARATOR)))
+}
+
+fn oss(value: impl Into<OsString>) -> OsString {
+    value.into()
+}
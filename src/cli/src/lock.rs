@@ -0,0 +1,610 @@
+//! `one lock`: inspect, diff and check a `flake.lock` (natively, without `nix flake metadata`).
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand, ValueHint};
+
+use crate::{
+    doctor::{self, Finding},
+    flake_lock::{FlakeLock, InputRef, Locked},
+    runner::{Cmd, Output, Runner},
+};
+
+/// Inputs not updated for longer than this are reported by `one lock check`
+pub const DEFAULT_MAX_AGE_DAYS: u64 = 90;
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Subcommand)]
+pub enum LockCommand {
+    /// Print the tree of inputs with their source, revision, date and narHash
+    Show(LockArgs),
+    /// Show which inputs changed between two revisions
+    Diff {
+        /// Old revision (any git ref)
+        #[arg(value_name = "REF")]
+        from: String,
+        /// New revision (any git ref) [default: the working tree]
+        #[arg(value_name = "REF")]
+        to: Option<String>,
+        #[command(flatten)]
+        lock: LockArgs,
+    },
+    /// Report unlocked and stale inputs, duplicate instances of the same source and nested
+    /// inputs that don't follow the root's
+    Check {
+        /// Days since the last update after which an input counts as stale
+        #[arg(long, value_name = "DAYS", default_value_t = DEFAULT_MAX_AGE_DAYS)]
+        max_age: u64,
+        #[command(flatten)]
+        lock: LockArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct LockArgs {
+    /// Directory containing the `flake.lock`, relative to the repository root [default: the
+    /// directory of `test.nixpkgs-from`, src/lang/rust/test]
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    flake: Option<PathBuf>,
+}
+
+/// An input reachable from the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// `follows` another input (`/`-separated path from the root)
+    Follows(String),
+    Node {
+        name: String,
+        locked: Option<Locked>,
+    },
+}
+
+/// Every input reachable from the root by its `/`-separated input path, in tree order
+pub fn flatten(lock: &FlakeLock) -> Result<Vec<(String, Input)>> {
+    let mut inputs = Vec::new();
+    walk(
+        lock,
+        &lock.root,
+        "",
+        &mut vec![lock.root.clone()],
+        &mut inputs,
+    )?;
+    Ok(inputs)
+}
+
+fn walk(
+    lock: &FlakeLock,
+    node: &str,
+    prefix: &str,
+    stack: &mut Vec<String>,
+    out: &mut Vec<(String, Input)>,
+) -> Result<()> {
+    for (input, reference) in &lock.node(node)?.inputs {
+        let path = format!("{prefix}{input}");
+        match reference {
+            InputRef::Follows(target) => out.push((path, Input::Follows(target.join("/")))),
+            InputRef::Node(name) => {
+                out.push((
+                    path.clone(),
+                    Input::Node {
+                        name: name.clone(),
+                        locked: lock.node(name)?.locked.clone(),
+                    },
+                ));
+                // Lock files are acyclic, but don't trust them blindly
+                if !stack.contains(name) {
+                    stack.push(name.clone());
+                    walk(lock, name, &format!("{path}/"), stack, out)?;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `2025-09-18`
+fn date(unix: i64) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(unix.max(0) as u64);
+    humantime::format_rfc3339_seconds(time).to_string()[..10].to_string()
+}
+
+fn age(unix: i64, now: i64) -> String {
+    match (now - unix) / DAY {
+        days if days <= 0 => "today".to_string(),
+        1 => "1 day old".to_string(),
+        days => format!("{days} days old"),
+    }
+}
+
+fn short_rev(rev: &str) -> &str {
+    &rev[..rev.len().min(12)]
+}
+
+/// `github:NixOS/nixpkgs/08b8f92ac635 (2025-09-16)`
+fn describe(locked: &Locked) -> String {
+    let mut text = locked.source();
+    if let Some(rev) = &locked.rev {
+        text.push('/');
+        text.push_str(short_rev(rev));
+    }
+    if let Some(modified) = locked.last_modified {
+        text.push_str(&format!(" ({})", date(modified)));
+    }
+    text
+}
+
+pub fn show(lock: &FlakeLock, now: i64) -> Result<String> {
+    let inputs = flatten(lock)?;
+    let mut out = String::new();
+    for (index, (path, input)) in inputs.iter().enumerate() {
+        let depth = path.matches('/').count();
+        let name = path.rsplit('/').next().unwrap_or(path);
+
+        // Whether each ancestor (and the input itself) is the last of its siblings
+        let mut prefix = String::new();
+        for level in 0..=depth {
+            let parent = path.splitn(level + 1, '/').take(level).collect::<Vec<_>>();
+            let has_later_sibling = inputs[index + 1..].iter().any(|(later, _)| {
+                let parts: Vec<_> = later.split('/').collect();
+                parts.len() == level + 1 && parts[..level] == parent[..]
+            });
+            prefix.push_str(match (level == depth, has_later_sibling) {
+                (true, true) => "├── ",
+                (true, false) => "└── ",
+                (false, true) => "│   ",
+                (false, false) => "    ",
+            });
+        }
+
+        let details = match input {
+            Input::Follows(target) => format!("follows {target}"),
+            Input::Node { locked: None, .. } => "not locked".to_string(),
+            Input::Node {
+                locked: Some(locked),
+                ..
+            } => {
+                let mut text = describe(locked);
+                if let Some(modified) = locked.last_modified {
+                    text = text.replacen(
+                        &format!("({})", date(modified)),
+                        &format!("({}, {})", date(modified), age(modified, now)),
+                        1,
+                    );
+                }
+                if let Some(hash) = &locked.nar_hash {
+                    text.push_str(&format!(" {hash}"));
+                }
+                text
+            }
+        };
+        out.push_str(&format!("{prefix}{name}: {details}\n"));
+    }
+    Ok(out)
+}
+
+/// One line per input that was added, removed or changed
+pub fn diff(old: &FlakeLock, new: &FlakeLock) -> Result<Vec<String>> {
+    let old_inputs: BTreeMap<_, _> = flatten(old)?.into_iter().collect();
+    let new_inputs = flatten(new)?;
+    let describe_input = |input: &Input| match input {
+        Input::Follows(target) => format!("follows {target}"),
+        Input::Node { locked: None, .. } => "not locked".to_string(),
+        Input::Node {
+            locked: Some(locked),
+            ..
+        } => describe(locked),
+    };
+
+    let mut lines = Vec::new();
+    for (path, input) in &new_inputs {
+        let Some(before) = old_inputs.get(path) else {
+            lines.push(format!("+ {path}: {}", describe_input(input)));
+            continue;
+        };
+        let change = match (before, input) {
+            (
+                Input::Node {
+                    locked: Some(a), ..
+                },
+                Input::Node {
+                    locked: Some(b), ..
+                },
+            ) if a.source() == b.source() => {
+                if a.rev != b.rev {
+                    let mut change = format!(
+                        "{}: {} -> {}",
+                        a.source(),
+                        describe(a)
+                            .trim_start_matches(&a.source())
+                            .trim_start_matches('/'),
+                        describe(b)
+                            .trim_start_matches(&b.source())
+                            .trim_start_matches('/'),
+                    );
+                    if let (Some(x), Some(y)) = (a.last_modified, b.last_modified) {
+                        let days = (y - x) / DAY;
+                        if days != 0 {
+                            let direction = if days > 0 { "newer" } else { "older" };
+                            change.push_str(&format!(", {} days {direction}", days.abs()));
+                        }
+                    }
+                    Some(change)
+                } else if a.nar_hash != b.nar_hash {
+                    Some("narHash changed (same revision)".to_string())
+                } else {
+                    None
+                }
+            }
+            (a, b) if a != b => Some(format!("{} -> {}", describe_input(a), describe_input(b))),
+            _ => None,
+        };
+        if let Some(change) = change {
+            lines.push(format!("~ {path}: {change}"));
+        }
+    }
+    let new_paths: Vec<_> = new_inputs.iter().map(|(path, _)| path).collect();
+    for (path, input) in &old_inputs {
+        if !new_paths.contains(&path) {
+            lines.push(format!("- {path}: {}", describe_input(input)));
+        }
+    }
+    Ok(lines)
+}
+
+pub fn check(lock: &FlakeLock, dir: &str, now: i64, max_age_days: u64) -> Result<Vec<Finding>> {
+    let inputs = flatten(lock)?;
+    let nodes: Vec<_> = inputs
+        .iter()
+        .filter_map(|(path, input)| match input {
+            Input::Node { name, locked } => Some((path, name, locked.as_ref())),
+            Input::Follows(_) => None,
+        })
+        .collect();
+    let mut findings = Vec::new();
+
+    let unlocked: Vec<_> = nodes
+        .iter()
+        .filter(|(_, _, locked)| {
+            locked.is_none_or(|l| matches!(l.kind.as_str(), "github" | "gitlab") && l.rev.is_none())
+        })
+        .map(|(path, _, _)| path.as_str())
+        .collect();
+    findings.push(if unlocked.is_empty() {
+        Finding::ok("locked", format!("{} inputs locked", nodes.len()))
+    } else {
+        Finding::fail(
+            "locked",
+            format!("not locked to a revision: {}", unlocked.join(", ")),
+            format!("run `nix flake lock {dir}`"),
+        )
+    });
+
+    let mut stale = false;
+    for (path, _, locked) in &nodes {
+        let Some(modified) = locked.and_then(|l| l.last_modified) else {
+            continue;
+        };
+        if (now - modified) / DAY <= max_age_days as i64 {
+            continue;
+        }
+        stale = true;
+        let top = path.split('/').next().unwrap_or(path);
+        let fix = if top == path.as_str() {
+            format!("nix flake update {top} --flake ./{dir}")
+        } else {
+            format!("update {top}, or make {path} follow one of your inputs")
+        };
+        findings.push(Finding::warn(
+            "stale",
+            format!(
+                "{path}: last updated {} ({})",
+                date(modified),
+                age(modified, now)
+            ),
+            fix,
+        ));
+    }
+    if !stale {
+        findings.push(Finding::ok(
+            "stale",
+            format!("every input was updated within {max_age_days} days"),
+        ));
+    }
+
+    // The same source locked by several nodes is evaluated (and downloaded) several times
+    let mut by_source: BTreeMap<String, Vec<(&String, &String, &Locked)>> = BTreeMap::new();
+    for (path, name, locked) in &nodes {
+        if let Some(locked) = locked {
+            by_source
+                .entry(locked.source())
+                .or_default()
+                .push((path, name, locked));
+        }
+    }
+    let mut duplicates = false;
+    for (source, instances) in &by_source {
+        let mut distinct: Vec<_> = instances.iter().map(|(_, name, _)| name).collect();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() < 2 {
+            continue;
+        }
+        duplicates = true;
+        let listed: Vec<_> = instances
+            .iter()
+            .map(|(path, _, locked)| {
+                format!(
+                    "{path} at {}",
+                    describe(locked)
+                        .trim_start_matches(source.as_str())
+                        .trim_start_matches('/')
+                )
+            })
+            .collect();
+        findings.push(Finding::warn(
+            "duplicates",
+            format!(
+                "{source} is locked {} times: {}",
+                distinct.len(),
+                listed.join(", ")
+            ),
+            "make nested inputs follow one of them, unless the versions are meant to differ",
+        ));
+    }
+    if !duplicates {
+        findings.push(Finding::ok("duplicates", "every source is locked once"));
+    }
+
+    // Nested inputs with their own copy of a source the root already has
+    let top_level: BTreeMap<String, (&String, &String)> = nodes
+        .iter()
+        .filter(|(path, _, _)| !path.contains('/'))
+        .filter_map(|(path, name, locked)| Some((locked.as_ref()?.source(), (*path, *name))))
+        .collect();
+    let mut not_following = false;
+    for (path, name, locked) in &nodes {
+        let Some((parent, input)) = path.rsplit_once('/') else {
+            continue;
+        };
+        let Some((root_input, root_node)) = locked.and_then(|l| top_level.get(&l.source())) else {
+            continue;
+        };
+        if root_node == name {
+            continue;
+        }
+        not_following = true;
+        let flake_attr = parent
+            .split('/')
+            .map(|p| format!("inputs.{p}"))
+            .collect::<Vec<_>>()
+            .join(".");
+        findings.push(Finding::warn(
+            "follows",
+            format!("{path} doesn't follow {root_input}"),
+            format!("add `{flake_attr}.inputs.{input}.follows = \"{root_input}\";` to flake.nix"),
+        ));
+    }
+    if !not_following {
+        findings.push(Finding::ok("follows", "nested inputs follow the root's"));
+    }
+    Ok(findings)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Run `command`; `default_dir` is used when `--flake` isn't given
+pub fn run(
+    runner: &dyn Runner,
+    root: &Path,
+    default_dir: &str,
+    command: LockCommand,
+) -> Result<()> {
+    let dir = |args: &LockArgs| -> String {
+        let dir = args
+            .flake
+            .as_ref()
+            .map(|d| d.display().to_string())
+            .unwrap_or_else(|| default_dir.to_string());
+        let dir = dir.trim_start_matches("./").trim_end_matches('/');
+        if dir.is_empty() { "." } else { dir }.to_string()
+    };
+
+    match command {
+        LockCommand::Show(args) => {
+            let dir = dir(&args);
+            let lock = FlakeLock::load(&root.join(&dir).join("flake.lock"))?;
+            println!("{dir}/flake.lock");
+            print!("{}", show(&lock, now())?);
+            Ok(())
+        }
+        LockCommand::Diff { from, to, lock } => {
+            let dir = dir(&lock);
+            let Some(old) = read_at(runner, root, &dir, Some(&from))? else {
+                return Ok(());
+            };
+            let Some(new) = read_at(runner, root, &dir, to.as_deref())? else {
+                return Ok(());
+            };
+            let lines = diff(&old, &new)?;
+            if lines.is_empty() {
+                println!("no input changes");
+            }
+            for line in lines {
+                println!("{line}");
+            }
+            Ok(())
+        }
+        LockCommand::Check { max_age, lock } => {
+            let dir = dir(&lock);
+            let path = root.join(&dir).join("flake.lock");
+            let lock = FlakeLock::load(&path)?;
+            doctor::report(&check(&lock, &dir, now(), max_age)?)
+        }
+    }
+}
+
+/// `<dir>/flake.lock` at `rev` (via `git show`), or in the working tree; `None` with
+/// `--dry-run`
+fn read_at(
+    runner: &dyn Runner,
+    root: &Path,
+    dir: &str,
+    rev: Option<&str>,
+) -> Result<Option<FlakeLock>> {
+    let file = if dir == "." {
+        "flake.lock".to_string()
+    } else {
+        format!("{dir}/flake.lock")
+    };
+    let Some(rev) = rev else {
+        return FlakeLock::load(&root.join(&file)).map(Some);
+    };
+
+    let args = vec![
+        OsString::from("show"),
+        OsString::from(format!("{rev}:{file}")),
+    ];
+    let Some(stdout) = runner.capture(&Cmd::new("git", args, root), Output::Inherit)? else {
+        return Ok(None);
+    };
+    let contents = String::from_utf8(stdout).context("git output is not valid UTF-8")?;
+    FlakeLock::parse(&contents)
+        .with_context(|| format!("failed to parse {file} at {rev}"))
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doctor::Level;
+
+    // 2025-10-18
+    const NOW: i64 = 1_760_745_600;
+
+    fn node(owner: &str, repo: &str, rev: &str, modified: i64) -> String {
+        format!(
+            r#"{{ "locked": {{ "type": "github", "owner": "{owner}", "repo": "{repo}", "rev": "{rev}",
+                "lastModified": {modified}, "narHash": "sha256-{rev}" }} }}"#
+        )
+    }
+
+    fn lock(fenix_rev: &str, fenix_follows: bool, extra: &str) -> FlakeLock {
+        let fenix_nixpkgs = if fenix_follows {
+            r#"["nixpkgs"]"#
+        } else {
+            r#""nixpkgs_2""#
+        };
+        FlakeLock::parse(&format!(
+            r#"{{
+              "nodes": {{
+                "fenix": {{
+                  "inputs": {{ "nixpkgs": {fenix_nixpkgs}, "rust-analyzer-src": "rust-analyzer-src" }},
+                  "locked": {{ "type": "github", "owner": "nix-community", "repo": "fenix",
+                    "rev": "{fenix_rev}", "lastModified": {}, "narHash": "sha256-x" }}
+                }},
+                "nixpkgs": {},
+                "nixpkgs_2": {},
+                "rust-analyzer-src": {},
+                "root": {{ "inputs": {{ "fenix": "fenix", "nixpkgs": "nixpkgs"{extra} }} }}
+              }},
+              "root": "root",
+              "version": 7
+            }}"#,
+            if fenix_rev == "bbbb" { NOW - DAY } else { NOW - 11 * DAY },
+            node("NixOS", "nixpkgs", "08b8f92ac6354983", NOW - 2 * DAY),
+            node("NixOS", "nixpkgs", "e9b7f2ff62b35f71", NOW - 300 * DAY),
+            node("rust-lang", "rust-analyzer", "9edc9cb", NOW - 40 * DAY),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn shows_the_input_tree() {
+        assert_eq!(
+            show(&lock("aaaa", true, ""), NOW).unwrap(),
+            "\
+├── fenix: github:nix-community/fenix/aaaa (2025-10-07, 11 days old) sha256-x
+│   ├── nixpkgs: follows nixpkgs
+│   └── rust-analyzer-src: github:rust-lang/rust-analyzer/9edc9cb (2025-09-08, 40 days old) sha256-9edc9cb
+└── nixpkgs: github:NixOS/nixpkgs/08b8f92ac635 (2025-10-16, 2 days old) sha256-08b8f92ac6354983
+"
+        );
+    }
+
+    #[test]
+    fn diffs_inputs() {
+        let old = lock("aaaa", true, "");
+        let new = lock("bbbb", false, r#", "extra": "nixpkgs_2""#);
+        assert_eq!(
+            diff(&old, &new).unwrap(),
+            [
+                "+ extra: github:NixOS/nixpkgs/e9b7f2ff62b3 (2024-12-22)",
+                "~ fenix: github:nix-community/fenix: aaaa (2025-10-07) -> bbbb (2025-10-17), 10 days newer",
+                "~ fenix/nixpkgs: follows nixpkgs -> github:NixOS/nixpkgs/e9b7f2ff62b3 (2024-12-22)",
+            ]
+        );
+        assert!(diff(&old, &old).unwrap().is_empty());
+        assert_eq!(
+            diff(&new, &old).unwrap().last().unwrap(),
+            "- extra: github:NixOS/nixpkgs/e9b7f2ff62b3 (2024-12-22)"
+        );
+    }
+
+    #[test]
+    fn checks_staleness_duplicates_and_follows() {
+        let findings = check(&lock("aaaa", false, ""), "src/lang/rust/test", NOW, 30).unwrap();
+        let problems: Vec<_> = findings
+            .iter()
+            .filter(|f| f.level != Level::Ok)
+            .map(|f| format!("{} {}", f.check, f.message))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "stale fenix/nixpkgs: last updated 2024-12-22 (300 days old)",
+                "stale fenix/rust-analyzer-src: last updated 2025-09-08 (40 days old)",
+                "duplicates github:NixOS/nixpkgs is locked 2 times: fenix/nixpkgs at e9b7f2ff62b3 (2024-12-22), nixpkgs at 08b8f92ac635 (2025-10-16)",
+                "follows fenix/nixpkgs doesn't follow nixpkgs",
+            ]
+        );
+        let follows = findings.iter().find(|f| f.check == "follows").unwrap();
+        assert_eq!(
+            follows.fix.as_deref(),
+            Some("add `inputs.fenix.inputs.nixpkgs.follows = \"nixpkgs\";` to flake.nix")
+        );
+
+        let findings = check(&lock("aaaa", true, ""), ".", NOW, 60).unwrap();
+        assert!(
+            findings.iter().all(|f| f.level == Level::Ok),
+            "{findings:?}"
+        );
+    }
+
+    #[test]
+    fn repository_lock_is_readable() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let lock = FlakeLock::load(&root.join("src/lang/rust/test/flake.lock")).unwrap();
+        let tree = show(&lock, NOW).unwrap();
+        assert!(
+            tree.contains("│   ├── nixpkgs: follows nixpkgs\n"),
+            "{tree}"
+        );
+        let findings = check(&lock, "src/lang/rust/test", NOW, 3650).unwrap();
+        assert!(
+            findings.iter().all(|f| f.level != Level::Fail),
+            "{findings:?}"
+        );
+    }
+}
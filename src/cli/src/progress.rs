@@ -0,0 +1,640 @@
+//! `--progress`: run nix with `--log-format internal-json` and render our own progress.
+//!
+//! nix writes one `@nix {...}` JSON message per stderr line. [`Tracker`] turns them into
+//! [`Event`]s (builds starting and finishing, downloads, failures with the tail of their log)
+//! and [`Renderer`] prints those, plus a live status line when stderr is a terminal.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    examples::format_duration,
+    runner::{Cmd, Output, Runner},
+};
+
+/// Log lines kept per build and shown when it fails
+pub const LOG_TAIL_LINES: usize = 20;
+
+/// Arguments that switch nix to the format parsed here
+pub const NIX_ARGS: [&str; 2] = ["--log-format", "internal-json"];
+
+// Activity and result types from nix's `logging.hh`
+const ACT_BUILDS: u64 = 104;
+const ACT_BUILD: u64 = 105;
+const ACT_SUBSTITUTE: u64 = 108;
+const RES_BUILD_LOG_LINE: u64 = 101;
+const RES_PROGRESS: u64 = 105;
+
+const LVL_ERROR: u64 = 0;
+
+/// One `@nix` line
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Message {
+    Start {
+        id: u64,
+        #[serde(rename = "type")]
+        kind: u64,
+        #[serde(default)]
+        fields: Vec<Field>,
+    },
+    Stop {
+        id: u64,
+    },
+    Result {
+        id: u64,
+        #[serde(rename = "type")]
+        kind: u64,
+        #[serde(default)]
+        fields: Vec<Field>,
+    },
+    Msg {
+        level: u64,
+        msg: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Field {
+    Int(u64),
+    String(String),
+}
+
+impl Field {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Field::String(s) => Some(s),
+            Field::Int(_) => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<u64> {
+        match self {
+            Field::Int(n) => Some(*n),
+            Field::String(_) => None,
+        }
+    }
+}
+
+impl Message {
+    /// Parse a stderr line; `None` for lines that aren't internal-json (printed as they are)
+    pub fn parse(line: &str) -> Option<Result<Self>> {
+        let json = line.strip_prefix("@nix ")?;
+        Some(
+            serde_json::from_str(json)
+                .with_context(|| format!("failed to parse nix log message: {json}")),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Building(String),
+    Finished { name: String, duration: Duration },
+    Downloaded(String),
+    Failed { name: String, log: Vec<String> },
+    Message(String),
+}
+
+struct Build {
+    name: String,
+    drv: String,
+    started: Instant,
+    log: VecDeque<String>,
+}
+
+/// A build that stopped, successfully or not
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finished {
+    pub name: String,
+    pub drv: String,
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+/// State of a nix invocation, fed one stderr line at a time
+#[derive(Default)]
+pub struct Tracker {
+    active: HashMap<u64, Build>,
+    /// Active builds in start order, for the status line
+    order: Vec<u64>,
+    substituting: HashMap<u64, String>,
+    /// Log tails of stopped builds, in case nix reports them as failed afterwards
+    tails: HashMap<String, Vec<String>>,
+    pub finished: Vec<Finished>,
+    pub downloaded: usize,
+    builds_activity: Option<u64>,
+    /// `done`/`expected` builds as counted by nix itself
+    builds: Option<(u64, u64)>,
+}
+
+impl Tracker {
+    pub fn handle_line(&mut self, line: &str, now: Instant) -> Result<Vec<Event>> {
+        match Message::parse(line) {
+            None => Ok(vec![Event::Message(line.to_string())]),
+            Some(message) => Ok(self.handle(message?, now)),
+        }
+    }
+
+    pub fn handle(&mut self, message: Message, now: Instant) -> Vec<Event> {
+        match message {
+            Message::Start { id, kind, fields } => {
+                let path = fields.first().and_then(Field::as_str).unwrap_or_default();
+                match kind {
+                    ACT_BUILD => {
+                        let name = store_name(path).to_string();
+                        self.active.insert(
+                            id,
+                            Build {
+                                name: name.clone(),
+                                drv: path.to_string(),
+                                started: now,
+                                log: VecDeque::new(),
+                            },
+                        );
+                        self.order.push(id);
+                        vec![Event::Building(name)]
+                    }
+                    ACT_BUILDS => {
+                        self.builds_activity = Some(id);
+                        vec![]
+                    }
+                    ACT_SUBSTITUTE => {
+                        self.substituting.insert(id, store_name(path).to_string());
+                        vec![]
+                    }
+                    _ => vec![],
+                }
+            }
+            Message::Stop { id } => {
+                if let Some(name) = self.substituting.remove(&id) {
+                    self.downloaded += 1;
+                    return vec![Event::Downloaded(name)];
+                }
+                let Some(build) = self.stop(id) else {
+                    return vec![];
+                };
+                let duration = now.saturating_duration_since(build.started);
+                self.tails.insert(build.drv.clone(), build.log.into());
+                self.finished.push(Finished {
+                    name: build.name.clone(),
+                    drv: build.drv,
+                    duration,
+                    failed: false,
+                });
+                vec![Event::Finished {
+                    name: build.name,
+                    duration,
+                }]
+            }
+            Message::Result { id, kind, fields } => {
+                match kind {
+                    RES_BUILD_LOG_LINE => {
+                        let line = fields.first().and_then(Field::as_str);
+                        if let (Some(build), Some(line)) = (self.active.get_mut(&id), line) {
+                            if build.log.len() == LOG_TAIL_LINES {
+                                build.log.pop_front();
+                            }
+                            build.log.push_back(line.to_string());
+                        }
+                    }
+                    // `[done, expected, running, failed]` of the overall `Builds` activity
+                    RES_PROGRESS if self.builds_activity == Some(id) => {
+                        let count = |i: usize| fields.get(i).and_then(Field::as_int);
+                        if let (Some(done), Some(expected)) = (count(0), count(1)) {
+                            self.builds = Some((done, expected));
+                        }
+                    }
+                    _ => {}
+                }
+                vec![]
+            }
+            Message::Msg { level, msg } => {
+                let text = strip_ansi(&msg);
+                match (level, failed_drv(&text)) {
+                    (LVL_ERROR, Some(drv)) => vec![self.fail(drv, now)],
+                    _ => vec![Event::Message(msg)],
+                }
+            }
+            Message::Other => vec![],
+        }
+    }
+
+    fn stop(&mut self, id: u64) -> Option<Build> {
+        self.order.retain(|&active| active != id);
+        self.active.remove(&id)
+    }
+
+    fn fail(&mut self, drv: &str, now: Instant) -> Event {
+        // Usually the build already stopped; otherwise stop it here
+        let running = self
+            .active
+            .iter()
+            .find(|(_, build)| build.drv == drv)
+            .map(|(&id, _)| id);
+        if let Some(build) = running.and_then(|id| self.stop(id)) {
+            self.tails.insert(build.drv.clone(), build.log.into());
+            self.finished.push(Finished {
+                name: build.name,
+                drv: build.drv,
+                duration: now.saturating_duration_since(build.started),
+                failed: false,
+            });
+        }
+
+        let name = store_name(drv).to_string();
+        if let Some(finished) = self.finished.iter_mut().find(|f| f.drv == drv) {
+            finished.failed = true;
+        } else {
+            // Failed before we saw it start (e.g. a dependency of a remote build)
+            self.finished.push(Finished {
+                name: name.clone(),
+                drv: drv.to_string(),
+                duration: Duration::ZERO,
+                failed: true,
+            });
+        }
+        Event::Failed {
+            name,
+            log: self.tails.get(drv).cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn failed(&self) -> usize {
+        self.finished.iter().filter(|f| f.failed).count()
+    }
+
+    /// `[2/5 built, 3 downloaded] building cargo-test (12s), cargo-doc (3s)`
+    pub fn status(&self, now: Instant) -> String {
+        let (done, expected) = self.builds.unwrap_or((
+            (self.finished.len() - self.failed()) as u64,
+            (self.finished.len() + self.active.len()) as u64,
+        ));
+        let mut status = format!("[{done}/{expected} built, {} downloaded", self.downloaded);
+        if self.failed() > 0 {
+            status.push_str(&format!(", {} failed", self.failed()));
+        }
+        status.push(']');
+
+        let running: Vec<_> = self
+            .order
+            .iter()
+            .filter_map(|id| self.active.get(id))
+            .map(|b| {
+                let secs = now.saturating_duration_since(b.started).as_secs();
+                format!("{} ({secs}s)", b.name)
+            })
+            .collect();
+        if !running.is_empty() {
+            status.push_str(" building ");
+            status.push_str(&running.join(", "));
+        }
+        status
+    }
+}
+
+/// Prints [`Event`]s as plain lines, and on a terminal also keeps a status line at the bottom
+pub struct Renderer<W> {
+    out: W,
+    tty: bool,
+    status: String,
+    redrawn: Option<Instant>,
+}
+
+impl<W: Write> Renderer<W> {
+    pub fn new(out: W, tty: bool) -> Self {
+        Self {
+            out,
+            tty,
+            status: String::new(),
+            redrawn: None,
+        }
+    }
+
+    pub fn event(&mut self, event: &Event) -> Result<()> {
+        self.clear_status()?;
+        match event {
+            Event::Building(name) => writeln!(self.out, "building {name}"),
+            Event::Finished { name, duration } => {
+                writeln!(self.out, "finished {name} ({})", format_duration(*duration))
+            }
+            Event::Downloaded(name) => writeln!(self.out, "downloaded {name}"),
+            Event::Failed { name, log } => {
+                writeln!(self.out, "FAILED {name}")?;
+                log.iter()
+                    .try_for_each(|line| writeln!(self.out, "  > {}", self.text(line)))
+            }
+            Event::Message(msg) => writeln!(self.out, "{}", self.text(msg)),
+        }
+        .context("failed to write progress")?;
+        self.draw_status()
+    }
+
+    /// Update the status line (terminals only) when it changed, at most every 100ms
+    pub fn status(&mut self, status: String, now: Instant) -> Result<()> {
+        if !self.tty {
+            return Ok(());
+        }
+        let stale = self
+            .redrawn
+            .is_none_or(|at| now.saturating_duration_since(at) >= Duration::from_millis(100));
+        if status != self.status && stale {
+            self.clear_status()?;
+            self.status = status;
+            self.redrawn = Some(now);
+            self.draw_status()?;
+        }
+        Ok(())
+    }
+
+    /// Drop the status line and print the per-derivation summary
+    pub fn finish(&mut self, tracker: &Tracker) -> Result<()> {
+        self.clear_status()?;
+        self.status.clear();
+        if tracker.finished.is_empty() && tracker.downloaded == 0 {
+            return Ok(());
+        }
+
+        let width = tracker
+            .finished
+            .iter()
+            .map(|f| f.name.len())
+            .max()
+            .unwrap_or(0);
+        let mut summary = String::from("\n");
+        for build in &tracker.finished {
+            let result = if build.failed { "FAILED" } else { "built" };
+            summary.push_str(&format!(
+                "{:width$}  {result:6}  {:>8}\n",
+                build.name,
+                format_duration(build.duration)
+            ));
+        }
+        summary.push_str(&format!(
+            "{} built, {} downloaded, {} failed\n",
+            tracker.finished.len() - tracker.failed(),
+            tracker.downloaded,
+            tracker.failed()
+        ));
+        self.out
+            .write_all(summary.as_bytes())
+            .context("failed to write progress")
+    }
+
+    fn text(&self, msg: &str) -> String {
+        if self.tty {
+            msg.to_string()
+        } else {
+            strip_ansi(msg)
+        }
+    }
+
+    fn clear_status(&mut self) -> Result<()> {
+        if self.tty && !self.status.is_empty() {
+            write!(self.out, "\r\x1b[K").context("failed to write progress")?;
+        }
+        Ok(())
+    }
+
+    fn draw_status(&mut self) -> Result<()> {
+        if self.tty && !self.status.is_empty() {
+            write!(self.out, "{}", self.status).context("failed to write progress")?;
+            self.out.flush().context("failed to write progress")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `cmd` (which must include [`NIX_ARGS`]) and render its progress on stderr
+pub fn run(runner: &dyn Runner, cmd: &Cmd, tracker: &mut Tracker) -> Result<()> {
+    let stderr = io::stderr();
+    let tty = stderr.is_terminal();
+    let mut renderer = Renderer::new(stderr.lock(), tty);
+
+    let result = runner.stream_stderr(cmd, Output::Inherit, &mut |line| {
+        let now = Instant::now();
+        for event in tracker.handle_line(line, now)? {
+            renderer.event(&event)?;
+        }
+        renderer.status(tracker.status(now), now)
+    });
+    renderer.finish(tracker)?;
+    result
+}
+
+/// `/nix/store/<hash>-cargo-test.drv` -> `cargo-test`
+fn store_name(path: &str) -> &str {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    let name = match base.split_once('-') {
+        Some((hash, name)) if hash.len() == 32 => name,
+        _ => base,
+    };
+    name.strip_suffix(".drv").unwrap_or(name)
+}
+
+/// The derivation named by a build failure (`builder for '<drv>' failed`, or
+/// `Cannot build '<drv>'` since nix 2.20)
+fn failed_drv(msg: &str) -> Option<&str> {
+    ["builder for '", "Cannot build '"]
+        .iter()
+        .find_map(|prefix| {
+            let start = msg.find(prefix)? + prefix.len();
+            let len = msg[start..].find('\'')?;
+            Some(&msg[start..start + len])
+        })
+}
+
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `nix build --log-format internal-json` of a flake with one failing check
+    const CAPTURED: &str = include_str!("../testdata/internal-json.log");
+
+    /// Feed the capture with a clock advancing one second per line
+    fn replay() -> (Tracker, Vec<Event>) {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        let mut events = Vec::new();
+        for (i, line) in CAPTURED.lines().enumerate() {
+            let now = start + Duration::from_secs(i as u64);
+            events.extend(tracker.handle_line(line, now).unwrap());
+        }
+        (tracker, events)
+    }
+
+    #[test]
+    fn parses_messages() {
+        assert!(Message::parse("building...").is_none());
+        let line = r#"@nix {"action":"result","fields":[1,3,0,0],"id":2,"type":105}"#;
+        assert_eq!(
+            Message::parse(line).unwrap().unwrap(),
+            Message::Result {
+                id: 2,
+                kind: RES_PROGRESS,
+                fields: vec![Field::Int(1), Field::Int(3), Field::Int(0), Field::Int(0)],
+            }
+        );
+        let line = r#"@nix {"action":"setPhase","id":2}"#;
+        assert_eq!(Message::parse(line).unwrap().unwrap(), Message::Other);
+        assert!(Message::parse("@nix {").unwrap().is_err());
+    }
+
+    #[test]
+    fn tracks_builds_downloads_and_failures() {
+        let (tracker, events) = replay();
+        assert_eq!(
+            events,
+            [
+                Event::Message(
+                    "\u{1b}[35;1mwarning:\u{1b}[0m Git tree '/home/dev/one-for-all' is dirty"
+                        .into()
+                ),
+                Event::Downloaded("rustc-1.79.0".into()),
+                Event::Building("cargo-deps-0.1.0".into()),
+                Event::Finished {
+                    name: "cargo-deps-0.1.0".into(),
+                    duration: Duration::from_secs(5)
+                },
+                Event::Building("cargo-test".into()),
+                Event::Building("cargo-doc".into()),
+                Event::Finished {
+                    name: "cargo-test".into(),
+                    duration: Duration::from_secs(9)
+                },
+                Event::Failed {
+                    name: "cargo-test".into(),
+                    log: vec![
+                        "running 2 tests".into(),
+                        "test tests::parses ... ok".into(),
+                        "test tests::renders ... FAILED".into(),
+                        "test result: FAILED. 1 passed; 1 failed".into(),
+                    ]
+                },
+                Event::Finished {
+                    name: "cargo-doc".into(),
+                    duration: Duration::from_secs(11)
+                },
+            ]
+        );
+        assert_eq!(tracker.downloaded, 1);
+        assert_eq!(tracker.failed(), 1);
+        assert_eq!(tracker.builds, Some((2, 3)));
+    }
+
+    #[test]
+    fn status_line_lists_running_builds() {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        for (i, line) in CAPTURED.lines().take(20).enumerate() {
+            tracker
+                .handle_line(line, start + Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        assert_eq!(
+            tracker.status(start + Duration::from_secs(30)),
+            "[1/3 built, 1 downloaded] building cargo-test (13s), cargo-doc (12s)"
+        );
+    }
+
+    #[test]
+    fn log_tail_is_bounded() {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        let start_build = r#"@nix {"action":"start","fields":["/nix/store/3mq1r7h5qk3v9yd0w6a9cgkmr7m8rwl5-x.drv","",1,1],"id":7,"level":3,"parent":0,"text":"","type":105}"#;
+        tracker.handle_line(start_build, start).unwrap();
+        for i in 0..LOG_TAIL_LINES + 5 {
+            let line =
+                format!(r#"@nix {{"action":"result","fields":["line {i}"],"id":7,"type":101}}"#);
+            tracker.handle_line(&line, start).unwrap();
+        }
+        let events = tracker.handle(
+            Message::Msg {
+                level: LVL_ERROR,
+                msg: "error: Cannot build '/nix/store/3mq1r7h5qk3v9yd0w6a9cgkmr7m8rwl5-x.drv'."
+                    .into(),
+            },
+            start,
+        );
+        let [Event::Failed { name, log }] = events.as_slice() else {
+            panic!("{events:?}");
+        };
+        assert_eq!(name, "x");
+        assert_eq!(log.len(), LOG_TAIL_LINES);
+        assert_eq!(log[0], "line 5");
+    }
+
+    #[test]
+    fn plain_rendering_has_no_escapes() {
+        let (tracker, events) = replay();
+        let mut out = Vec::new();
+        let mut renderer = Renderer::new(&mut out, false);
+        for event in &events {
+            renderer.event(event).unwrap();
+        }
+        renderer
+            .status(tracker.status(Instant::now()), Instant::now())
+            .unwrap();
+        renderer.finish(&tracker).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains('\x1b'), "{out}");
+        assert!(out.starts_with("warning: Git tree '/home/dev/one-for-all' is dirty\n"));
+        assert!(
+            out.contains("FAILED cargo-test\n  > running 2 tests\n"),
+            "{out}"
+        );
+        assert!(
+            out.ends_with(
+                "cargo-deps-0.1.0  built       5.0s\n\
+             cargo-test        FAILED      9.0s\n\
+             cargo-doc         built      11.0s\n\
+             2 built, 1 downloaded, 1 failed\n"
+            ),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn tty_rendering_redraws_the_status_line() {
+        let mut out = Vec::new();
+        let mut renderer = Renderer::new(&mut out, true);
+        let now = Instant::now();
+        renderer
+            .status("[0/1 built, 0 downloaded]".into(), now)
+            .unwrap();
+        renderer.event(&Event::Building("x".into())).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "[0/1 built, 0 downloaded]\r\x1b[Kbuilding x\n[0/1 built, 0 downloaded]"
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! `one check --report`: build the flake's checks one at a time and write a JUnit XML or JSON
+//! report with the outcome and duration of each, for CI dashboards.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Write as _,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    fast_check::{self, EvalJob, Status},
+    runner::{self, Cmd, Output, Runner},
+};
+
+/// Lines of a failing check's build log attached to its case
+pub const LOG_TAIL_LINES: usize = 50;
+
+/// Prefixes `checks` are generated with in `flake.nix` (`mkChecksFor`), most specific first
+const CHECK_SETS: [&str; 2] = ["nixpkgs-latest", "nixpkgs"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Junit,
+    Json,
+}
+
+impl Format {
+    /// `.xml` is JUnit, `.json` is JSON
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("xml") => Ok(Format::Junit),
+            Some("json") => Ok(Format::Json),
+            _ => bail!(
+                "unknown report format for {}: use a .xml (JUnit) or .json file",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// The outcome of one check
+#[derive(Debug, Serialize)]
+pub struct Case {
+    /// Attribute under `checks.<system>`, e.g. `nixpkgs-latest-clippy`
+    pub attr: String,
+    /// The nixpkgs the check is built with (`nixpkgs`, `nixpkgs-latest`), or `checks`
+    pub suite: String,
+    /// `attr` without the suite prefix
+    pub name: String,
+    #[serde(serialize_with = "status_name")]
+    pub status: Status,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drv_path: Option<String>,
+    /// The evaluation error, or the tail of the build log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+}
+
+impl Case {
+    fn new(attr: String, status: Status, duration: Duration) -> Self {
+        let (suite, name) = split_attr(&attr);
+        Self {
+            suite: suite.to_string(),
+            name: name.to_string(),
+            attr,
+            status,
+            duration,
+            drv_path: None,
+            log: None,
+        }
+    }
+}
+
+fn status_name<S: Serializer>(status: &Status, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match status {
+        Status::Cached => "cached",
+        Status::Built => "passed",
+        Status::Failed | Status::EvalFailed(_) => "failed",
+    })
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+/// `nixpkgs-latest-clippy` -> (`nixpkgs-latest`, `clippy`); unprefixed checks are in `checks`
+pub fn split_attr(attr: &str) -> (&str, &str) {
+    CHECK_SETS
+        .iter()
+        .find_map(|set| Some((*set, attr.strip_prefix(set)?.strip_prefix('-')?)))
+        .unwrap_or(("checks", attr))
+}
+
+/// Every check of a flake, in evaluation order
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub system: String,
+    pub checks: Vec<Case>,
+}
+
+impl CheckReport {
+    /// The summary `one check --fast` prints, also used for history
+    pub fn summary(&self) -> fast_check::Report {
+        fast_check::Report {
+            checks: self
+                .checks
+                .iter()
+                .map(|case| (case.attr.clone(), case.status.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn render(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Junit => Ok(self.junit()),
+            Format::Json => serde_json::to_string_pretty(self)
+                .map(|json| json + "\n")
+                .context("failed to serialize the report"),
+        }
+    }
+
+    fn junit(&self) -> String {
+        let seconds = |d: Duration| format!("{:.3}", d.as_secs_f64());
+        let failures = |cases: &[&Case]| {
+            cases
+                .iter()
+                .filter(|c| matches!(c.status, Status::Failed | Status::EvalFailed(_)))
+                .count()
+        };
+        let all: Vec<_> = self.checks.iter().collect();
+        let total: Duration = all.iter().map(|c| c.duration).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites name=\"checks.{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">",
+            escape(&self.system),
+            all.len(),
+            failures(&all),
+            seconds(total)
+        );
+        let mut suites: Vec<&str> = Vec::new();
+        for case in &self.checks {
+            if !suites.contains(&case.suite.as_str()) {
+                suites.push(&case.suite);
+            }
+        }
+        for suite in suites {
+            let cases: Vec<_> = all.iter().copied().filter(|c| c.suite == suite).collect();
+            let _ = writeln!(
+                xml,
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">",
+                escape(suite),
+                cases.len(),
+                failures(&cases),
+                seconds(cases.iter().map(|c| c.duration).sum())
+            );
+            for case in cases {
+                let _ = write!(
+                    xml,
+                    "    <testcase name=\"{}\" classname=\"checks.{}.{}\" time=\"{}\"",
+                    escape(&case.name),
+                    escape(&self.system),
+                    escape(suite),
+                    seconds(case.duration)
+                );
+                let failure = match &case.status {
+                    Status::Built => {
+                        xml.push_str("/>\n");
+                        continue;
+                    }
+                    Status::Cached => {
+                        xml.push_str(
+                            ">\n      <properties>\n        \
+                             <property name=\"cached\" value=\"true\"/>\n      \
+                             </properties>\n    </testcase>\n",
+                        );
+                        continue;
+                    }
+                    Status::Failed => "build failed",
+                    Status::EvalFailed(_) => "evaluation failed",
+                };
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"{failure}\">{}</failure>\n    </testcase>",
+                    escape(case.log.as_deref().unwrap_or(""))
+                );
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+/// Escape text for XML, dropping the control characters XML 1.0 can't contain
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The last `lines` lines of `log`, without terminal colors
+pub fn tail(log: &str, lines: usize) -> String {
+    let mut plain = String::with_capacity(log.len());
+    let mut chars = log.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+            continue;
+        }
+        // CSI sequences (`ESC [ ... final`), the only ones nix emits
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    let all: Vec<_> = plain.trim_end().lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+/// Evaluate the checks of `flake` and build the uncached ones one at a time
+pub fn run(
+    runner: &dyn Runner,
+    flake: &OsStr,
+    build_args: &[OsString],
+    gc_roots_dir: &Path,
+    cwd: &Path,
+    output: Output,
+) -> Result<CheckReport> {
+    let system = fast_check::current_system(runner, cwd, output)?;
+    let eval_jobs = fast_check::eval_jobs_cmd(flake, &system, &[], gc_roots_dir, cwd);
+
+    let mut jobs = Vec::new();
+    runner.stream(&eval_jobs, output, &mut |line| {
+        if !line.trim().is_empty() {
+            jobs.push(EvalJob::parse(line)?);
+        }
+        Ok(())
+    })?;
+
+    let mut checks = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        if let Some(error) = job.error {
+            let mut case = Case::new(job.attr, Status::EvalFailed(error.clone()), Duration::ZERO);
+            case.log = Some(tail(&error, LOG_TAIL_LINES));
+            checks.push(case);
+            continue;
+        }
+        let Some(drv_path) = job.drv_path else {
+            bail!("nix-eval-jobs returned no derivation for {}", job.attr);
+        };
+        if job.is_cached {
+            let mut case = Case::new(job.attr, Status::Cached, Duration::ZERO);
+            case.drv_path = Some(drv_path);
+            checks.push(case);
+            continue;
+        }
+
+        let mut args = vec![
+            OsString::from("build"),
+            OsString::from("--no-link"),
+            OsString::from("--print-build-logs"),
+            OsString::from(format!("{drv_path}^*")),
+        ];
+        args.extend(build_args.iter().cloned());
+        let started = Instant::now();
+        let built = runner.run(&Cmd::new("nix", args, cwd), output);
+        let duration = started.elapsed();
+
+        let mut case = match built {
+            Ok(()) => Case::new(job.attr, Status::Built, duration),
+            Err(err) => {
+                runner::check_interrupted()?;
+                output.println(&format!("build failed: {err:#}"))?;
+                let mut case = Case::new(job.attr, Status::Failed, duration);
+                case.log = Some(build_log(runner, &drv_path, cwd, output));
+                case
+            }
+        };
+        case.drv_path = Some(drv_path);
+        checks.push(case);
+    }
+    Ok(CheckReport { system, checks })
+}
+
+/// The tail of `nix log <drv>`; the log may be missing (e.g. a dependency failed instead)
+fn build_log(runner: &dyn Runner, drv_path: &str, cwd: &Path, output: Output) -> String {
+    let args = vec![OsString::from("log"), OsString::from(drv_path)];
+    match runner.capture(&Cmd::new("nix", args, cwd), output) {
+        Ok(Some(log)) => tail(&String::from_utf8_lossy(&log), LOG_TAIL_LINES),
+        Ok(None) => String::new(),
+        Err(err) => format!("no build log: {err:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> CheckReport {
+        let case =
+            |attr: &str, status, ms| Case::new(attr.into(), status, Duration::from_millis(ms));
+        let mut failed = case("nixpkgs-latest-clippy", Status::Failed, 2500);
+        failed.log = Some("error: unused variable: `x` <here>".into());
+        let mut broken = case("broken", Status::EvalFailed("error: undefined".into()), 0);
+        broken.log = Some("error: undefined".into());
+        CheckReport {
+            system: "x86_64-linux".into(),
+            checks: vec![
+                case("nixpkgs-clippy", Status::Built, 1250),
+                case("nixpkgs-fmt", Status::Cached, 0),
+                failed,
+                broken,
+            ],
+        }
+    }
+
+    #[test]
+    fn splits_check_sets() {
+        assert_eq!(split_attr("nixpkgs-clippy"), ("nixpkgs", "clippy"));
+        assert_eq!(
+            split_attr("nixpkgs-latest-test"),
+            ("nixpkgs-latest", "test")
+        );
+        assert_eq!(split_attr("nixpkgsFoo"), ("checks", "nixpkgsFoo"));
+        assert_eq!(split_attr("cargoTest"), ("checks", "cargoTest"));
+    }
+
+    #[test]
+    fn format_follows_extension() {
+        assert_eq!(
+            Format::from_path(Path::new("out/junit.xml")).unwrap(),
+            Format::Junit
+        );
+        assert_eq!(
+            Format::from_path(Path::new("report.json")).unwrap(),
+            Format::Json
+        );
+        assert!(Format::from_path(Path::new("report.txt")).is_err());
+    }
+
+    #[test]
+    fn renders_junit() {
+        assert_eq!(
+            report().render(Format::Junit).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="checks.x86_64-linux" tests="4" failures="2" time="3.750">
+  <testsuite name="nixpkgs" tests="2" failures="0" time="1.250">
+    <testcase name="clippy" classname="checks.x86_64-linux.nixpkgs" time="1.250"/>
+    <testcase name="fmt" classname="checks.x86_64-linux.nixpkgs" time="0.000">
+      <properties>
+        <property name="cached" value="true"/>
+      </properties>
+    </testcase>
+  </testsuite>
+  <testsuite name="nixpkgs-latest" tests="1" failures="1" time="2.500">
+    <testcase name="clippy" classname="checks.x86_64-linux.nixpkgs-latest" time="2.500">
+      <failure message="build failed">error: unused variable: `x` &lt;here&gt;</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name="checks" tests="1" failures="1" time="0.000">
+    <testcase name="broken" classname="checks.x86_64-linux.checks" time="0.000">
+      <failure message="evaluation failed">error: undefined</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&report().render(Format::Json).unwrap()).unwrap();
+        assert_eq!(json["system"], "x86_64-linux");
+        assert_eq!(
+            json["checks"][0],
+            serde_json::json!({
+                "attr": "nixpkgs-clippy",
+                "suite": "nixpkgs",
+                "name": "clippy",
+                "status": "passed",
+                "duration_ms": 1250,
+            })
+        );
+        assert_eq!(json["checks"][1]["status"], "cached");
+        assert_eq!(json["checks"][3]["status"], "failed");
+        assert_eq!(json["checks"][3]["log"], "error: undefined");
+    }
+
+    #[test]
+    fn tails_logs_without_colors() {
+        let log = "one\n\x1b[31;1mtwo\x1b[0m\nthree\n\n";
+        assert_eq!(tail(log, 2), "two\nthree");
+        assert_eq!(tail(log, 10), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            escape("a & \"b\" <c>\x07"),
+            "a &amp; &quot;b&quot; &lt;c&gt;"
+        );
+    }
+}
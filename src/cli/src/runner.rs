@@ -0,0 +1,425 @@
+//! Running external commands (mostly `nix`) behind a [`Runner`] so they can be previewed
+//! with `--dry-run` and asserted on in tests.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, Once,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+
+/// A command line and the directory it runs in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    pub cwd: PathBuf,
+}
+
+impl Cmd {
+    pub fn new(program: impl Into<OsString>, args: Vec<OsString>, cwd: &Path) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            cwd: cwd.to_path_buf(),
+        }
+    }
+
+    /// Shell-like rendering, used for `> ...` announcements and error messages
+    pub fn display(&self) -> String {
+        let mut parts = vec![self.program.to_string_lossy().into_owned()];
+        parts.extend(self.args.iter().map(|arg| quote_arg(arg)));
+        parts.join(" ")
+    }
+}
+
+fn quote_arg(arg: &OsStr) -> String {
+    let s = arg.to_string_lossy();
+    if s.chars()
+        .any(|c| c.is_whitespace() || matches!(c, '\'' | '"'))
+    {
+        let escaped = s.replace('\'', "'\\''");
+        format!("'{}'", escaped)
+    } else {
+        s.into_owned()
+    }
+}
+
+/// Where a child's stdout/stderr goes
+#[derive(Clone, Copy)]
+pub enum Output<'a> {
+    Inherit,
+    Log(&'a File),
+}
+
+impl Output<'_> {
+    fn stdio(&self) -> Result<Stdio> {
+        Ok(match self {
+            Output::Inherit => Stdio::inherit(),
+            Output::Log(file) => file.try_clone().context("failed to open log")?.into(),
+        })
+    }
+
+    pub fn println(&self, line: &str) -> Result<()> {
+        match self {
+            Output::Inherit => println!("{}", line),
+            Output::Log(mut file) => writeln!(file, "{}", line).context("failed to write log")?,
+        }
+        Ok(())
+    }
+
+    pub fn announce(&self, cmd: &Cmd) -> Result<()> {
+        self.println(&format!("> {}", cmd.display()))
+    }
+}
+
+/// Executes commands on behalf of the subcommands
+pub trait Runner: Sync {
+    /// Run to completion, failing on a non-zero exit
+    fn run(&self, cmd: &Cmd, output: Output) -> Result<()>;
+
+    /// Run to completion and return stdout (stderr goes to `output`)
+    ///
+    /// Returns `None` if the command was not actually executed (`--dry-run`).
+    fn capture(&self, cmd: &Cmd, output: Output) -> Result<Option<Vec<u8>>>;
+
+    /// Run to completion, handing each line of stdout to `on_line` as soon as it is printed
+    fn stream(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()>;
+
+    /// Like [`Runner::stream`], but for stderr (stdout goes to `output`)
+    fn stream_stderr(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()>;
+}
+
+/// Runs commands for real
+pub struct System;
+
+impl System {
+    fn command(cmd: &Cmd, output: Output) -> Result<Command> {
+        let mut command = Command::new(&cmd.program);
+        command
+            .args(&cmd.args)
+            .current_dir(&cmd.cwd)
+            .stderr(output.stdio()?);
+        // Interactive commands (`one develop`) need our stdin; logged ones never get it
+        if let Output::Log(_) = output {
+            command.stdin(Stdio::null());
+        }
+        Ok(command)
+    }
+}
+
+impl Runner for System {
+    fn run(&self, cmd: &Cmd, output: Output) -> Result<()> {
+        output.announce(cmd)?;
+        let status = Self::command(cmd, output)?
+            .stdout(output.stdio()?)
+            .status()
+            .with_context(|| format!("failed to run {}", cmd.display()))?;
+        check_status(cmd, status)
+    }
+
+    fn capture(&self, cmd: &Cmd, output: Output) -> Result<Option<Vec<u8>>> {
+        output.announce(cmd)?;
+        let result = Self::command(cmd, output)?
+            .stdout(Stdio::piped())
+            .output()
+            .with_context(|| format!("failed to run {}", cmd.display()))?;
+        check_status(cmd, result.status)?;
+        Ok(Some(result.stdout))
+    }
+
+    fn stream(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        output.announce(cmd)?;
+        let mut child = Self::command(cmd, output)?
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", cmd.display()))?;
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        drain(cmd, child, stdout, on_line)
+    }
+
+    fn stream_stderr(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        output.announce(cmd)?;
+        let mut child = Self::command(cmd, output)?
+            .stdout(output.stdio()?)
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", cmd.display()))?;
+
+        let stderr = child.stderr.take().expect("stderr is piped");
+        drain(cmd, child, stderr, on_line)
+    }
+}
+
+/// Hand every line of `pipe` to `on_line`, then wait for `child`
+fn drain(
+    cmd: &Cmd,
+    mut child: Child,
+    pipe: impl Read,
+    on_line: &mut dyn FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let result = BufReader::new(pipe)
+        .lines()
+        .try_for_each(|line| on_line(&line.context("failed to read output")?));
+    if result.is_err() {
+        let _ = child.kill();
+    }
+
+    let status = child
+        .wait()
+        .with_context(|| format!("failed to wait for {}", cmd.display()))?;
+    result?;
+    check_status(cmd, status)
+}
+
+fn check_status(cmd: &Cmd, status: ExitStatus) -> Result<()> {
+    check_interrupted()?;
+    if !status.success() {
+        bail!("{} exited with {}", cmd.display(), status);
+    }
+    Ok(())
+}
+
+/// Prints the commands that would run (`--dry-run`)
+pub struct DryRun;
+
+impl DryRun {
+    fn print(cmd: &Cmd) {
+        println!("would run: {}", cmd.display());
+    }
+}
+
+impl Runner for DryRun {
+    fn run(&self, cmd: &Cmd, _output: Output) -> Result<()> {
+        Self::print(cmd);
+        Ok(())
+    }
+
+    fn capture(&self, cmd: &Cmd, _output: Output) -> Result<Option<Vec<u8>>> {
+        Self::print(cmd);
+        Ok(None)
+    }
+
+    fn stream(
+        &self,
+        cmd: &Cmd,
+        _output: Output,
+        _on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        Self::print(cmd);
+        Ok(())
+    }
+
+    fn stream_stderr(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        self.stream(cmd, output, on_line)
+    }
+}
+
+/// Records every command and answers from a script instead of running anything
+///
+/// Commands are matched against scripted responses by the prefix of their rendered command
+/// line (see [`Cmd::display`]); the first match wins and unmatched commands succeed with no
+/// output. Responses added with `*_once` are used up by their first match.
+#[derive(Default)]
+pub struct Fake {
+    calls: Mutex<Vec<Cmd>>,
+    script: Mutex<Vec<(String, Response)>>,
+}
+
+#[derive(Clone)]
+struct Response {
+    stdout: String,
+    success: bool,
+    once: bool,
+}
+
+impl Fake {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Commands starting with `prefix` succeed and print `stdout`
+    pub fn respond(self, prefix: &str, stdout: &str) -> Self {
+        self.add(prefix, stdout, true, false)
+    }
+
+    /// The next command starting with `prefix` succeeds and prints `stdout`
+    pub fn respond_once(self, prefix: &str, stdout: &str) -> Self {
+        self.add(prefix, stdout, true, true)
+    }
+
+    /// Commands starting with `prefix` fail
+    pub fn fail(self, prefix: &str) -> Self {
+        self.add(prefix, "", false, false)
+    }
+
+    /// The next command starting with `prefix` fails
+    pub fn fail_once(self, prefix: &str) -> Self {
+        self.add(prefix, "", false, true)
+    }
+
+    fn add(mut self, prefix: &str, stdout: &str, success: bool, once: bool) -> Self {
+        self.script.get_mut().unwrap().push((
+            prefix.to_string(),
+            Response {
+                stdout: stdout.to_string(),
+                success,
+                once,
+            },
+        ));
+        self
+    }
+
+    /// Everything run so far, in order
+    pub fn calls(&self) -> Vec<Cmd> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Rendered command lines run so far, with `root` replaced by `$ROOT`
+    pub fn commands(&self, root: &Path) -> Vec<String> {
+        let root = root.display().to_string();
+        self.calls()
+            .iter()
+            .map(|cmd| cmd.display().replace(&root, "$ROOT"))
+            .collect()
+    }
+
+    fn call(&self, cmd: &Cmd) -> Result<String> {
+        self.calls.lock().unwrap().push(cmd.clone());
+
+        let display = cmd.display();
+        let mut script = self.script.lock().unwrap();
+        let response = match script
+            .iter()
+            .position(|(prefix, _)| display.starts_with(prefix.as_str()))
+        {
+            Some(i) if script[i].1.once => script.remove(i).1,
+            Some(i) => script[i].1.clone(),
+            None => Response {
+                stdout: String::new(),
+                success: true,
+                once: false,
+            },
+        };
+
+        if !response.success {
+            bail!("{} exited with exit status: 1", display);
+        }
+        Ok(response.stdout)
+    }
+}
+
+impl Runner for Fake {
+    fn run(&self, cmd: &Cmd, _output: Output) -> Result<()> {
+        self.call(cmd).map(drop)
+    }
+
+    fn capture(&self, cmd: &Cmd, _output: Output) -> Result<Option<Vec<u8>>> {
+        self.call(cmd).map(|stdout| Some(stdout.into_bytes()))
+    }
+
+    fn stream(
+        &self,
+        cmd: &Cmd,
+        _output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        self.call(cmd)?.lines().try_for_each(on_line)
+    }
+
+    /// Scripted responses are handed out as stderr lines here
+    fn stream_stderr(
+        &self,
+        cmd: &Cmd,
+        output: Output,
+        on_line: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<()> {
+        self.stream(cmd, output, on_line)
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static HANDLER: Once = Once::new();
+
+/// Survive Ctrl-C so cleanup can run
+///
+/// Children are in our process group and receive the signal themselves; we just record it
+/// and stop before the next step.
+pub fn trap_interrupts() {
+    HANDLER.call_once(|| {
+        // Failing to install the handler only loses the cleanup guarantee
+        let _ = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst));
+    });
+}
+
+pub fn check_interrupted() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        bail!("interrupted");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_quotes_arguments() {
+        let cmd = Cmd::new(
+            "nix",
+            vec!["develop".into(), "--command".into(), "echo it's".into()],
+            Path::new("."),
+        );
+        assert_eq!(cmd.display(), r"nix develop --command 'echo it'\''s'");
+    }
+
+    #[test]
+    fn fake_follows_script() {
+        let fake = Fake::new()
+            .respond("nix eval", "x86_64-linux")
+            .fail("nix build");
+        let cwd = Path::new("/repo");
+        let eval = Cmd::new("nix", vec!["eval".into()], cwd);
+        let build = Cmd::new("nix", vec!["build".into()], cwd);
+
+        assert_eq!(
+            fake.capture(&eval, Output::Inherit).unwrap(),
+            Some(b"x86_64-linux".to_vec())
+        );
+        assert!(fake.run(&build, Output::Inherit).is_err());
+        assert_eq!(fake.commands(cwd), ["nix eval", "nix build"]);
+    }
+}
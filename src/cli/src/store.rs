@@ -0,0 +1,227 @@
+//! Local artifact store: `pkgs/<package>/<hash>/` directories plus an index.
+//!
+//! The index lists every stored artifact and may carry an Ed25519 signature over its
+//! entries (same key format as `build-signer`), so any tool that rewrites it must
+//! either re-sign it with the same key or refuse to touch it.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_STORE_DIR: &str = ".one/store";
+const INDEX_FILE: &str = "index.json";
+const PKGS_DIR: &str = "pkgs";
+
+/// A single artifact recorded in the store index
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Logical package name (or flake ref) the artifact was built from
+    pub package: String,
+    /// Content hash identifying the artifact directory
+    pub hash: String,
+    /// Commit the artifact was built from
+    pub commit: String,
+    /// RFC 3339 timestamp of when the artifact was pushed
+    pub created: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Index {
+    pub entries: Vec<IndexEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl Index {
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some() || self.public_key.is_some()
+    }
+
+    /// Check the index signature (if any) against the embedded public key
+    pub fn verify(&self) -> Result<()> {
+        let (Some(signature), Some(public_key)) = (&self.signature, &self.public_key) else {
+            if self.is_signed() {
+                bail!("index has a signature or public key but not both");
+            }
+            return Ok(());
+        };
+
+        let verifying_key = decode_public_key(public_key)?;
+        let sig_bytes: [u8; 64] = hex::decode(signature)
+            .context("failed to decode index signature")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("index signature must be 64 bytes"))?;
+
+        verifying_key
+            .verify(&self.signed_bytes()?, &Signature::from_bytes(&sig_bytes))
+            .context("index signature verification failed")
+    }
+
+    /// (Re-)sign the entries, replacing any previous signature
+    pub fn sign(&mut self, signing_key: &SigningKey) -> Result<()> {
+        let signature = signing_key.sign(&self.signed_bytes()?);
+        self.signature = Some(hex::encode(signature.to_bytes()));
+        self.public_key = Some(hex::encode(signing_key.verifying_key().to_bytes()));
+        Ok(())
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(&self.entries).context("failed to serialize index entries")
+    }
+}
+
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn open(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.root.join(INDEX_FILE)
+    }
+
+    /// Directory holding the artifact, metadata and proof for `entry`
+    ///
+    /// The index isn't necessarily signed, so its fields are checked before they become paths.
+    pub fn entry_dir(&self, entry: &IndexEntry) -> Result<PathBuf> {
+        Ok(self
+            .root
+            .join(PKGS_DIR)
+            .join(path_component("package", &entry.package)?)
+            .join(path_component("hash", &entry.hash)?))
+    }
+
+    /// Read the index, treating a missing file as an empty store
+    pub fn read_index(&self) -> Result<Index> {
+        let path = self.index_path();
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Atomically replace the index on disk
+    pub fn write_index(&self, index: &Index) -> Result<()> {
+        let path = self.index_path();
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(index).context("failed to serialize index")?;
+
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed to create {}", self.root.display()))?;
+        fs::write(&tmp, json).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+/// `value` if it is safe to use as a single path component (no separators, `.` or `..`)
+pub fn path_component<'a>(field: &str, value: &'a str) -> Result<&'a str> {
+    if value.is_empty() || value == "." || value == ".." || value.contains(['/', '\\', '\0']) {
+        bail!("invalid {field} {value:?} in the store index");
+    }
+    Ok(value)
+}
+
+/// Read a raw 32-byte Ed25519 private key (the format produced by `generate-keypair.sh`)
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let bytes: [u8; 32] = fs::read(path)
+        .with_context(|| format!("failed to read private key {}", path.display()))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("private key must be exactly 32 bytes"))?;
+
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn decode_public_key(public_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(public_key)
+        .context("failed to decode index public key")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("index public key must be 32 bytes"))?;
+
+    VerifyingKey::from_bytes(&bytes).context("invalid index public key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(package: &str, hash: &str) -> IndexEntry {
+        IndexEntry {
+            package: package.into(),
+            hash: hash.into(),
+            commit: "0fd9b80763fa999d0b8bf3f9d35ff98b57df5258".into(),
+            created: "2025-11-21T14:15:50Z".into(),
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn sign_and_verify_roundtrip() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut index = Index {
+            entries: vec![entry("foo", "aaa"), entry("bar", "bbb")],
+            ..Default::default()
+        };
+
+        index.sign(&key).unwrap();
+        index.verify().unwrap();
+
+        index.entries.pop();
+        assert!(index.verify().is_err());
+    }
+
+    #[test]
+    fn unsigned_index_verifies() {
+        let index = Index {
+            entries: vec![entry("foo", "aaa")],
+            ..Default::default()
+        };
+        assert!(!index.is_signed());
+        index.verify().unwrap();
+    }
+
+    #[test]
+    fn entry_dir_layout() {
+        let store = Store::open("/store");
+        assert_eq!(
+            store.entry_dir(&entry("foo", "aaa")).unwrap(),
+            Path::new("/store/pkgs/foo/aaa")
+        );
+    }
+
+    #[test]
+    fn entry_dir_rejects_path_traversal() {
+        let store = Store::open("/store");
+        for (package, hash) in [
+            ("..", "aaa"),
+            ("foo", "../../etc"),
+            ("foo/bar", "aaa"),
+            ("", "aaa"),
+            ("foo", "."),
+        ] {
+            assert!(
+                store.entry_dir(&entry(package, hash)).is_err(),
+                "{package}/{hash}"
+            );
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! `one watch`: re-run a build, the checks or an example test whenever a relevant source
+//! file changes, printing only the results that changed since the previous run.
+//!
+//! Relevant means not ignored by git (nix flakes only see tracked and untracked-but-not-ignored
+//! files) and, when watching an example built from `cleanCargoSource ./.`, kept by
+//! `filterCargoSources`. Changes to the library are always relevant because examples are
+//! tested against the local tree.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueHint};
+use ignore::gitignore::Gitignore;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::{fast_check, runner};
+
+/// Quiet period after the last change before re-running
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often the wait loop checks for Ctrl-C
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Changed files listed before a re-run
+const MAX_LISTED: usize = 5;
+
+/// Sources every example is tested against (`one-for-all` and nixpkgs are overridden locally)
+const SHARED_SOURCES: &[&str] = &[
+    "flake.nix",
+    "flake.lock",
+    "src/lang/rust/lib",
+    "src/lang/rust/pkgs",
+    "src/lang/rust/test/flake.lock",
+];
+
+/// Never relevant, even when not ignored by git
+const SKIPPED_DIRS: &[&str] = &[".git", ".one", "target"];
+
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Rebuild this flake attribute (omit the leading `#`) instead of running the checks
+    #[arg(short, long, value_name = "ATTR", conflicts_with = "example")]
+    pub attr: Option<String>,
+    /// Re-test this example (name, alias or path, like `one test`) instead of running the
+    /// checks
+    #[arg(short, long, value_name = "NAME_OR_PATH", value_hint = ValueHint::AnyPath)]
+    pub example: Option<String>,
+    /// Quiet period after the last change before re-running (e.g. `500ms`) [default: 300ms]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub debounce: Option<Duration>,
+    /// Extra arguments forwarded to nix
+    #[arg(long = "nix-arg", value_name = "ARG")]
+    pub nix_args: Vec<String>,
+}
+
+/// Which changes trigger a re-run
+pub struct Filter {
+    root: PathBuf,
+    gitignores: Vec<Gitignore>,
+    /// The watched example and whether only its cargo sources count; `None` watches the whole
+    /// repository
+    example: Option<(PathBuf, bool)>,
+}
+
+impl Filter {
+    pub fn new(root: &Path, example: Option<&Path>) -> Result<Self> {
+        let mut gitignores = Vec::new();
+        collect_gitignores(root, &mut gitignores)?;
+
+        let example = match example {
+            Some(dir) => {
+                let flake = fs::read_to_string(dir.join("flake.nix"))
+                    .with_context(|| format!("failed to read {}/flake.nix", dir.display()))?;
+                // Extra filesets (e.g. trunk's `index.html`) make other files relevant too
+                let cargo_only =
+                    flake.contains("cleanCargoSource ./.") && !flake.contains("fileset.");
+                Some((dir.to_path_buf(), cargo_only))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            gitignores,
+            example,
+        })
+    }
+
+    pub fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if rel.components().any(|c| {
+            let c = c.as_os_str().to_string_lossy();
+            SKIPPED_DIRS.contains(&c.as_ref()) || c == "result" || c.starts_with("result-")
+        }) {
+            return false;
+        }
+        if is_editor_file(path) || self.is_ignored(path) {
+            return false;
+        }
+
+        let Some((example, cargo_only)) = &self.example else {
+            return true;
+        };
+        if let Ok(in_example) = path.strip_prefix(example) {
+            return !cargo_only || is_cargo_source(in_example);
+        }
+        SHARED_SOURCES.iter().any(|shared| rel.starts_with(shared))
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        let is_dir = path.is_dir();
+        self.gitignores.iter().any(|gitignore| {
+            path.starts_with(gitignore.path())
+                && gitignore
+                    .matched_path_or_any_parents(path, is_dir)
+                    .is_ignore()
+        })
+    }
+}
+
+fn collect_gitignores(dir: &Path, out: &mut Vec<Gitignore>) -> Result<()> {
+    let gitignore = dir.join(".gitignore");
+    if gitignore.is_file() {
+        let (matcher, error) = Gitignore::new(&gitignore);
+        if let Some(error) = error {
+            eprintln!("warning: {}: {error}", gitignore.display());
+        }
+        out.push(matcher);
+    }
+
+    let entries = fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() && !SKIPPED_DIRS.contains(&name.as_ref()) {
+            collect_gitignores(&entry.path(), out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Swap, backup and lock files written by editors while saving
+fn is_editor_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    name.ends_with('~')
+        || name.ends_with(".swp")
+        || name.ends_with(".swx")
+        || name.starts_with(".#")
+        || name == "4913"
+}
+
+/// Mirrors `lib/filterCargoSources.nix` (plus the flake itself)
+fn is_cargo_source(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let parent = path
+        .parent()
+        .and_then(Path::file_name)
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    name.ends_with(".rs")
+        || name.ends_with(".toml")
+        || name.ends_with(".nix")
+        || name == "Cargo.lock"
+        || name == "flake.lock"
+        || (parent == ".cargo" && name == "config")
+}
+
+/// Block until a relevant file changes, then until no relevant change arrived for `debounce`;
+/// returns the changed files
+pub fn wait_for_changes(
+    events: &Receiver<notify::Result<Event>>,
+    filter: &Filter,
+    debounce: Duration,
+) -> Result<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    let mut last_change: Option<Instant> = None;
+    loop {
+        runner::check_interrupted()?;
+        let timeout = match last_change {
+            Some(at) => match debounce.checked_sub(at.elapsed()) {
+                Some(left) => left.min(POLL_INTERVAL),
+                None => return Ok(changed),
+            },
+            None => POLL_INTERVAL,
+        };
+
+        let event = match events.recv_timeout(timeout) {
+            Ok(event) => event.context("failed to watch the source tree")?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => bail!("file watcher stopped"),
+        };
+        // Reads (including nix's own) must not trigger a re-run
+        if matches!(event.kind, EventKind::Access(_)) {
+            continue;
+        }
+        let relevant: Vec<_> = event
+            .paths
+            .into_iter()
+            .filter(|path| filter.is_relevant(path))
+            .collect();
+        if !relevant.is_empty() {
+            changed.extend(relevant);
+            last_change = Some(Instant::now());
+        }
+    }
+}
+
+/// Outcome of one run: whether each check/attribute/example passed, with a short status
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Results(pub BTreeMap<String, (bool, String)>);
+
+impl Results {
+    pub fn single(name: &str, result: Result<()>) -> Self {
+        let status = match result {
+            Ok(()) => (true, "ok".to_string()),
+            Err(err) => (false, format!("FAILED: {err:#}")),
+        };
+        Self([(name.to_string(), status)].into())
+    }
+
+    pub fn from_report(report: &fast_check::Report) -> Self {
+        Self(
+            report
+                .checks
+                .iter()
+                .map(|(attr, status)| {
+                    let passed = matches!(
+                        status,
+                        fast_check::Status::Cached | fast_check::Status::Built
+                    );
+                    (attr.clone(), (passed, status.to_string()))
+                })
+                .collect(),
+        )
+    }
+
+    fn failed(&self) -> usize {
+        self.0.values().filter(|(passed, _)| !passed).count()
+    }
+
+    /// Lines for the results that appeared, disappeared or started/stopped passing
+    pub fn delta(&self, previous: &Results) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (name, (passed, status)) in &self.0 {
+            match previous.0.get(name) {
+                None => lines.push(format!("{name}: {status} (new)")),
+                Some((was_passing, was)) if was_passing != passed => {
+                    let change = if *passed { "fixed" } else { "broken" };
+                    lines.push(format!("{name}: {status} ({change}, was {was})"));
+                }
+                Some(_) => {}
+            }
+        }
+        for name in previous.0.keys().filter(|n| !self.0.contains_key(*n)) {
+            lines.push(format!("{name}: gone"));
+        }
+        lines
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} passed, {} failed",
+            self.0.len() - self.failed(),
+            self.failed()
+        )
+    }
+}
+
+/// Run `once` now and after every batch of relevant changes, until interrupted
+pub fn run(
+    root: &Path,
+    filter: &Filter,
+    debounce: Duration,
+    mut once: impl FnMut() -> Results,
+) -> Result<()> {
+    runner::trap_interrupts();
+    let (tx, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("failed to start file watcher")?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {}", root.display()))?;
+
+    let mut previous = once();
+    for (name, (_, status)) in &previous.0 {
+        println!("{name}: {status}");
+    }
+    println!(
+        "--- {}; watching for changes (Ctrl-C to stop)",
+        previous.summary()
+    );
+
+    loop {
+        let changed = match wait_for_changes(&events, filter, debounce) {
+            Ok(changed) => changed,
+            Err(_) if runner::check_interrupted().is_err() => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        print_changed(root, &changed);
+
+        let results = once();
+        let delta = results.delta(&previous);
+        if delta.is_empty() {
+            println!("--- no changes in results ({})", results.summary());
+        } else {
+            for line in delta {
+                println!("{line}");
+            }
+            println!("--- {}", results.summary());
+        }
+        previous = results;
+    }
+}
+
+fn print_changed(root: &Path, changed: &BTreeSet<PathBuf>) {
+    let mut names: Vec<_> = changed
+        .iter()
+        .take(MAX_LISTED)
+        .map(|path| {
+            path.strip_prefix(root)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect();
+    if changed.len() > MAX_LISTED {
+        names.push(format!("and {} more", changed.len() - MAX_LISTED));
+    }
+    println!("--- changed: {}", names.join(", "));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+
+    use anyhow::anyhow;
+    use notify::event::{AccessKind, ModifyKind};
+
+    use super::*;
+
+    fn root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../..")
+            .canonicalize()
+            .unwrap()
+    }
+
+    fn send(tx: &Sender<notify::Result<Event>>, kind: EventKind, path: PathBuf) {
+        tx.send(Ok(Event::new(kind).add_path(path))).unwrap();
+    }
+
+    #[test]
+    fn filters_the_repository() {
+        let root = root();
+        let filter = Filter::new(&root, None).unwrap();
+        assert!(filter.is_relevant(&root.join("src/lang/rust/lib/cargoBuild.nix")));
+        assert!(filter.is_relevant(&root.join("src/cli/src/lib.rs")));
+        assert!(!filter.is_relevant(&root.join("src/cli/target/debug/one")));
+        assert!(!filter.is_relevant(&root.join(".git/index")));
+        assert!(!filter.is_relevant(&root.join("result-doc/share")));
+        assert!(!filter.is_relevant(&root.join("src/lang/rust/lib/.cargoBuild.nix.swp")));
+        // Ignored by the root .gitignore
+        assert!(!filter.is_relevant(&root.join("src/crypto/Cargo.lock")));
+        assert!(!filter.is_relevant(Path::new("/elsewhere/flake.nix")));
+    }
+
+    #[test]
+    fn filters_an_example() {
+        let root = root();
+        let example = root.join("src/lang/rust/examples/quick-start");
+        let filter = Filter::new(&root, Some(&example)).unwrap();
+        assert!(filter.is_relevant(&example.join("src/main.rs")));
+        assert!(filter.is_relevant(&example.join("flake.nix")));
+        assert!(!filter.is_relevant(&example.join("README.md")));
+        assert!(filter.is_relevant(&root.join("src/lang/rust/lib/cargoBuild.nix")));
+        assert!(!filter.is_relevant(&root.join("src/lang/rust/examples/trunk/src/main.rs")));
+        assert!(!filter.is_relevant(&root.join("README.md")));
+
+        // trunk adds `index.html` and assets to its source
+        let trunk = root.join("src/lang/rust/examples/trunk");
+        let filter = Filter::new(&root, Some(&trunk)).unwrap();
+        assert!(filter.is_relevant(&trunk.join("index.html")));
+    }
+
+    #[test]
+    fn debounces_relevant_changes() {
+        let root = root();
+        let filter = Filter::new(&root, None).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let lib = root.join("src/lang/rust/lib/cargoBuild.nix");
+        let modify = EventKind::Modify(ModifyKind::Any);
+        send(
+            &tx,
+            EventKind::Access(AccessKind::Any),
+            root.join("README.md"),
+        );
+        send(&tx, modify, root.join(".git/index"));
+        send(&tx, modify, lib.clone());
+        send(&tx, modify, lib.clone());
+        send(&tx, modify, root.join("README.md"));
+
+        let changed = wait_for_changes(&rx, &filter, Duration::from_millis(50)).unwrap();
+        assert_eq!(changed, [root.join("README.md"), lib].into());
+
+        tx.send(Err(notify::Error::generic("boom"))).unwrap();
+        assert!(wait_for_changes(&rx, &filter, Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn reports_only_the_delta() {
+        let first = Results(
+            [
+                ("a".to_string(), (true, "built".to_string())),
+                ("b".to_string(), (false, "FAILED".to_string())),
+                ("c".to_string(), (true, "cached".to_string())),
+            ]
+            .into(),
+        );
+        let second = Results(
+            [
+                ("a".to_string(), (true, "cached".to_string())),
+                ("b".to_string(), (true, "built".to_string())),
+                ("d".to_string(), (false, "EVAL FAILED".to_string())),
+            ]
+            .into(),
+        );
+        assert_eq!(
+            second.delta(&first),
+            [
+                "b: built (fixed, was FAILED)",
+                "d: EVAL FAILED (new)",
+                "c: gone"
+            ]
+        );
+        assert!(second.delta(&second).is_empty());
+        assert_eq!(second.summary(), "2 passed, 1 failed");
+
+        let failed = Results::single("quick-start", Err(anyhow!("nix exited with 1")));
+        assert_eq!(
+            failed.0["quick-start"],
+            (false, "FAILED: nix exited with 1".to_string())
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! `one why-rebuild`: explain why an attribute's derivation differs between two revisions.
+//!
+//! Both derivation closures come from `nix derivation show --recursive`. Starting at the two
+//! roots, input derivations are paired by name and compared recursively; a derivation whose
+//! only differences are changed inputs just propagates a change, so only derivations with
+//! differences of their own (environment, sources, builder, added or removed inputs) are
+//! reported as root causes. Store hashes are masked when comparing strings, since they change
+//! whenever anything upstream does.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde::Deserialize;
+
+use crate::{
+    fast_check,
+    runner::{Cmd, Output, Runner},
+};
+
+const STORE_DIR: &str = "/nix/store/";
+const HASH_LEN: usize = 32;
+
+/// Longest value shown for a differing environment variable
+const MAX_VALUE_LEN: usize = 80;
+
+#[derive(Args)]
+pub struct WhyRebuildArgs {
+    /// Flake attribute (omit the leading `#`); a bare name is a check of the current system
+    #[arg(value_name = "ATTR")]
+    pub attr: String,
+    /// Old revision (any git ref)
+    #[arg(long, value_name = "REF", default_value = "HEAD")]
+    pub from: String,
+    /// New revision (any git ref) [default: the working tree]
+    #[arg(long, value_name = "REF")]
+    pub to: Option<String>,
+}
+
+/// One entry of `nix derivation show` output
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Derivation {
+    pub name: String,
+    pub builder: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub system: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub input_srcs: Vec<String>,
+    /// Values are the used outputs, in either the old (list) or new (object) format
+    #[serde(default)]
+    pub input_drvs: BTreeMap<String, serde_json::Value>,
+}
+
+/// A derivation closure keyed by `.drv` path
+#[derive(Debug, Default)]
+pub struct Closure(BTreeMap<String, Derivation>);
+
+impl Closure {
+    pub fn parse(json: &str) -> Result<Self> {
+        let closure: BTreeMap<String, Derivation> =
+            serde_json::from_str(json).context("failed to parse `nix derivation show` output")?;
+        if closure.is_empty() {
+            bail!("`nix derivation show` returned no derivations");
+        }
+        Ok(Self(closure))
+    }
+
+    /// The derivation no other one depends on
+    pub fn root(&self) -> Result<&str> {
+        let inputs: BTreeSet<_> = self
+            .0
+            .values()
+            .flat_map(|drv| drv.input_drvs.keys())
+            .collect();
+        let mut roots = self.0.keys().filter(|path| !inputs.contains(path));
+        match (roots.next(), roots.next()) {
+            (Some(root), None) => Ok(root),
+            _ => bail!("`nix derivation show` output has no single root derivation"),
+        }
+    }
+}
+
+/// A difference local to one derivation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cause {
+    Env {
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    SourceChanged(String),
+    SourceAdded(String),
+    SourceRemoved(String),
+    InputAdded(String),
+    InputRemoved(String),
+    Builder,
+    Args,
+    System {
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Env { name, old, new } => {
+                let show = |value: &Option<String>| match value {
+                    None => "unset".to_string(),
+                    Some(value) => format!("{:?}", truncate(&mask_hashes(value))),
+                };
+                write!(f, "{name} differs: {} -> {}", show(old), show(new))
+            }
+            Cause::SourceChanged(name) => write!(f, "{name} changed"),
+            Cause::SourceAdded(name) => write!(f, "source {name} added"),
+            Cause::SourceRemoved(name) => write!(f, "source {name} removed"),
+            Cause::InputAdded(name) => write!(f, "input {name} added"),
+            Cause::InputRemoved(name) => write!(f, "input {name} removed"),
+            Cause::Builder => f.write_str("builder differs"),
+            Cause::Args => f.write_str("builder arguments differ"),
+            Cause::System { old, new } => write!(f, "system differs: {old} -> {new}"),
+        }
+    }
+}
+
+/// A derivation with differences of its own, and how the root depends on it
+#[derive(Debug, PartialEq, Eq)]
+pub struct RootCause {
+    /// Derivation names from the root down to this one
+    pub chain: Vec<String>,
+    pub causes: Vec<Cause>,
+}
+
+/// Root causes of `old` and `new` differing, closest to the root first
+pub fn explain(old: &Closure, new: &Closure) -> Result<Vec<RootCause>> {
+    let mut found = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut queue = vec![(old.root()?.to_string(), new.root()?.to_string(), Vec::new())];
+
+    // Breadth-first, so each derivation is reported via its shortest chain
+    while !queue.is_empty() {
+        let mut next = Vec::new();
+        for (old_path, new_path, mut chain) in queue {
+            if old_path == new_path || !visited.insert((old_path.clone(), new_path.clone())) {
+                continue;
+            }
+            let a = lookup(old, &old_path)?;
+            let b = lookup(new, &new_path)?;
+            chain.push(b.name.clone());
+
+            let mut causes = local_causes(a, b);
+            let (pairs, removed, added) = pair_inputs(old, a, new, b);
+            causes.extend(removed.into_iter().map(Cause::InputRemoved));
+            causes.extend(added.into_iter().map(Cause::InputAdded));
+            if !causes.is_empty() {
+                found.push(RootCause {
+                    chain: chain.clone(),
+                    causes,
+                });
+            }
+            for (old_input, new_input) in pairs {
+                next.push((old_input, new_input, chain.clone()));
+            }
+        }
+        queue = next;
+    }
+    Ok(found)
+}
+
+fn lookup<'a>(closure: &'a Closure, path: &str) -> Result<&'a Derivation> {
+    closure
+        .0
+        .get(path)
+        .with_context(|| format!("{path} is missing from `nix derivation show` output"))
+}
+
+fn local_causes(old: &Derivation, new: &Derivation) -> Vec<Cause> {
+    let mut causes = Vec::new();
+    if old.system != new.system {
+        causes.push(Cause::System {
+            old: old.system.clone(),
+            new: new.system.clone(),
+        });
+    }
+    if mask_hashes(&old.builder) != mask_hashes(&new.builder) {
+        causes.push(Cause::Builder);
+    }
+    let masked = |args: &[String]| args.iter().map(|a| mask_hashes(a)).collect::<Vec<_>>();
+    if masked(&old.args) != masked(&new.args) {
+        causes.push(Cause::Args);
+    }
+
+    let names: BTreeSet<_> = old.env.keys().chain(new.env.keys()).collect();
+    for name in names {
+        let (a, b) = (old.env.get(name), new.env.get(name));
+        if a.map(|v| mask_hashes(v)) != b.map(|v| mask_hashes(v)) {
+            causes.push(Cause::Env {
+                name: name.clone(),
+                old: a.cloned(),
+                new: b.cloned(),
+            });
+        }
+    }
+
+    let by_name = |srcs: &[String]| -> BTreeMap<String, BTreeSet<String>> {
+        let mut map: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+        for src in srcs {
+            map.entry(store_name(src).to_string())
+                .or_default()
+                .insert(src.clone());
+        }
+        map
+    };
+    let (a, b) = (by_name(&old.input_srcs), by_name(&new.input_srcs));
+    for (name, paths) in &a {
+        match b.get(name) {
+            None => causes.push(Cause::SourceRemoved(name.clone())),
+            Some(new_paths) if new_paths != paths => {
+                causes.push(Cause::SourceChanged(describe_source(name)))
+            }
+            Some(_) => {}
+        }
+    }
+    for name in b.keys().filter(|name| !a.contains_key(*name)) {
+        causes.push(Cause::SourceAdded(name.clone()));
+    }
+    causes
+}
+
+/// Friendlier names for well-known sources
+fn describe_source(name: &str) -> String {
+    match name {
+        "Cargo.lock" | "Cargo.toml" => name.to_string(),
+        "source" => "source tree".to_string(),
+        _ => format!("source {name}"),
+    }
+}
+
+/// Input derivations paired by name: `(changed pairs, removed names, added names)`
+fn pair_inputs(
+    old: &Closure,
+    a: &Derivation,
+    new: &Closure,
+    b: &Derivation,
+) -> (Vec<(String, String)>, Vec<String>, Vec<String>) {
+    let by_name = |closure: &Closure, drv: &Derivation| {
+        let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for path in drv.input_drvs.keys() {
+            let name = closure
+                .0
+                .get(path)
+                .map(|d| d.name.clone())
+                .unwrap_or_else(|| store_name(path).trim_end_matches(".drv").to_string());
+            map.entry(name).or_default().push(path.clone());
+        }
+        map
+    };
+    let (mut a, b) = (by_name(old, a), by_name(new, b));
+
+    let (mut pairs, mut removed, mut added) = (Vec::new(), Vec::new(), Vec::new());
+    for (name, new_paths) in b {
+        let mut old_paths = a.remove(&name).unwrap_or_default();
+        // Unchanged inputs pair with themselves; the rest pair in order
+        let mut new_paths: Vec<_> = new_paths
+            .into_iter()
+            .filter(|path| match old_paths.iter().position(|old| old == path) {
+                Some(at) => {
+                    old_paths.remove(at);
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        while !old_paths.is_empty() && !new_paths.is_empty() {
+            pairs.push((old_paths.remove(0), new_paths.remove(0)));
+        }
+        removed.extend(old_paths.iter().map(|_| name.clone()));
+        added.extend(new_paths.iter().map(|_| name.clone()));
+    }
+    for (name, paths) in a {
+        removed.extend(paths.iter().map(|_| name.clone()));
+    }
+    (pairs, removed, added)
+}
+
+/// The name part of a store path (`/nix/store/<hash>-<name>`)
+fn store_name(path: &str) -> &str {
+    path.strip_prefix(STORE_DIR)
+        .and_then(|rest| rest.get(HASH_LEN + 1..))
+        .unwrap_or(path)
+}
+
+/// Replace the hash of every store path in `s` with `…`
+fn mask_hashes(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find(STORE_DIR) {
+        let (before, path) = rest.split_at(at + STORE_DIR.len());
+        out.push_str(before);
+        let is_hash = path.len() > HASH_LEN
+            && path.as_bytes()[HASH_LEN] == b'-'
+            && path[..HASH_LEN]
+                .bytes()
+                .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase());
+        if is_hash {
+            out.push('…');
+            rest = &path[HASH_LEN..];
+        } else {
+            rest = path;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() <= MAX_VALUE_LEN {
+        return s.to_string();
+    }
+    let cut: String = s.chars().take(MAX_VALUE_LEN).collect();
+    format!("{cut}…")
+}
+
+pub fn render(attr: &str, causes: &[RootCause]) -> String {
+    if causes.is_empty() {
+        return format!("{attr}: the derivations are identical, nothing to rebuild\n");
+    }
+    let mut out = format!("{attr} rebuilds because of:\n");
+    for cause in causes {
+        out.push_str(&format!("  {}\n", cause.chain.join(" <- ")));
+        for cause in &cause.causes {
+            out.push_str(&format!("    {cause}\n"));
+        }
+    }
+    out
+}
+
+/// Resolve `args` to two closures and print the explanation
+pub fn run(runner: &dyn Runner, root: &Path, args: WhyRebuildArgs) -> Result<()> {
+    let attr = if args.attr.contains('.') {
+        args.attr.trim_start_matches('#').to_string()
+    } else {
+        let system = fast_check::current_system(runner, root, Output::Inherit)?;
+        format!("checks.{system}.{}", args.attr)
+    };
+
+    let old = flake_ref(runner, root, Some(&args.from))?;
+    let new = flake_ref(runner, root, args.to.as_deref())?;
+    let (Some(old), Some(new)) = (
+        show(runner, root, &old, &attr)?,
+        show(runner, root, &new, &attr)?,
+    ) else {
+        // --dry-run
+        return Ok(());
+    };
+    print!("{}", render(&attr, &explain(&old, &new)?));
+    Ok(())
+}
+
+/// `git+file` reference to the repository at `rev`, or the working tree
+fn flake_ref(runner: &dyn Runner, root: &Path, rev: Option<&str>) -> Result<OsString> {
+    let Some(rev) = rev else {
+        return Ok(OsString::from(root));
+    };
+    let args = ["rev-parse", "--verify", &format!("{rev}^{{commit}}")]
+        .map(OsString::from)
+        .to_vec();
+    let commit = match runner.capture(&Cmd::new("git", args, root), Output::Inherit)? {
+        Some(stdout) => String::from_utf8(stdout)
+            .context("git output is not valid UTF-8")?
+            .trim()
+            .to_string(),
+        None => rev.to_string(),
+    };
+    Ok(OsString::from(format!(
+        "git+file://{}?rev={commit}",
+        root.display()
+    )))
+}
+
+fn show(runner: &dyn Runner, root: &Path, flake: &OsString, attr: &str) -> Result<Option<Closure>> {
+    let mut installable = flake.clone();
+    installable.push(format!("#{attr}"));
+    let args = vec![
+        OsString::from("derivation"),
+        OsString::from("show"),
+        OsString::from("--recursive"),
+        OsString::from("--accept-flake-config"),
+        installable,
+    ];
+    match runner.capture(&Cmd::new("nix", args, root), Output::Inherit)? {
+        Some(stdout) => Ok(Some(Closure::parse(
+            &String::from_utf8(stdout).context("nix output is not valid UTF-8")?,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closures() -> (Closure, Closure) {
+        (
+            Closure::parse(include_str!("../testdata/drv-before.json")).unwrap(),
+            Closure::parse(include_str!("../testdata/drv-after.json")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn masks_store_hashes() {
+        assert_eq!(
+            mask_hashes("-L /nix/store/3q3fhgz6qvgq6jmx9nvhhf3s9wdn37k5-zlib/lib /nix/store/x"),
+            "-L /nix/store/…-zlib/lib /nix/store/x"
+        );
+        assert_eq!(
+            store_name("/nix/store/3q3fhgz6qvgq6jmx9nvhhf3s9wdn37k5-Cargo.lock"),
+            "Cargo.lock"
+        );
+    }
+
+    #[test]
+    fn finds_the_root_causes() {
+        let (old, new) = closures();
+        assert!(old
+            .root()
+            .unwrap()
+            .ends_with("-quick-start-clippy-0.1.0.drv"));
+
+        let causes = explain(&old, &new).unwrap();
+        assert_eq!(
+            causes,
+            [
+                RootCause {
+                    chain: vec!["quick-start-clippy-0.1.0".into()],
+                    causes: vec![Cause::Env {
+                        name: "cargoClippyExtraArgs".into(),
+                        old: Some("--all-targets".into()),
+                        new: Some("--all-targets -- --deny warnings".into()),
+                    }],
+                },
+                RootCause {
+                    chain: vec![
+                        "quick-start-clippy-0.1.0".into(),
+                        "quick-start-deps-0.1.0".into()
+                    ],
+                    causes: vec![Cause::Env {
+                        name: "RUSTFLAGS".into(),
+                        old: Some(String::new()),
+                        new: Some("-C target-cpu=native".into()),
+                    }],
+                },
+                RootCause {
+                    chain: vec![
+                        "quick-start-clippy-0.1.0".into(),
+                        "vendor-cargo-deps".into()
+                    ],
+                    causes: vec![Cause::SourceChanged("Cargo.lock".into())],
+                },
+            ]
+        );
+
+        assert_eq!(
+            render("checks.x86_64-linux.nixpkgs-clippy", &causes),
+            "\
+checks.x86_64-linux.nixpkgs-clippy rebuilds because of:
+  quick-start-clippy-0.1.0
+    cargoClippyExtraArgs differs: \"--all-targets\" -> \"--all-targets -- --deny warnings\"
+  quick-start-clippy-0.1.0 <- quick-start-deps-0.1.0
+    RUSTFLAGS differs: \"\" -> \"-C target-cpu=native\"
+  quick-start-clippy-0.1.0 <- vendor-cargo-deps
+    Cargo.lock changed
+"
+        );
+    }
+
+    #[test]
+    fn identical_closures_have_no_causes() {
+        let (old, _) = closures();
+        assert!(explain(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_added_and_removed_inputs() {
+        let (old, new) = closures();
+        let mut old = old;
+        let root = old.root().unwrap().to_string();
+        let clippy = old.0.get_mut(&root).unwrap();
+        let rustc = clippy
+            .input_drvs
+            .keys()
+            .find(|k| k.ends_with("-rustc-1.86.0.drv"))
+            .unwrap()
+            .clone();
+        clippy.input_drvs.remove(&rustc);
+        clippy.input_srcs.clear();
+
+        let causes = explain(&old, &new).unwrap();
+        let root_causes = &causes[0].causes;
+        assert!(root_causes.contains(&Cause::InputAdded("rustc-1.86.0".into())));
+        assert!(root_causes.contains(&Cause::SourceAdded("source".into())));
+    }
+}
@@ -0,0 +1,661 @@
+//! Asserts the exact commands each subcommand runs, using the scripted fake runner.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use one::{runner::Fake, Cli};
+
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../..")
+        .canonicalize()
+        .unwrap()
+}
+
+/// Run `one <args>` against the repository root and return the rendered commands
+fn run(fake: &Fake, args: &[&str]) -> (anyhow::Result<()>, Vec<String>) {
+    run_in(&root(), fake, args)
+}
+
+fn run_in(root: &Path, fake: &Fake, args: &[&str]) -> (anyhow::Result<()>, Vec<String>) {
+    // Keep the developer's own config out of the tests, and the history out of the tree
+    let user_config = Path::new(env!("CARGO_TARGET_TMPDIR")).join("one-user-config.toml");
+    std::fs::write(&user_config, "[history]\nrecord = false\n").unwrap();
+    std::env::set_var("ONE_USER_CONFIG", user_config);
+    let mut argv = vec!["one", "--root", root.to_str().unwrap()];
+    argv.extend(args);
+
+    let result = one::run(Cli::try_parse_from(argv).unwrap(), fake);
+    (result, fake.commands(root))
+}
+
+fn pinned_nixpkgs() -> String {
+    let lock: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(root().join("src/lang/rust/test/flake.lock")).unwrap(),
+    )
+    .unwrap();
+    let rev = lock["nodes"]["nixpkgs"]["locked"]["rev"].as_str().unwrap();
+    format!("github:NixOS/nixpkgs/{rev}")
+}
+
+const EVAL_JOBS: &str = include_str!("../testdata/nix-eval-jobs.jsonl");
+
+#[test]
+fn build() {
+    let fake = Fake::new();
+    let (result, commands) = run(&fake, &["build", "-a", "#foo", "--nix-arg=--impure"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        ["nix build --accept-flake-config --print-build-logs --impure $ROOT#foo"]
+    );
+}
+
+#[test]
+fn build_with_progress() {
+    let fake = Fake::new().respond("nix build", include_str!("../testdata/internal-json.log"));
+    let (result, commands) = run(&fake, &["build", "--progress", "--nix-arg=--keep-going"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        ["nix build --log-format internal-json --accept-flake-config --print-build-logs --keep-going $ROOT"]
+    );
+}
+
+#[test]
+fn check() {
+    let fake = Fake::new();
+    let (result, commands) = run(&fake, &["check", "-v", "--no-build"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        ["nix flake check --accept-flake-config --print-build-logs -L --no-build --no-write-lock-file $ROOT"]
+    );
+}
+
+#[test]
+fn check_fast_builds_uncached_checks() {
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .respond("nix-eval-jobs", EVAL_JOBS);
+    let (result, commands) = run(&fake, &["check", "--fast", "--nix-arg=--keep-going"]);
+
+    // The recorded output contains an evaluation error
+    assert_eq!(result.unwrap_err().to_string(), "1 of 5 checks failed");
+    assert_eq!(
+        commands,
+        [
+            "nix eval --raw --impure --expr builtins.currentSystem",
+            "nix-eval-jobs --gc-roots-dir $ROOT/.one/gcroots --check-cache-status --flake $ROOT#checks.x86_64-linux",
+            "nix build --no-link --print-build-logs /nix/store/3mq1r7h5qk3v9yd0w6a9cgkmr7m8rwl5-cargo-test.drv^* /nix/store/9b0lqg3pz4cd5a7w1y2x8n0m6k4j2h1g-cargo-doc.drv^* --keep-going",
+        ]
+    );
+}
+
+#[test]
+fn check_report_records_each_check() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("checks.json");
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .respond("nix-eval-jobs", EVAL_JOBS)
+        .fail("nix build --no-link --print-build-logs /nix/store/9b0lqg3pz4cd5a7w1y2x8n0m6k4j2h1g")
+        .respond("nix log", "building\nerror: missing docs\n");
+    let (result, commands) = run(&fake, &["check", "--report", path.to_str().unwrap()]);
+
+    assert_eq!(result.unwrap_err().to_string(), "2 of 5 checks failed");
+    assert_eq!(
+        commands[2..],
+        [
+            "nix build --no-link --print-build-logs /nix/store/3mq1r7h5qk3v9yd0w6a9cgkmr7m8rwl5-cargo-test.drv^*",
+            "nix build --no-link --print-build-logs /nix/store/9b0lqg3pz4cd5a7w1y2x8n0m6k4j2h1g-cargo-doc.drv^*",
+            "nix log /nix/store/9b0lqg3pz4cd5a7w1y2x8n0m6k4j2h1g-cargo-doc.drv",
+        ]
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let statuses: Vec<_> = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| (c["attr"].as_str().unwrap(), c["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            ("cargoClippy", "cached"),
+            ("cargoTest", "passed"),
+            ("cargoFmt", "cached"),
+            ("cargoDoc", "failed"),
+            ("broken", "failed"),
+        ]
+    );
+    assert_eq!(report["checks"][3]["log"], "building\nerror: missing docs");
+}
+
+#[test]
+fn check_since_builds_affected_outputs() {
+    let fake = Fake::new()
+        .respond(
+            "git diff",
+            "src/lang/rust/checks/clippy/src/main.rs\nsrc/cli/src/lib.rs\n",
+        )
+        .respond("git ls-files", "docs/new-page.md\n")
+        .respond(
+            "nix eval --accept-flake-config --json",
+            r#"["nixpkgs-clippy","nixpkgs-latest-clippy"]"#,
+        )
+        .respond("nix eval", "x86_64-linux");
+    let (result, commands) = run(&fake, &["check", "--since", "main"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        [
+            "git diff --name-only main",
+            "git ls-files --others --exclude-standard",
+            "nix eval --raw --impure --expr builtins.currentSystem",
+            "nix eval --accept-flake-config --json $ROOT#checks.x86_64-linux \
+             --apply builtins.attrNames",
+            "nix build --accept-flake-config --no-link --print-build-logs \
+             $ROOT#checks.x86_64-linux.nixpkgs-clippy \
+             $ROOT#checks.x86_64-linux.nixpkgs-latest-clippy \
+             $ROOT#packages.x86_64-linux.book",
+        ]
+    );
+
+    // Checks the flake doesn't provide on this system are skipped
+    let fake = Fake::new()
+        .respond("git diff", "src/lang/rust/checks/codesign/src/main.rs\n")
+        .respond(
+            "nix eval --accept-flake-config --json",
+            r#"["nixpkgs-clippy","nixpkgs-latest-clippy"]"#,
+        )
+        .respond("nix eval", "x86_64-linux");
+    let (result, commands) = run(&fake, &["check", "--since", "main"]);
+    result.unwrap();
+    assert_eq!(
+        commands.last().unwrap(),
+        "nix eval --accept-flake-config --json $ROOT#checks.x86_64-linux \
+         --apply builtins.attrNames"
+    );
+
+    // A change to the flake itself runs everything
+    let fake = Fake::new().respond("git diff", "flake.nix\n");
+    let (result, commands) = run(&fake, &["check", "--since", "main"]);
+    result.unwrap();
+    assert_eq!(
+        commands.last().unwrap(),
+        "nix flake check --accept-flake-config --print-build-logs --no-write-lock-file $ROOT"
+    );
+}
+
+#[test]
+fn ci() {
+    let fake = Fake::new();
+    let (result, commands) = run(&fake, &["ci", "--", "--no-build"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        ["nix run --accept-flake-config $ROOT#ci -- --no-build"]
+    );
+}
+
+#[test]
+fn develop() {
+    let fake = Fake::new();
+    let (result, commands) = run(
+        &fake,
+        &[
+            "develop",
+            "-a",
+            "rust",
+            "--command",
+            "cargo",
+            "--",
+            "test",
+            "--all",
+        ],
+    );
+    result.unwrap();
+    assert_eq!(
+        commands,
+        ["nix develop --accept-flake-config $ROOT#rust --command cargo test --all"]
+    );
+}
+
+#[test]
+fn test_example_follows_ci() {
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .respond("nix-eval-jobs", "");
+    let (result, commands) = run(&fake, &["test", "quick-start"]);
+    result.unwrap();
+
+    let example = "$ROOT/src/lang/rust/examples/quick-start";
+    let overrides = format!(
+        "--override-input one-for-all path:$ROOT --override-input nixpkgs {}",
+        pinned_nixpkgs()
+    );
+    assert_eq!(
+        commands,
+        [
+            format!("cp $ROOT/src/lang/rust/test/flake.lock {example}/flake.lock"),
+            format!("git add -N --force {example}/flake.lock"),
+            "nix eval --raw --impure --expr builtins.currentSystem".to_string(),
+            format!("nix-eval-jobs --gc-roots-dir $ROOT/.one/gcroots --check-cache-status --flake {example}#checks.x86_64-linux {overrides}"),
+            format!("nix flake check --no-build --no-write-lock-file --accept-flake-config --print-build-logs {example} {overrides}"),
+            format!("git rm -f --quiet {example}/flake.lock"),
+        ]
+    );
+}
+
+#[test]
+fn test_example_cleans_up_after_failure() {
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .fail("nix-eval-jobs");
+    let (result, commands) = run(&fake, &["test", "quick-start"]);
+
+    assert!(result.is_err());
+    assert_eq!(
+        commands.last().unwrap(),
+        "git rm -f --quiet $ROOT/src/lang/rust/examples/quick-start/flake.lock"
+    );
+}
+
+#[test]
+fn test_all_checks_selected_examples() {
+    let tmp = tempfile::tempdir().unwrap();
+    let logs = tmp.path().join("logs");
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .fail("nix flake check --no-build --no-write-lock-file --accept-flake-config --print-build-logs $ROOT/src/lang/rust/examples/trunk ".replace("$ROOT", root().to_str().unwrap()).as_str());
+    let (result, commands) = run(
+        &fake,
+        &[
+            "test",
+            "--all",
+            "--only",
+            "trunk*",
+            "--log-dir",
+            logs.to_str().unwrap(),
+        ],
+    );
+
+    assert_eq!(result.unwrap_err().to_string(), "1 of 2 examples failed");
+    let checked: Vec<_> = commands
+        .iter()
+        .filter(|c| c.starts_with("nix flake check"))
+        .collect();
+    assert_eq!(checked.len(), 2);
+    assert!(logs.join("trunk.log").exists());
+    assert!(logs.join("trunk-workspace.log").exists());
+}
+
+#[test]
+fn dry_run_is_global() {
+    let cli = Cli::try_parse_from(["one", "gc", "--keep-last", "1", "--dry-run"]).unwrap();
+    assert!(cli.dry_run);
+    let cli = Cli::try_parse_from(["one", "--dry-run", "build"]).unwrap();
+    assert!(cli.dry_run);
+}
+
+#[test]
+fn config_profiles_and_nix_args() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    std::fs::write(
+        root.join("one.toml"),
+        r#"
+        [nix]
+        extra-args = ["--option", "sandbox", "true"]
+
+        [build]
+        attribute = "default"
+
+        [profiles.ci.build]
+        nix-args = ["--keep-going"]
+        "#,
+    )
+    .unwrap();
+
+    let fake = Fake::new();
+    let (result, _) = run_in(root, &fake, &["--profile", "ci", "build", "--nix-arg=-L"]);
+    result.unwrap();
+    let (result, commands) = run_in(root, &fake, &["build", "-a", "other"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        [
+            "nix build --accept-flake-config --print-build-logs --option sandbox true --keep-going -L $ROOT#default",
+            "nix build --accept-flake-config --print-build-logs --option sandbox true $ROOT#other",
+        ]
+    );
+
+    let (result, _) = run_in(root, &fake, &["--profile", "nope", "build"]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn history_records_runs() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    std::fs::write(root.join("one.toml"), "[history]\nrecord = true\n").unwrap();
+
+    let fake = Fake::new().respond("nix build", include_str!("../testdata/internal-json.log"));
+    let (result, _) = run_in(root, &fake, &["build", "--progress", "-a", "checks"]);
+    result.unwrap();
+    let fake = Fake::new();
+    let (result, _) = run_in(root, &fake, &["--dry-run", "build"]);
+    result.unwrap();
+
+    let history = std::fs::read_to_string(root.join(".one/history.jsonl")).unwrap();
+    let records: Vec<serde_json::Value> = history
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<_> = records
+        .iter()
+        .map(|r| format!("{} {} {}", r["kind"], r["attr"], r["outcome"]).replace('"', ""))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "run checks passed",
+            "derivation cargo-deps-0.1.0 passed",
+            "derivation cargo-test failed",
+            "derivation cargo-doc passed",
+        ]
+    );
+    assert_eq!(records[0]["downloaded"], 1);
+    assert!(records.iter().all(|r| r["duration_ms"].is_u64()));
+
+    let (result, _) = run_in(root, &fake, &["history", "cargo-*"]);
+    result.unwrap();
+}
+
+#[test]
+fn doctor_queries_nix() {
+    let fake = Fake::new()
+        .respond("nix --version", "nix (Nix) 2.24.9\n")
+        .respond(
+            "nix config show",
+            r#"{"experimental-features": {"value": ["flakes", "nix-command"]}}"#,
+        );
+    // The outcome depends on the machine's PATH and signing key
+    let (_, commands) = run(&fake, &["doctor"]);
+    assert_eq!(commands, ["nix --version", "nix config show --json"]);
+}
+
+#[test]
+fn why_rebuild_compares_revisions() {
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .respond("git rev-parse", "0123abcd\n")
+        .respond(
+            "nix derivation show --recursive --accept-flake-config git+file",
+            include_str!("../testdata/drv-before.json"),
+        )
+        .respond(
+            "nix derivation show",
+            include_str!("../testdata/drv-after.json"),
+        );
+    let (result, commands) = run(&fake, &["why-rebuild", "nixpkgs-clippy", "--from", "main"]);
+    result.unwrap();
+    assert_eq!(
+        commands,
+        [
+            "nix eval --raw --impure --expr builtins.currentSystem",
+            "git rev-parse --verify main^{commit}",
+            "nix derivation show --recursive --accept-flake-config \
+             git+file://$ROOT?rev=0123abcd#checks.x86_64-linux.nixpkgs-clippy",
+            "nix derivation show --recursive --accept-flake-config \
+             $ROOT#checks.x86_64-linux.nixpkgs-clippy",
+        ]
+    );
+}
+
+#[test]
+fn lock_diff_reads_old_revision_with_git() {
+    let lock = std::fs::read_to_string(root().join("src/lang/rust/test/flake.lock")).unwrap();
+    let old = lock.replace(
+        "08b8f92ac6354983f5382124fef6006cade4a1c1",
+        "0000000000000000000000000000000000000000",
+    );
+    assert_ne!(lock, old, "test lock no longer pins this nixpkgs revision");
+    let fake = Fake::new().respond("git show", &old);
+    let (result, commands) = run(&fake, &["lock", "diff", "main"]);
+    result.unwrap();
+    assert_eq!(commands, ["git show main:src/lang/rust/test/flake.lock"]);
+}
+
+#[test]
+fn cache_push_adds_the_closure() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let key = dir.join("secret.key");
+    std::fs::write(
+        &key,
+        "test-cache-1:BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwfqSmxj4pxSCr71UHsTLsX5lUd2rr6+e5JCHupp\
+         FEbSLA==",
+    )
+    .unwrap();
+    let cache = dir.join("cache");
+    let store = root().join("src/cli/testdata/cache-store");
+    let hello = "/nix/store/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs-hello-2.12";
+    let greeting = "/nix/store/9y8pmvk8gdwwznmkzxa6pwyah52xy3nk-greeting-1.0";
+    let fake = Fake::new()
+        .respond("nix eval", "x86_64-linux")
+        .respond("nix build", &format!("{hello}\n"))
+        .respond(
+            "nix path-info",
+            &format!(
+                r#"{{"{hello}": {{"references": ["{greeting}"], "deriver": null}},
+                    "{greeting}": {{"references": [], "deriver": null}}}}"#
+            ),
+        );
+    let args = [
+        "cache",
+        "push",
+        "hello",
+        "--dir",
+        cache.to_str().unwrap(),
+        "--secret-key-file",
+        key.to_str().unwrap(),
+        "--store",
+        store.to_str().unwrap(),
+    ];
+    let (result, commands) = run(&fake, &args);
+    result.unwrap();
+    let store_arg = "$ROOT/src/cli/testdata/cache-store";
+    assert_eq!(
+        commands[1..],
+        [
+            format!(
+                "nix build --no-link --print-out-paths --accept-flake-config --store {store_arg} \
+                 $ROOT#checks.x86_64-linux.hello"
+            ),
+            format!("nix path-info --recursive --json --store {store_arg} {hello}"),
+        ]
+    );
+
+    let narinfo =
+        std::fs::read_to_string(dir.join("cache/0a2pbqkkxk5hq6b0lqsy3m9r8ad9yxgs.narinfo"))
+            .unwrap();
+    assert!(
+        narinfo.starts_with(&format!("StorePath: {hello}\nURL: nar/")),
+        "{narinfo}"
+    );
+    assert!(narinfo.contains("\nReferences: 9y8pmvk8gdwwznmkzxa6pwyah52xy3nk-greeting-1.0\n"));
+    assert!(narinfo.contains("\nSig: test-cache-1:"), "{narinfo}");
+    assert!(dir
+        .join("cache/9y8pmvk8gdwwznmkzxa6pwyah52xy3nk.narinfo")
+        .exists());
+    assert!(dir.join("cache/nix-cache-info").exists());
+    let nars = std::fs::read_dir(dir.join("cache/nar")).unwrap().count();
+    assert_eq!(nars, 2);
+
+    // Pushing again finds everything cached
+    let (result, _) = run(&fake, &args);
+    result.unwrap();
+    assert_eq!(std::fs::read_dir(dir.join("cache/nar")).unwrap().count(), 2);
+}
+
+#[test]
+fn bisect_finds_the_first_bad_commit() {
+    let good = "0a1b2c3d4e5f60718293a4b5c6d7e8f901234567";
+    let bad = "1b2c3d4e5f60718293a4b5c6d7e8f90123456789";
+    let fake = Fake::new()
+        .respond("nix eval --raw --impure", "x86_64-linux")
+        .respond("git rev-parse --verify v1.0", &format!("{good}\n"))
+        .respond("git rev-parse --verify main", &format!("{bad}\n"))
+        .respond("git log", &format!("{bad} Bump nixpkgs\n"))
+        .respond(
+            "nix eval",
+            "/nix/store/0f4gx0hm0k6dq2v0c0n3rkvq4d0w7bq1-clippy.drv",
+        )
+        // Fails at `bad`, passes at `good`, fails at the commit in between
+        .fail_once("nix build")
+        .respond_once("nix build", "")
+        .fail("nix build")
+        .respond("nix log", "error: unused import\n")
+        .respond(
+            "git bisect start",
+            "Bisecting: 0 revisions left to test after this\n",
+        )
+        .respond(
+            "git bisect bad",
+            &format!("{bad} is the first bad commit\n"),
+        );
+    let (result, commands) = run(
+        &fake,
+        &[
+            "bisect",
+            "--check",
+            "nixpkgs-clippy",
+            "--good",
+            "v1.0",
+            "--bad",
+            "main",
+            "--nix-arg=--offline",
+        ],
+    );
+    result.unwrap();
+    let drv = "/nix/store/0f4gx0hm0k6dq2v0c0n3rkvq4d0w7bq1-clippy.drv";
+    let test = [
+        "git log -1 '--format=%H %s'".to_string(),
+        "nix eval --raw --accept-flake-config \
+         $ROOT/.one/bisect#checks.x86_64-linux.nixpkgs-clippy.drvPath --offline"
+            .to_string(),
+        format!("nix build --no-link --print-build-logs {drv}^* --offline"),
+    ];
+    let log = format!("nix log {drv}");
+    let mut expected = vec![
+        "nix eval --raw --impure --expr builtins.currentSystem".to_string(),
+        "git rev-parse --verify v1.0^{commit}".to_string(),
+        "git rev-parse --verify main^{commit}".to_string(),
+        format!("git worktree add --detach $ROOT/.one/bisect {bad}"),
+    ];
+    expected.extend(test.clone());
+    expected.push(log.clone());
+    expected.push(format!("git checkout --quiet --detach {good}"));
+    expected.extend(test.clone());
+    expected.push(format!("git bisect start {bad} {good}"));
+    expected.extend(test);
+    expected.push(log);
+    expected.extend([
+        "git bisect bad".to_string(),
+        "git bisect reset".to_string(),
+        "git worktree remove --force $ROOT/.one/bisect".to_string(),
+    ]);
+    assert_eq!(commands, expected);
+}
+
+#[test]
+fn bisect_refuses_a_passing_bad_commit() {
+    let fake = Fake::new()
+        .respond("nix eval --raw --impure", "x86_64-linux")
+        .respond(
+            "git rev-parse",
+            "1b2c3d4e5f60718293a4b5c6d7e8f90123456789\n",
+        )
+        .respond(
+            "git log",
+            "1b2c3d4e5f60718293a4b5c6d7e8f90123456789 Fix clippy\n",
+        )
+        .respond(
+            "nix eval",
+            "/nix/store/0f4gx0hm0k6dq2v0c0n3rkvq4d0w7bq1-clippy.drv",
+        );
+    let (result, commands) = run(
+        &fake,
+        &["bisect", "--check", "nixpkgs-clippy", "--good", "v1.0"],
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "checks.x86_64-linux.nixpkgs-clippy passes at 1b2c3d4e5f60 Fix clippy, nothing to bisect"
+    );
+    // Cleaned up anyway
+    assert_eq!(
+        commands[commands.len() - 2..],
+        [
+            "git bisect reset",
+            "git worktree remove --force $ROOT/.one/bisect"
+        ]
+    );
+}
+
+#[test]
+fn bisect_refuses_a_failing_good_commit() {
+    let fake = Fake::new()
+        .respond("nix eval --raw --impure", "x86_64-linux")
+        .respond(
+            "git rev-parse",
+            "1b2c3d4e5f60718293a4b5c6d7e8f90123456789\n",
+        )
+        .respond(
+            "git log",
+            "1b2c3d4e5f60718293a4b5c6d7e8f90123456789 Bump nixpkgs\n",
+        )
+        .respond(
+            "nix eval",
+            "/nix/store/0f4gx0hm0k6dq2v0c0n3rkvq4d0w7bq1-clippy.drv",
+        )
+        .fail("nix build");
+    let (result, commands) = run(
+        &fake,
+        &["bisect", "--check", "nixpkgs-clippy", "--good", "v1.0"],
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "checks.x86_64-linux.nixpkgs-clippy already fails at 1b2c3d4e5f60 Bump nixpkgs, \
+         pass an older --good"
+    );
+    assert!(!commands.iter().any(|c| c.starts_with("git bisect start")));
+}
+
+#[test]
+fn init_copies_and_pins_a_template() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dest = tmp.path().join("hello");
+    let fake = Fake::new();
+    let (result, commands) = run(
+        &fake,
+        &[
+            "init",
+            "quick-start",
+            dest.to_str().unwrap(),
+            "--name",
+            "hello",
+            "--ref",
+            "v1.0",
+        ],
+    );
+    result.unwrap();
+    assert!(commands.is_empty());
+
+    let manifest = std::fs::read_to_string(dest.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"hello\""), "{manifest}");
+    let flake = std::fs::read_to_string(dest.join("flake.nix")).unwrap();
+    assert!(flake.contains(r#"one-for-all.url = "github:l1ne-company/one-for-all/v1.0";"#));
+}
@@ -0,0 +1,44 @@
+//! Proof model shared by build-signer, build-verifier and zk-prover.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub mod nar;
+pub mod statement;
+pub mod summary;
+
+pub use statement::{Facts, Inputs, Predicate, Statement};
+pub use summary::{parse_test_log, TestSummary};
+
+/// Signed part of a format version 1 proof
+///
+/// Field order matters: the signature covers `serde_json::to_vec(&payload)`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Payload {
+    pub commit: String,
+    pub flake_lock_hash: String,
+    pub build_command: String,
+    pub artifact_tar_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drv_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_log_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement: Option<Statement>,
+    pub timestamp: String,
+    pub nonce: String,
+}
+
+/// Format version 1 proof: an Ed25519 signature over the payload
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Proof {
+    pub payload: Payload,
+    pub signature: String,
+    pub public_key: String,
+    pub format_version: u8,
+}
+
+/// Hex-encoded SHA256 of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
@@ -0,0 +1,277 @@
+//! Nix Archive (NAR) serialization and hashing, without the nix CLI.
+//!
+//! The output is byte-for-byte what `nix-store --dump` produces, so [`nar_hash`] matches
+//! the `narHash` Nix records for a store path (`nix path-info --json`).
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+const NAR_MAGIC: &str = "nix-archive-1";
+
+/// Alphabet used by Nix's base32 encoding (no `e`, `o`, `u`, `t`)
+pub const NIX_BASE32_ALPHABET: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Write the NAR serialization of `path` to `out`
+pub fn dump(path: &Path, out: &mut impl Write) -> Result<()> {
+    write_str(out, NAR_MAGIC)?;
+    dump_node(path, out)
+}
+
+/// SHA256 of the NAR serialization of `path`, formatted like Nix's `narHash` (`sha256:<base32>`)
+pub fn nar_hash(path: &Path) -> Result<String> {
+    let mut hasher = HashWriter(Sha256::new());
+    dump(path, &mut hasher)?;
+    Ok(format!("sha256:{}", nix_base32(&hasher.0.finalize())))
+}
+
+/// Encode bytes the way Nix prints hashes in base32
+pub fn nix_base32(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+
+    let len = (bytes.len() * 8 - 1) / 5 + 1;
+    (0..len)
+        .rev()
+        .map(|n| {
+            let b = n * 5;
+            let i = b / 8;
+            let j = b % 8;
+            let low = bytes[i] >> j;
+            let high = if j > 3 && i + 1 < bytes.len() {
+                bytes[i + 1] << (8 - j)
+            } else {
+                0
+            };
+            NIX_BASE32_ALPHABET[((low | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+fn dump_node(path: &Path, out: &mut impl Write) -> Result<()> {
+    let metadata =
+        fs::symlink_metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?;
+    let file_type = metadata.file_type();
+
+    write_str(out, "(")?;
+    write_str(out, "type")?;
+
+    if file_type.is_symlink() {
+        let target = fs::read_link(path)
+            .with_context(|| format!("Failed to read symlink {}", path.display()))?;
+        write_str(out, "symlink")?;
+        write_str(out, "target")?;
+        write_bytes(out, os_bytes(target.as_os_str())?)?;
+    } else if file_type.is_file() {
+        write_str(out, "regular")?;
+        if is_executable(&metadata) {
+            write_str(out, "executable")?;
+            write_str(out, "")?;
+        }
+        write_str(out, "contents")?;
+        // Streamed rather than read into memory: build outputs can be large
+        let len = metadata.len();
+        out.write_all(&len.to_le_bytes())?;
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let copied = io::copy(&mut file, out)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if copied != len {
+            bail!("{} changed while it was being read", path.display());
+        }
+        write_padding(out, len)?;
+    } else if file_type.is_dir() {
+        write_str(out, "directory")?;
+
+        let mut entries = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory {}", path.display()))?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<io::Result<Vec<_>>>()
+            .with_context(|| format!("Failed to read directory {}", path.display()))?;
+        // NAR entries are sorted by raw name bytes
+        entries.sort_by(|a, b| a.as_encoded_bytes().cmp(b.as_encoded_bytes()));
+
+        for name in entries {
+            write_str(out, "entry")?;
+            write_str(out, "(")?;
+            write_str(out, "name")?;
+            write_bytes(out, os_bytes(&name)?)?;
+            write_str(out, "node")?;
+            dump_node(&path.join(&name), out)?;
+            write_str(out, ")")?;
+        }
+    } else {
+        bail!("Unsupported file type at {}", path.display());
+    }
+
+    write_str(out, ")")
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> Result<()> {
+    write_bytes(out, s.as_bytes())
+}
+
+/// NAR strings: little-endian u64 length, the bytes, then zero padding to 8 bytes
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(bytes)?;
+    write_padding(out, bytes.len() as u64)
+}
+
+fn write_padding(out: &mut impl Write, len: u64) -> Result<()> {
+    let padding = ((8 - len % 8) % 8) as usize;
+    out.write_all(&[0u8; 8][..padding])?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn os_bytes(s: &std::ffi::OsStr) -> Result<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(s.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn os_bytes(s: &std::ffi::OsStr) -> Result<&[u8]> {
+    s.to_str()
+        .map(str::as_bytes)
+        .with_context(|| format!("Non UTF-8 file name: {s:?}"))
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expected NAR bytes built from (tag, bytes) pairs
+    fn nar(strings: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for s in strings {
+            write_bytes(&mut out, s).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn nix_base32_matches_nix() {
+        // `nix hash convert --to nix32 sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=`
+        let empty_sha256 = Sha256::digest(b"");
+        assert_eq!(
+            nix_base32(&empty_sha256),
+            "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+        );
+    }
+
+    #[test]
+    fn regular_file_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hello");
+        fs::write(&file, "hello\n").unwrap();
+
+        let mut out = Vec::new();
+        dump(&file, &mut out).unwrap();
+        assert_eq!(
+            out,
+            nar(&[
+                b"nix-archive-1",
+                b"(",
+                b"type",
+                b"regular",
+                b"contents",
+                b"hello\n",
+                b")"
+            ])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directory_layout_is_sorted_and_marks_executables() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("out");
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/tool"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(root.join("bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("bin/tool", root.join("a-link")).unwrap();
+
+        let mut out = Vec::new();
+        dump(&root, &mut out).unwrap();
+        assert_eq!(
+            out,
+            nar(&[
+                b"nix-archive-1",
+                b"(",
+                b"type",
+                b"directory",
+                b"entry",
+                b"(",
+                b"name",
+                b"a-link",
+                b"node",
+                b"(",
+                b"type",
+                b"symlink",
+                b"target",
+                b"bin/tool",
+                b")",
+                b")",
+                b"entry",
+                b"(",
+                b"name",
+                b"bin",
+                b"node",
+                b"(",
+                b"type",
+                b"directory",
+                b"entry",
+                b"(",
+                b"name",
+                b"tool",
+                b"node",
+                b"(",
+                b"type",
+                b"regular",
+                b"executable",
+                b"",
+                b"contents",
+                b"#!/bin/sh\n",
+                b")",
+                b")",
+                b")",
+                b")",
+                b")",
+            ])
+        );
+
+        let hash = nar_hash(&root).unwrap();
+        assert!(hash.starts_with("sha256:"));
+        assert_eq!(hash.len(), "sha256:".len() + 52);
+    }
+}
@@ -0,0 +1,269 @@
+//! Typed description of what a proof attests.
+//!
+//! A [`Statement`] binds a set of [`Inputs`] (hashes identifying what was built and tested),
+//! records [`Facts`] observed while building, and lists the [`Predicate`]s being claimed.
+//! Predicates have a small textual syntax so they can be passed on the command line:
+//!
+//! ```text
+//! tests-passed
+//! build-succeeded
+//! coverage >= 80
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::summary::TestSummary;
+
+/// Hashes identifying exactly what the statement is about
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Inputs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_tree_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flake_lock_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drv_hash: Option<String>,
+    /// Nix store path of the build output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out_path: Option<String>,
+    /// NAR hash of the build output, in Nix's `sha256:<base32>` form (see [`crate::nar`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nar_hash: Option<String>,
+    /// SHA256 of the test output the `tests` fact was extracted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_summary_digest: Option<String>,
+}
+
+/// Observations the predicates are evaluated against
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Facts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tests: Option<TestSummary>,
+    /// Line coverage in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+}
+
+/// A claim that can be evaluated against a statement
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Predicate {
+    /// At least one test ran and none failed
+    TestsPassed,
+    /// The build completed (the statement must bind the derivation)
+    BuildSucceeded,
+    /// Coverage is at least this many percent
+    CoverageAtLeast(f64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Statement {
+    pub inputs: Inputs,
+    #[serde(default)]
+    pub facts: Facts,
+    pub claims: Vec<Predicate>,
+}
+
+impl Statement {
+    /// Check that every claimed predicate holds
+    pub fn evaluate(&self) -> Result<()> {
+        for claim in &self.claims {
+            claim
+                .evaluate(self)
+                .with_context(|| format!("Claim `{claim}` does not hold"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the claims include `required` (or something stronger)
+    pub fn claims(&self, required: &Predicate) -> bool {
+        self.claims.iter().any(|claim| claim.implies(required))
+    }
+}
+
+impl Predicate {
+    pub fn evaluate(&self, statement: &Statement) -> Result<()> {
+        match *self {
+            Predicate::TestsPassed => {
+                let tests = statement
+                    .facts
+                    .tests
+                    .context("No test results recorded")?;
+                if statement.inputs.test_summary_digest.is_none() {
+                    bail!("Test results are not bound to a test output digest");
+                }
+                if tests.failed > 0 {
+                    bail!("{} test(s) failed", tests.failed);
+                }
+                if tests.passed == 0 {
+                    bail!("No tests ran");
+                }
+            }
+            Predicate::BuildSucceeded => {
+                if statement.inputs.drv_hash.is_none() {
+                    bail!("No derivation recorded");
+                }
+            }
+            Predicate::CoverageAtLeast(threshold) => {
+                let coverage = statement.facts.coverage.context("No coverage recorded")?;
+                if coverage < threshold {
+                    bail!("Coverage is {coverage}%");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether holding `self` means `other` holds too
+    pub fn implies(&self, other: &Predicate) -> bool {
+        match (self, other) {
+            (Predicate::CoverageAtLeast(a), Predicate::CoverageAtLeast(b)) => a >= b,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s {
+            "tests-passed" => return Ok(Predicate::TestsPassed),
+            "build-succeeded" => return Ok(Predicate::BuildSucceeded),
+            _ => {}
+        }
+
+        if let Some(threshold) = s
+            .strip_prefix("coverage")
+            .and_then(|rest| rest.trim_start().strip_prefix(">="))
+        {
+            let threshold = threshold.trim().trim_end_matches('%').trim_end();
+            let threshold: f64 = threshold
+                .parse()
+                .with_context(|| format!("Invalid coverage threshold: {threshold:?}"))?;
+            if !(0.0..=100.0).contains(&threshold) {
+                bail!("Coverage threshold must be between 0 and 100, got {threshold}");
+            }
+            return Ok(Predicate::CoverageAtLeast(threshold));
+        }
+
+        bail!(
+            "Unknown predicate {s:?} (expected `tests-passed`, `build-succeeded` or `coverage >= N`)"
+        )
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::TestsPassed => f.write_str("tests-passed"),
+            Predicate::BuildSucceeded => f.write_str("build-succeeded"),
+            Predicate::CoverageAtLeast(threshold) => write!(f, "coverage >= {threshold}"),
+        }
+    }
+}
+
+impl TryFrom<String> for Predicate {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Predicate> for String {
+    fn from(predicate: Predicate) -> Self {
+        predicate.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passing() -> Statement {
+        Statement {
+            inputs: Inputs {
+                drv_hash: Some("abc-foo.drv".into()),
+                test_summary_digest: Some("00".repeat(32)),
+                ..Default::default()
+            },
+            facts: Facts {
+                tests: Some(TestSummary {
+                    passed: 3,
+                    failed: 0,
+                }),
+                coverage: Some(85.5),
+            },
+            claims: vec![
+                Predicate::TestsPassed,
+                Predicate::BuildSucceeded,
+                Predicate::CoverageAtLeast(80.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn predicate_syntax_roundtrip() {
+        for (input, expected) in [
+            ("tests-passed", Predicate::TestsPassed),
+            (" build-succeeded ", Predicate::BuildSucceeded),
+            ("coverage >= 80", Predicate::CoverageAtLeast(80.0)),
+            ("coverage>=72.5%", Predicate::CoverageAtLeast(72.5)),
+        ] {
+            let parsed: Predicate = input.parse().unwrap();
+            assert_eq!(parsed, expected);
+            assert_eq!(parsed.to_string().parse::<Predicate>().unwrap(), expected);
+        }
+
+        assert!("coverage >= 101".parse::<Predicate>().is_err());
+        assert!("coverage > 80".parse::<Predicate>().is_err());
+        assert!("tests-failed".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn claims_serialize_as_strings() {
+        let json = serde_json::to_value(passing()).unwrap();
+        assert_eq!(
+            json["claims"],
+            serde_json::json!(["tests-passed", "build-succeeded", "coverage >= 80"])
+        );
+        let back: Statement = serde_json::from_value(json).unwrap();
+        assert_eq!(back, passing());
+    }
+
+    #[test]
+    fn passing_statement_evaluates() {
+        passing().evaluate().unwrap();
+    }
+
+    #[test]
+    fn false_claims_are_rejected() {
+        let mut failing_tests = passing();
+        failing_tests.facts.tests = Some(TestSummary {
+            passed: 2,
+            failed: 1,
+        });
+        assert!(failing_tests.evaluate().is_err());
+
+        let mut low_coverage = passing();
+        low_coverage.facts.coverage = Some(79.9);
+        assert!(low_coverage.evaluate().is_err());
+
+        let mut unbound = passing();
+        unbound.inputs.drv_hash = None;
+        assert!(unbound.evaluate().is_err());
+    }
+
+    #[test]
+    fn stronger_claims_satisfy_weaker_requirements() {
+        let statement = passing();
+        assert!(statement.claims(&Predicate::CoverageAtLeast(50.0)));
+        assert!(!statement.claims(&Predicate::CoverageAtLeast(90.0)));
+        assert!(statement.claims(&Predicate::TestsPassed));
+    }
+}
@@ -0,0 +1,108 @@
+//! Extract pass/fail counts from test runner output.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TestSummary {
+    pub passed: u64,
+    pub failed: u64,
+}
+
+/// Sum every libtest (`test result: ok. 3 passed; 0 failed; ...`) and cargo-nextest
+/// (`Summary [...] 3 tests run: 3 passed, 0 skipped`, or `1 test run:` for a single test)
+/// summary line in `log`
+pub fn parse_test_log(log: &str) -> Result<TestSummary> {
+    let mut summary = TestSummary::default();
+    let mut found = false;
+
+    for line in log.lines() {
+        let counts = if let Some((_, rest)) = line.split_once("test result:") {
+            rest.split(';')
+        } else if let Some((_, rest)) = line
+            .split_once("tests run:")
+            .or_else(|| line.split_once("test run:"))
+        {
+            rest.split(',')
+        } else {
+            continue;
+        };
+
+        found = true;
+        for part in counts {
+            let mut words = part
+                .split_whitespace()
+                .skip_while(|w| w.parse::<u64>().is_err());
+            let (Some(n), Some(kind)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let n: u64 = n.parse().expect("skip_while stopped on a number");
+            match kind.trim_end_matches([';', ',', '.']) {
+                "passed" => summary.passed += n,
+                "failed" => summary.failed += n,
+                _ => {}
+            }
+        }
+    }
+
+    if !found {
+        bail!("No test result summary found in test log");
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_libtest_summaries() {
+        let log = "\
+running 2 tests
+test tests::a ... ok
+test tests::b ... ok
+
+test result: ok. 2 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+running 3 tests
+test result: FAILED. 2 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+";
+        assert_eq!(
+            parse_test_log(log).unwrap(),
+            TestSummary {
+                passed: 4,
+                failed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn parses_nextest_summary() {
+        let log = "     Summary [   0.012s] 5 tests run: 4 passed, 1 failed, 0 skipped\n";
+        assert_eq!(
+            parse_test_log(log).unwrap(),
+            TestSummary {
+                passed: 4,
+                failed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn parses_single_test_nextest_summary() {
+        let log = "     Summary [   0.004s] 1 test run: 1 passed, 0 skipped\n";
+        assert_eq!(
+            parse_test_log(log).unwrap(),
+            TestSummary {
+                passed: 1,
+                failed: 0
+            }
+        );
+    }
+
+    #[test]
+    fn log_without_summary_is_rejected() {
+        assert!(parse_test_log("Compiling foo v0.1.0\n").is_err());
+    }
+}
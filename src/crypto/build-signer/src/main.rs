@@ -1,9 +1,8 @@
 use anyhow::{Context, Result};
+use build_proof::{Facts, Inputs, Payload, Predicate, Proof, Statement};
 use clap::Parser;
-use ed25519_dalek::{Signature, Signer, SigningKey};
+use ed25519_dalek::{Signer, SigningKey};
 use rand::rngs::OsRng;
-use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::PathBuf;
 
@@ -31,10 +30,30 @@ struct Args {
     #[arg(long)]
     drv_hash: Option<String>,
 
+    /// Optional: Build output (e.g. `./result`) whose NAR hash is recorded in the statement
+    #[arg(long)]
+    out_path: Option<PathBuf>,
+
     /// Optional: SHA256 hash of build log
     #[arg(long)]
     build_log_hash: Option<String>,
 
+    /// Optional: Hash of the source tree the build was made from
+    #[arg(long)]
+    source_tree_hash: Option<String>,
+
+    /// Optional: Test output to record in the statement (libtest or cargo-nextest)
+    #[arg(long)]
+    test_log: Option<PathBuf>,
+
+    /// Optional: Measured line coverage in percent
+    #[arg(long)]
+    coverage: Option<f64>,
+
+    /// Claim to attest, e.g. `tests-passed`, `build-succeeded` or `coverage >= 80` (repeatable)
+    #[arg(long = "claim", value_name = "PREDICATE")]
+    claims: Vec<Predicate>,
+
     /// Path to Ed25519 private key file (32 bytes)
     #[arg(long)]
     private_key: PathBuf,
@@ -44,28 +63,6 @@ struct Args {
     out: PathBuf,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Payload {
-    commit: String,
-    flake_lock_hash: String,
-    build_command: String,
-    artifact_tar_hash: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    drv_hash: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    build_log_hash: Option<String>,
-    timestamp: String,
-    nonce: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Proof {
-    payload: Payload,
-    signature: String,
-    public_key: String,
-    format_version: u8,
-}
-
 fn generate_nonce() -> String {
     let mut nonce = [0u8; 16];
     use rand::RngCore;
@@ -83,13 +80,10 @@ fn get_timestamp() -> String {
 
     // Simple UTC timestamp calculation
     const SECONDS_PER_DAY: u64 = 86400;
-    const DAYS_FROM_0_TO_1970: u64 = 719162;
 
     let days_since_epoch = secs / SECONDS_PER_DAY;
     let secs_today = secs % SECONDS_PER_DAY;
 
-    let total_days = DAYS_FROM_0_TO_1970 + days_since_epoch;
-
     // Simplified year calculation (good enough for our use case)
     let year = 1970 + (days_since_epoch / 365);
     let day_of_year = days_since_epoch % 365;
@@ -108,6 +102,55 @@ fn get_timestamp() -> String {
             seconds)
 }
 
+/// Build the statement from the statement-related arguments (if any were given)
+fn build_statement(args: &Args) -> Result<Option<Statement>> {
+    if args.source_tree_hash.is_none()
+        && args.out_path.is_none()
+        && args.test_log.is_none()
+        && args.coverage.is_none()
+        && args.claims.is_empty()
+    {
+        return Ok(None);
+    }
+
+    let mut inputs = Inputs {
+        source_tree_hash: args.source_tree_hash.clone(),
+        flake_lock_hash: Some(args.flake_lock_hash.clone()),
+        drv_hash: args.drv_hash.clone(),
+        out_path: None,
+        nar_hash: None,
+        test_summary_digest: None,
+    };
+    let mut facts = Facts {
+        tests: None,
+        coverage: args.coverage,
+    };
+
+    if let Some(ref path) = args.out_path {
+        // Resolve `result` symlinks to the store path itself
+        let path = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve output path: {}", path.display()))?;
+        inputs.nar_hash = Some(build_proof::nar::nar_hash(&path)?);
+        inputs.out_path = Some(path.display().to_string());
+    }
+
+    if let Some(ref path) = args.test_log {
+        let log = fs::read(path)
+            .with_context(|| format!("Failed to read test log: {}", path.display()))?;
+        inputs.test_summary_digest = Some(build_proof::sha256_hex(&log));
+        facts.tests = Some(build_proof::parse_test_log(&String::from_utf8_lossy(&log))?);
+    }
+
+    let statement = Statement {
+        inputs,
+        facts,
+        claims: args.claims.clone(),
+    };
+    statement.evaluate().context("Refusing to sign a false statement")?;
+
+    Ok(Some(statement))
+}
+
 fn sign_payload(signing_key: &SigningKey, payload: &Payload) -> Result<String> {
     // Serialize payload deterministically
     let payload_bytes = serde_json::to_vec(payload)
@@ -134,6 +177,8 @@ fn main() -> Result<()> {
     let signing_key = SigningKey::from_bytes(&key_bytes);
     let verifying_key = signing_key.verifying_key();
 
+    let statement = build_statement(&args)?;
+
     // Create payload
     let payload = Payload {
         commit: args.commit,
@@ -142,6 +187,7 @@ fn main() -> Result<()> {
         artifact_tar_hash: args.artifact_tar_hash,
         drv_hash: args.drv_hash,
         build_log_hash: args.build_log_hash,
+        statement,
         timestamp: get_timestamp(),
         nonce: generate_nonce(),
     };
@@ -168,6 +214,14 @@ fn main() -> Result<()> {
     println!("  Commit: {}", proof.payload.commit);
     println!("  Artifact hash: {}", proof.payload.artifact_tar_hash);
     println!("  Public key: {}", proof.public_key);
+    if let Some(ref statement) = proof.payload.statement {
+        if let Some(ref nar_hash) = statement.inputs.nar_hash {
+            println!("  NAR hash: {}", nar_hash);
+        }
+        for claim in &statement.claims {
+            println!("  Claim: {}", claim);
+        }
+    }
 
     Ok(())
 }
@@ -1,16 +1,17 @@
 use anyhow::{Context, Result};
+use build_proof::{Payload, Predicate, Proof};
 use clap::Parser;
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(name = "build-verifier")]
 #[command(about = "Verify signed Nix build artifacts", long_about = None)]
 struct Args {
-    /// Path to proof.json file
+    /// Path to proof.json (format version 1) or public.json (format version 2) file
     proof_file: PathBuf,
 
     /// Expected git commit SHA
@@ -32,33 +33,47 @@ struct Args {
     /// Skip flake.lock hash verification (useful for testing)
     #[arg(long)]
     skip_flake_lock_check: bool,
+
+    /// Require the proof to claim this predicate, e.g. `tests-passed` or `coverage >= 80` (repeatable)
+    #[arg(long = "require", value_name = "PREDICATE")]
+    required: Vec<Predicate>,
+
+    /// Optional: Test output to check against the statement's test summary digest
+    #[arg(long)]
+    test_log: Option<PathBuf>,
+
+    /// Optional: Build output to check against the statement's NAR hash (no nix CLI needed)
+    #[arg(long)]
+    out_path: Option<PathBuf>,
+
+    /// Optional: Write a JSON verification report (consumed by report-status)
+    #[arg(long)]
+    report: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Payload {
-    commit: String,
-    flake_lock_hash: String,
-    build_command: String,
-    artifact_tar_hash: String,
+/// Outcome of a verification run, written even when verification fails
+#[derive(Serialize, Debug, Default)]
+struct VerificationReport {
+    verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    drv_hash: Option<String>,
+    artifact_tar_hash: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    build_log_hash: Option<String>,
-    timestamp: String,
-    nonce: String,
+    error: Option<String>,
+    /// Set when the proof verified but doesn't vouch for who produced it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Proof {
-    payload: Payload,
-    signature: String,
-    public_key: String,
-    format_version: u8,
-}
+/// Format version 2 proofs are not signed: anyone can prove a made-up test log
+const UNSIGNED_ZK_WARNING: &str = "unsigned zero-knowledge proof, not tied to a trusted builder";
 
 fn compute_file_sha256(path: &PathBuf) -> Result<String> {
-    let contents = fs::read(path)
-        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let contents =
+        fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
 
     let mut hasher = Sha256::new();
     hasher.update(&contents);
@@ -69,8 +84,7 @@ fn compute_file_sha256(path: &PathBuf) -> Result<String> {
 
 fn verify_signature(proof: &Proof) -> Result<()> {
     // Decode public key
-    let pub_key_bytes = hex::decode(&proof.public_key)
-        .context("Failed to decode public key")?;
+    let pub_key_bytes = hex::decode(&proof.public_key).context("Failed to decode public key")?;
 
     if pub_key_bytes.len() != 32 {
         anyhow::bail!("Public key must be 32 bytes");
@@ -79,12 +93,10 @@ fn verify_signature(proof: &Proof) -> Result<()> {
     let mut key_array = [0u8; 32];
     key_array.copy_from_slice(&pub_key_bytes);
 
-    let verifying_key = VerifyingKey::from_bytes(&key_array)
-        .context("Invalid public key")?;
+    let verifying_key = VerifyingKey::from_bytes(&key_array).context("Invalid public key")?;
 
     // Decode signature
-    let sig_bytes = hex::decode(&proof.signature)
-        .context("Failed to decode signature")?;
+    let sig_bytes = hex::decode(&proof.signature).context("Failed to decode signature")?;
 
     if sig_bytes.len() != 64 {
         anyhow::bail!("Signature must be 64 bytes");
@@ -96,8 +108,8 @@ fn verify_signature(proof: &Proof) -> Result<()> {
     let signature = Signature::from_bytes(&sig_array);
 
     // Serialize payload (same way as signer)
-    let payload_bytes = serde_json::to_vec(&proof.payload)
-        .context("Failed to serialize payload")?;
+    let payload_bytes =
+        serde_json::to_vec(&proof.payload).context("Failed to serialize payload")?;
 
     // Verify signature
     verifying_key
@@ -137,22 +149,62 @@ fn main() -> Result<()> {
         args.expected_commit = std::env::var("GITHUB_SHA").ok();
     }
 
+    let mut report = VerificationReport {
+        commit: args.expected_commit.clone(),
+        ..Default::default()
+    };
+    let result = verify(&args, &mut report);
+
+    if let Some(ref path) = args.report {
+        report.verified = result.is_ok();
+        report.error = result.as_ref().err().map(|err| format!("{err:#}"));
+
+        let report_json = serde_json::to_string_pretty(&report)
+            .context("Failed to serialize verification report")?;
+        fs::write(path, report_json)
+            .with_context(|| format!("Failed to write report: {}", path.display()))?;
+    }
+
+    result
+}
+
+/// Just enough of a proof file to decide how to verify it
+#[derive(Deserialize)]
+struct FormatProbe {
+    format_version: u8,
+}
+
+fn verify(args: &Args, report: &mut VerificationReport) -> Result<()> {
     // Read and parse proof
     let proof_contents = fs::read_to_string(&args.proof_file)
         .with_context(|| format!("Failed to read proof file: {}", args.proof_file.display()))?;
 
-    let proof: Proof = serde_json::from_str(&proof_contents)
-        .context("Failed to parse proof JSON")?;
+    let probe: FormatProbe =
+        serde_json::from_str(&proof_contents).context("Failed to parse proof JSON")?;
+
+    match probe.format_version {
+        1 => verify_signed(args, &proof_contents, report),
+        zk_prover::FORMAT_VERSION => verify_zk(args, &proof_contents, report),
+        version => anyhow::bail!("Unsupported proof format version: {}", version),
+    }
+}
+
+/// Format version 1: Ed25519-signed attestation
+fn verify_signed(args: &Args, proof_contents: &str, report: &mut VerificationReport) -> Result<()> {
+    let proof: Proof =
+        serde_json::from_str(proof_contents).context("Failed to parse proof JSON")?;
 
     println!("📋 Verifying build proof...");
     println!("  Format version: {}", proof.format_version);
     println!("  Commit: {}", proof.payload.commit);
     println!("  Public key: {}", proof.public_key);
 
-    // Check format version
-    if proof.format_version != 1 {
-        anyhow::bail!("Unsupported proof format version: {}", proof.format_version);
+    // Keep the expected commit if there is one: that's where a status should be reported
+    if report.commit.is_none() {
+        report.commit = Some(proof.payload.commit.clone());
     }
+    report.public_key = Some(proof.public_key.clone());
+    report.artifact_tar_hash = Some(proof.payload.artifact_tar_hash.clone());
 
     // Verify signature
     print!("🔐 Verifying signature... ");
@@ -168,22 +220,8 @@ fn main() -> Result<()> {
         println!("✓");
     }
 
-    // Verify commit SHA
-    if !args.skip_commit_check {
-        if let Some(expected_commit) = args.expected_commit {
-            print!("📝 Verifying commit SHA... ");
-            if proof.payload.commit != expected_commit {
-                anyhow::bail!(
-                    "Commit mismatch: expected {}, got {}",
-                    expected_commit,
-                    proof.payload.commit
-                );
-            }
-            println!("✓");
-        } else {
-            println!("⚠️  No expected commit provided (set GITHUB_SHA or use --expected-commit)");
-        }
-    }
+    check_commit(args, &proof.payload.commit)?;
+    check_statement(args, &proof.payload)?;
 
     // Verify flake.lock hash
     if !args.skip_flake_lock_check {
@@ -211,10 +249,166 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Format version 2: zero-knowledge proof of a passing test run (`public.json` + `.proof`)
+fn verify_zk(args: &Args, proof_contents: &str, report: &mut VerificationReport) -> Result<()> {
+    let public: zk_prover::PublicInputs =
+        serde_json::from_str(proof_contents).context("Failed to parse public inputs JSON")?;
+
+    println!("📋 Verifying zero-knowledge proof...");
+    println!("  Format version: {}", public.format_version);
+    println!("  Backend: {}", public.backend);
+    println!("  Statement: {}", public.statement);
+    println!("  Commit: {}", public.commit);
+
+    if report.commit.is_none() {
+        report.commit = Some(public.commit.clone());
+    }
+
+    // Refuse rather than skip anything the caller asked for but this format can't check
+    let version = public.format_version;
+    if args.trusted_keys.is_some() {
+        anyhow::bail!(
+            "Format version {} proofs are not signed, so --trusted-keys cannot be checked",
+            version
+        );
+    }
+    if args.test_log.is_some() {
+        anyhow::bail!("--test-log is not supported for format version {}", version);
+    }
+    if args.out_path.is_some() {
+        anyhow::bail!("--out-path is not supported for format version {}", version);
+    }
+    if !args.skip_flake_lock_check && args.flake_lock.exists() {
+        anyhow::bail!(
+            "flake.lock is not covered by format version {} (use --skip-flake-lock-check)",
+            version
+        );
+    }
+
+    // The proof file is referenced relative to public.json
+    let proof_path = args
+        .proof_file
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(&public.proof);
+    let proof = fs::read(&proof_path)
+        .with_context(|| format!("Failed to read proof: {}", proof_path.display()))?;
+
+    print!("🧮 Verifying proof... ");
+    zk_prover::verify(&public, &proof)?;
+    println!("✓");
+
+    for required in &args.required {
+        if !public.statement.implies(required) {
+            anyhow::bail!("Proof does not claim required predicate `{}`", required);
+        }
+    }
+
+    check_commit(args, &public.commit)?;
+
+    println!("\n✅ Verification successful!");
+    println!("  Source hash: {}", public.source_hash);
+    println!("  Tests passed: {}", public.tests_passed);
+    println!("⚠️  Warning: {}", UNSIGNED_ZK_WARNING);
+    report.warning = Some(UNSIGNED_ZK_WARNING.to_string());
+
+    Ok(())
+}
+
+/// Evaluate the payload's statement and check it against the local evidence and requirements
+fn check_statement(args: &Args, payload: &Payload) -> Result<()> {
+    let Some(ref statement) = payload.statement else {
+        if !args.required.is_empty() {
+            anyhow::bail!("Proof has no statement but predicates are required");
+        }
+        if args.out_path.is_some() {
+            anyhow::bail!("Proof has no statement to check the output path against");
+        }
+        return Ok(());
+    };
+
+    print!("📜 Evaluating statement... ");
+    let inputs = &statement.inputs;
+    if inputs
+        .flake_lock_hash
+        .as_ref()
+        .is_some_and(|hash| *hash != payload.flake_lock_hash)
+    {
+        anyhow::bail!("Statement flake.lock hash does not match the payload");
+    }
+    if inputs.drv_hash.is_some() && inputs.drv_hash != payload.drv_hash {
+        anyhow::bail!("Statement derivation does not match the payload");
+    }
+    statement.evaluate()?;
+
+    if let Some(ref path) = args.test_log {
+        let log = fs::read(path)
+            .with_context(|| format!("Failed to read test log: {}", path.display()))?;
+        if inputs.test_summary_digest.as_deref() != Some(&build_proof::sha256_hex(&log)) {
+            anyhow::bail!("Test log does not match the statement's test summary digest");
+        }
+        let summary = build_proof::parse_test_log(&String::from_utf8_lossy(&log))?;
+        if statement.facts.tests != Some(summary) {
+            anyhow::bail!("Test log summary does not match the statement");
+        }
+    }
+
+    if let Some(ref path) = args.out_path {
+        let expected = inputs
+            .nar_hash
+            .as_deref()
+            .context("Statement does not record a NAR hash for the output")?;
+        // Hash what a `result` symlink points to, like the signer does
+        let resolved = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve output path: {}", path.display()))?;
+        let computed = build_proof::nar::nar_hash(&resolved)?;
+        if computed != expected {
+            anyhow::bail!(
+                "Output NAR hash mismatch:\n  Expected: {}\n  Computed: {}",
+                expected,
+                computed
+            );
+        }
+    }
+
+    for required in &args.required {
+        if !statement.claims(required) {
+            anyhow::bail!("Proof does not claim required predicate `{}`", required);
+        }
+    }
+    println!("✓");
+
+    for claim in &statement.claims {
+        println!("  Claim: {}", claim);
+    }
+
+    Ok(())
+}
+
+fn check_commit(args: &Args, commit: &str) -> Result<()> {
+    if args.skip_commit_check {
+        return Ok(());
+    }
+
+    if let Some(ref expected_commit) = args.expected_commit {
+        print!("📝 Verifying commit SHA... ");
+        if commit != expected_commit {
+            anyhow::bail!(
+                "Commit mismatch: expected {}, got {}",
+                expected_commit,
+                commit
+            );
+        }
+        println!("✓");
+    } else {
+        println!("⚠️  No expected commit provided (set GITHUB_SHA or use --expected-commit)");
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_hash_length() {
         // Just a simple test to verify hex encoding produces correct length
@@ -33,7 +33,7 @@ in
       "build-signer"
     ];
 
-    cargoHash = "sha256-aD7da1xJzL1CAvPfihlpmyE9jByt45ld+ia3OmY0ops=";
+    cargoLock.lockFile = ../Cargo.lock;
 
     meta = {
       description = "Sign Nix build artifacts with Ed25519";
@@ -57,11 +57,59 @@ in
       "build-verifier"
     ];
 
-    cargoHash = "sha256-aD7da1xJzL1CAvPfihlpmyE9jByt45ld+ia3OmY0ops=";
+    cargoLock.lockFile = ../Cargo.lock;
 
     meta = {
       description = "Verify signed Nix build artifacts";
       mainProgram = "build-verifier";
     };
   };
+
+  report-status = pkgs.rustPlatform.buildRustPackage {
+    pname = "report-status";
+    version = "0.1.0";
+
+    src = workspaceSrc;
+
+    # Build only the status reporter binary
+    cargoBuildFlags = [
+      "--bin"
+      "report-status"
+    ];
+    cargoTestFlags = [
+      "--package"
+      "report-status"
+    ];
+
+    cargoLock.lockFile = ../Cargo.lock;
+
+    meta = {
+      description = "Post build verification results as forge commit statuses";
+      mainProgram = "report-status";
+    };
+  };
+
+  zk-prover = pkgs.rustPlatform.buildRustPackage {
+    pname = "zk-prover";
+    version = "0.1.0";
+
+    src = workspaceSrc;
+
+    # Build only the prover binary
+    cargoBuildFlags = [
+      "--bin"
+      "zk-prover"
+    ];
+    cargoTestFlags = [
+      "--package"
+      "zk-prover"
+    ];
+
+    cargoLock.lockFile = ../Cargo.lock;
+
+    meta = {
+      description = "Zero-knowledge proofs of passing test runs";
+      mainProgram = "zk-prover";
+    };
+  };
 }
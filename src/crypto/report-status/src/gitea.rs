@@ -0,0 +1,80 @@
+use anyhow::Result;
+
+use crate::{post_json, split_repo, CommitStatus, Forge};
+
+/// Gitea/Forgejo API (`POST /repos/{owner}/{repo}/statuses/{sha}`)
+///
+/// `api_url` includes the `/api/v1` prefix, e.g. `https://codeberg.org/api/v1`.
+pub struct Gitea {
+    api_url: String,
+    repo: String,
+    token: String,
+}
+
+impl Gitea {
+    pub fn new(
+        api_url: impl Into<String>,
+        repo: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Self {
+            api_url: api_url.into(),
+            repo: repo.into(),
+            token: token.into(),
+        }
+    }
+}
+
+impl Forge for Gitea {
+    fn post_status(&self, status: &CommitStatus) -> Result<()> {
+        let (owner, name) = split_repo(&self.repo)?;
+        let url = format!(
+            "{}/repos/{owner}/{name}/statuses/{}",
+            self.api_url.trim_end_matches('/'),
+            status.sha
+        );
+
+        post_json(
+            &url,
+            &format!("token {}", self.token),
+            "application/json",
+            status.body(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakeServer, State};
+
+    #[test]
+    fn posts_status_with_token_auth() {
+        let server = FakeServer::start(201);
+        let forge = Gitea::new(format!("{}/api/v1/", server.url()), "org/repo", "secret");
+
+        forge
+            .post_status(&CommitStatus {
+                sha: "abc123".into(),
+                state: State::Failure,
+                context: "build-proof".into(),
+                description: "Build proof rejected: Signature verification failed".into(),
+                target_url: None,
+            })
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let req = &requests[0];
+        assert_eq!(req.path, "/api/v1/repos/org/repo/statuses/abc123");
+        assert_eq!(req.header("authorization"), Some("token secret"));
+        assert_eq!(
+            req.json(),
+            serde_json::json!({
+                "state": "failure",
+                "context": "build-proof",
+                "description": "Build proof rejected: Signature verification failed",
+            })
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+
+use crate::{post_json, split_repo, CommitStatus, Forge};
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// GitHub REST API (`POST /repos/{owner}/{repo}/statuses/{sha}`)
+pub struct GitHub {
+    api_url: String,
+    repo: String,
+    token: String,
+}
+
+impl GitHub {
+    pub fn new(
+        api_url: impl Into<String>,
+        repo: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Self {
+            api_url: api_url.into(),
+            repo: repo.into(),
+            token: token.into(),
+        }
+    }
+}
+
+impl Forge for GitHub {
+    fn post_status(&self, status: &CommitStatus) -> Result<()> {
+        let (owner, name) = split_repo(&self.repo)?;
+        let url = format!(
+            "{}/repos/{owner}/{name}/statuses/{}",
+            self.api_url.trim_end_matches('/'),
+            status.sha
+        );
+
+        post_json(
+            &url,
+            &format!("Bearer {}", self.token),
+            "application/vnd.github+json",
+            status.body(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakeServer, State};
+
+    #[test]
+    fn posts_status_to_statuses_endpoint() {
+        let server = FakeServer::start(201);
+        let forge = GitHub::new(server.url(), "l1ne-company/one-for-all", "secret");
+
+        forge
+            .post_status(&CommitStatus {
+                sha: "abc123".into(),
+                state: State::Success,
+                context: "build-proof".into(),
+                description: "Build proof verified".into(),
+                target_url: Some("https://example.com/run/1".into()),
+            })
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let req = &requests[0];
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, "/repos/l1ne-company/one-for-all/statuses/abc123");
+        assert_eq!(req.header("authorization"), Some("Bearer secret"));
+        assert_eq!(
+            req.json(),
+            serde_json::json!({
+                "state": "success",
+                "context": "build-proof",
+                "description": "Build proof verified",
+                "target_url": "https://example.com/run/1",
+            })
+        );
+    }
+
+    #[test]
+    fn http_errors_are_reported() {
+        let server = FakeServer::start(422);
+        let forge = GitHub::new(server.url(), "org/repo", "secret");
+
+        let err = forge
+            .post_status(&CommitStatus {
+                sha: "abc123".into(),
+                state: State::Failure,
+                context: "build-proof".into(),
+                description: "nope".into(),
+                target_url: None,
+            })
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("HTTP 422"), "{err:#}");
+    }
+}
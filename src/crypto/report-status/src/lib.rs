@@ -0,0 +1,197 @@
+//! Post build-verifier results to a forge as commit statuses.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub mod gitea;
+pub mod github;
+
+#[cfg(test)]
+mod fake;
+
+pub use gitea::Gitea;
+pub use github::GitHub;
+
+/// Maximum description length accepted by the GitHub statuses API
+const MAX_DESCRIPTION_LEN: usize = 140;
+
+/// Report written by `build-verifier --report`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct VerificationReport {
+    pub verified: bool,
+    #[serde(default)]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub artifact_tar_hash: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Caveat on a successful verification (e.g. an unsigned proof)
+    #[serde(default)]
+    pub warning: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
+/// A commit status as understood by both GitHub and Gitea/Forgejo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitStatus {
+    pub sha: String,
+    pub state: State,
+    pub context: String,
+    pub description: String,
+    pub target_url: Option<String>,
+}
+
+/// JSON body shared by the GitHub and Gitea statuses endpoints
+#[derive(Serialize)]
+struct StatusBody<'a> {
+    state: State,
+    context: &'a str,
+    description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_url: Option<&'a str>,
+}
+
+impl CommitStatus {
+    /// Map a verification report onto a status for `sha`
+    pub fn from_report(report: &VerificationReport, sha: String, context: String) -> Self {
+        let description = if report.verified {
+            match (&report.warning, &report.public_key) {
+                (Some(warning), _) => format!("Build proof verified, but: {warning}"),
+                (None, Some(key)) => format!("Build proof verified (key {})", short(key)),
+                (None, None) => "Build proof verified".to_string(),
+            }
+        } else {
+            let reason = report.error.as_deref().unwrap_or("unknown error");
+            format!("Build proof rejected: {reason}")
+        };
+
+        Self {
+            sha,
+            state: if report.verified {
+                State::Success
+            } else {
+                State::Failure
+            },
+            context,
+            description: truncate(&description, MAX_DESCRIPTION_LEN),
+            target_url: None,
+        }
+    }
+
+    fn body(&self) -> StatusBody<'_> {
+        StatusBody {
+            state: self.state,
+            context: &self.context,
+            description: &self.description,
+            target_url: self.target_url.as_deref(),
+        }
+    }
+}
+
+/// A code forge that can attach statuses to commits
+pub trait Forge {
+    fn post_status(&self, status: &CommitStatus) -> Result<()>;
+}
+
+/// POST `body` to `url`, turning non-2xx responses into errors that include the response body
+fn post_json(url: &str, authorization: &str, accept: &str, body: impl Serialize) -> Result<()> {
+    match ureq::post(url)
+        .set("Authorization", authorization)
+        .set("Accept", accept)
+        .set("User-Agent", "report-status")
+        .send_json(body)
+    {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            anyhow::bail!("POST {url} failed with HTTP {code}: {body}")
+        }
+        Err(err) => Err(err).with_context(|| format!("POST {url} failed")),
+    }
+}
+
+/// Split `owner/repo` into its two components
+fn split_repo(repo: &str) -> Result<(&str, &str)> {
+    repo.split_once('/')
+        .filter(|(owner, name)| !owner.is_empty() && !name.is_empty() && !name.contains('/'))
+        .with_context(|| format!("repository must be `owner/name`, got {repo:?}"))
+}
+
+fn short(key: &str) -> &str {
+    key.get(..16).unwrap_or(key)
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut out: String = s.chars().take(max - 1).collect();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn success_report_maps_to_success() {
+        let report = VerificationReport {
+            verified: true,
+            public_key: Some("f0f65bae20a3256c55f5669c4f8ac97aaac9072c".into()),
+            ..Default::default()
+        };
+        let status = CommitStatus::from_report(&report, "abc".into(), "build-proof".into());
+        assert_eq!(status.state, State::Success);
+        assert_eq!(
+            status.description,
+            "Build proof verified (key f0f65bae20a3256c)"
+        );
+    }
+
+    #[test]
+    fn success_warning_is_shown() {
+        let report = VerificationReport {
+            verified: true,
+            warning: Some("unsigned zero-knowledge proof, not tied to a trusted builder".into()),
+            ..Default::default()
+        };
+        let status = CommitStatus::from_report(&report, "abc".into(), "build-proof".into());
+        assert_eq!(status.state, State::Success);
+        assert_eq!(
+            status.description,
+            "Build proof verified, but: unsigned zero-knowledge proof, not tied to a trusted builder"
+        );
+    }
+
+    #[test]
+    fn failure_description_is_truncated() {
+        let report = VerificationReport {
+            verified: false,
+            error: Some("x".repeat(500)),
+            ..Default::default()
+        };
+        let status = CommitStatus::from_report(&report, "abc".into(), "build-proof".into());
+        assert_eq!(status.state, State::Failure);
+        assert_eq!(status.description.chars().count(), MAX_DESCRIPTION_LEN);
+        assert!(status.description.starts_with("Build proof rejected: xxx"));
+    }
+
+    #[test]
+    fn repo_must_have_owner_and_name() {
+        assert_eq!(split_repo("org/repo").unwrap(), ("org", "repo"));
+        assert!(split_repo("repo").is_err());
+        assert!(split_repo("org/").is_err());
+        assert!(split_repo("a/b/c").is_err());
+    }
+}
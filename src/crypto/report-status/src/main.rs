@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use report_status::{CommitStatus, Forge, GitHub, Gitea, VerificationReport};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "report-status")]
+#[command(about = "Post a build-verifier report as a commit status", long_about = None)]
+struct Args {
+    /// Path to the report written by `build-verifier --report`
+    report: PathBuf,
+
+    /// Forge API to talk to
+    #[arg(long, value_enum, default_value_t = ForgeKind::Github)]
+    forge: ForgeKind,
+
+    /// Repository as `owner/name` (defaults to GITHUB_REPOSITORY)
+    #[arg(long)]
+    repo: Option<String>,
+
+    /// API base URL (defaults to GITHUB_API_URL or https://api.github.com for GitHub,
+    /// GITEA_API_URL for Gitea/Forgejo)
+    #[arg(long)]
+    api_url: Option<String>,
+
+    /// Commit to attach the status to (defaults to the report's commit, then GITHUB_SHA)
+    #[arg(long)]
+    sha: Option<String>,
+
+    /// Status context shown by the forge
+    #[arg(long, default_value = "build-proof")]
+    context: String,
+
+    /// Optional: URL linked from the status (e.g. the CI run)
+    #[arg(long)]
+    target_url: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ForgeKind {
+    /// GitHub REST API, token from GITHUB_TOKEN
+    Github,
+    /// Gitea or Forgejo API, token from GITEA_TOKEN or FORGEJO_TOKEN
+    Gitea,
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()))
+}
+
+fn build_forge(args: &Args) -> Result<Box<dyn Forge>> {
+    let repo = args
+        .repo
+        .clone()
+        .or_else(|| env_var(&["GITHUB_REPOSITORY"]))
+        .context("No repository given (use --repo or set GITHUB_REPOSITORY)")?;
+
+    let forge: Box<dyn Forge> = match args.forge {
+        ForgeKind::Github => {
+            let api_url = args
+                .api_url
+                .clone()
+                .or_else(|| env_var(&["GITHUB_API_URL"]))
+                .unwrap_or_else(|| report_status::github::DEFAULT_API_URL.to_string());
+            let token = env_var(&["GITHUB_TOKEN"]).context("GITHUB_TOKEN is not set")?;
+            Box::new(GitHub::new(api_url, repo, token))
+        }
+        ForgeKind::Gitea => {
+            let api_url = args
+                .api_url
+                .clone()
+                .or_else(|| env_var(&["GITEA_API_URL"]))
+                .context("No API URL given (use --api-url or set GITEA_API_URL)")?;
+            let token = env_var(&["GITEA_TOKEN", "FORGEJO_TOKEN"])
+                .context("GITEA_TOKEN (or FORGEJO_TOKEN) is not set")?;
+            Box::new(Gitea::new(api_url, repo, token))
+        }
+    };
+
+    Ok(forge)
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let report_contents = fs::read_to_string(&args.report)
+        .with_context(|| format!("Failed to read report: {}", args.report.display()))?;
+    let report: VerificationReport =
+        serde_json::from_str(&report_contents).context("Failed to parse report JSON")?;
+
+    let sha = args
+        .sha
+        .clone()
+        .or_else(|| report.commit.clone())
+        .or_else(|| env_var(&["GITHUB_SHA"]))
+        .context("No commit to report on (use --sha or set GITHUB_SHA)")?;
+
+    let mut status = CommitStatus::from_report(&report, sha, args.context.clone());
+    status.target_url = args.target_url.clone();
+
+    let forge = build_forge(&args)?;
+
+    println!("📡 Reporting {:?} for {}...", status.state, status.sha);
+    println!("  {}", status.description);
+    forge.post_status(&status)?;
+    println!("✓ Status posted");
+
+    Ok(())
+}
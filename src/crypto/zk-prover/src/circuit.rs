@@ -0,0 +1,137 @@
+//! The `tests-passed` circuit.
+//!
+//! Public inputs (instance column, in order):
+//!   0. `source`     - the source hash mapped into the field
+//!   1. `commitment` - Poseidon(source, commit, results_digest, passed, failed)
+//!   2. `commit`     - the git commit mapped into the field
+//!   3. `passed`     - the number of passing tests
+//!
+//! Private witness: `results_digest`, `failed`.
+//!
+//! Constraints: the commitment is computed over the same `source`, `commit` and `passed`
+//! cells that are exposed publicly, and `failed` is constrained to be zero. A valid proof
+//! therefore shows that the committed test results for this source and commit recorded
+//! `passed` passing tests and no failures, without revealing the results themselves.
+
+use halo2_gadgets::poseidon::{
+    primitives::{self as poseidon, ConstantLength, P128Pow5T3},
+    Hash, Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    pasta::Fp,
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Instance},
+};
+
+const WIDTH: usize = 3;
+const RATE: usize = 2;
+const INPUTS: usize = 5;
+
+pub(crate) const SOURCE_ROW: usize = 0;
+pub(crate) const COMMITMENT_ROW: usize = 1;
+pub(crate) const COMMIT_ROW: usize = 2;
+pub(crate) const PASSED_ROW: usize = 3;
+/// Number of instance rows
+pub(crate) const INSTANCES: usize = 4;
+
+/// Poseidon commitment the circuit recomputes, evaluated natively
+pub(crate) fn commitment(
+    source: Fp,
+    commit: Fp,
+    results_digest: Fp,
+    passed: u64,
+    failed: u64,
+) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<INPUTS>, WIDTH, RATE>::init().hash([
+        source,
+        commit,
+        results_digest,
+        Fp::from(passed),
+        Fp::from(failed),
+    ])
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct TestsPassedConfig {
+    poseidon: Pow5Config<Fp, WIDTH, RATE>,
+    state: [Column<Advice>; WIDTH],
+    instance: Column<Instance>,
+}
+
+#[derive(Default)]
+pub(crate) struct TestsPassedCircuit {
+    /// `[source, commit, results_digest, passed, failed]`
+    pub(crate) witness: Value<[Fp; INPUTS]>,
+}
+
+impl Circuit<Fp> for TestsPassedCircuit {
+    type Config = TestsPassedConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let state = [(); WIDTH].map(|_| meta.advice_column());
+        let partial_sbox = meta.advice_column();
+        let rc_a = [(); WIDTH].map(|_| meta.fixed_column());
+        let rc_b = [(); WIDTH].map(|_| meta.fixed_column());
+
+        meta.enable_constant(rc_b[0]);
+
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        state.iter().for_each(|col| meta.enable_equality(*col));
+
+        let poseidon = Pow5Chip::configure::<P128Pow5T3>(meta, state, partial_sbox, rc_a, rc_b);
+
+        TestsPassedConfig {
+            poseidon,
+            state,
+            instance,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let chip = Pow5Chip::construct(config.poseidon.clone());
+
+        let message = layouter.assign_region(
+            || "load witness",
+            |mut region| {
+                let cells = (0..INPUTS)
+                    .map(|i| {
+                        region.assign_advice(
+                            || format!("input {i}"),
+                            config.state[i % WIDTH],
+                            i / WIDTH,
+                            || self.witness.map(|w| w[i]),
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // The whole point of the statement: no test failed
+                region.constrain_constant(cells[4].cell(), Fp::zero())?;
+
+                Ok(cells)
+            },
+        )?;
+
+        layouter.constrain_instance(message[0].cell(), config.instance, SOURCE_ROW)?;
+        layouter.constrain_instance(message[1].cell(), config.instance, COMMIT_ROW)?;
+        layouter.constrain_instance(message[3].cell(), config.instance, PASSED_ROW)?;
+
+        let hasher = Hash::<_, _, P128Pow5T3, ConstantLength<INPUTS>, WIDTH, RATE>::init(
+            chip,
+            layouter.namespace(|| "init poseidon"),
+        )?;
+        let message: [_; INPUTS] = message.try_into().expect("exactly INPUTS cells");
+        let output = hasher.hash(layouter.namespace(|| "commitment"), message)?;
+
+        layouter.constrain_instance(output.cell(), config.instance, COMMITMENT_ROW)
+    }
+}
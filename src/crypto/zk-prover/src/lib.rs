@@ -0,0 +1,269 @@
+//! Zero-knowledge proofs that a committed test run on a given source had no failures.
+//!
+//! Backend: Halo2 (IPA commitments over the Pasta curves, so no trusted setup) with a
+//! Poseidon commitment. Proving and verification parameters are derived deterministically
+//! from [`K`], which is why the verifier only needs `public.json` and the `.proof` file.
+
+use anyhow::{bail, Context, Result};
+use halo2_proofs::{
+    circuit::Value,
+    pasta::{group::ff::PrimeField, EqAffine, Fp},
+    plonk::{self, SingleVerifier, VerifyingKey},
+    poly::commitment::Params,
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+};
+use rand::{rand_core::UnwrapErr, rngs::SysRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+mod circuit;
+
+use build_proof::{parse_test_log, Predicate};
+use circuit::TestsPassedCircuit;
+
+/// Proof `format_version` handled by this crate (version 1 is the Ed25519 attestation)
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Identifies the proof system and circuit layout
+pub const BACKEND: &str = "halo2-ipa-pasta/poseidon-v2";
+
+/// Circuit size (2^K rows)
+const K: u32 = 8;
+
+/// Contents of `public.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublicInputs {
+    pub format_version: u8,
+    pub backend: String,
+    /// The proven predicate; the circuit only supports `tests-passed`
+    pub statement: Predicate,
+    /// Git commit the tests were run on (public input)
+    pub commit: String,
+    /// Hex-encoded hash of the source tree (public input)
+    pub source_hash: String,
+    /// Hex-encoded Poseidon commitment to the test results (public input)
+    pub commitment: String,
+    /// Number of passing tests recorded in the committed results (public input)
+    pub tests_passed: u64,
+    /// Proof file, relative to `public.json`
+    pub proof: String,
+}
+
+/// Hash arbitrary bytes into the field by truncating SHA-256 to 248 bits
+pub fn hash_to_field(bytes: &[u8]) -> Fp {
+    let digest = Sha256::digest(bytes);
+    let mut repr = [0u8; 32];
+    repr[..31].copy_from_slice(&digest[..31]);
+    Fp::from_repr(repr).expect("248-bit values are always canonical")
+}
+
+fn encode_field(value: Fp) -> String {
+    hex::encode(value.to_repr())
+}
+
+fn decode_field(hex_str: &str) -> Result<Fp> {
+    let repr: [u8; 32] = hex::decode(hex_str)
+        .context("Failed to decode field element")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Field element must be 32 bytes"))?;
+
+    Option::from(Fp::from_repr(repr)).context("Field element is not canonical")
+}
+
+fn source_field(source_hash: &str) -> Result<Fp> {
+    let bytes = hex::decode(source_hash).context("Source hash must be hex encoded")?;
+    Ok(hash_to_field(&bytes))
+}
+
+fn keys() -> Result<(Params<EqAffine>, plonk::ProvingKey<EqAffine>)> {
+    let params = Params::new(K);
+    let empty = TestsPassedCircuit::default();
+    let vk = plonk::keygen_vk(&params, &empty).context("Failed to generate verifying key")?;
+    let pk = plonk::keygen_pk(&params, vk, &empty).context("Failed to generate proving key")?;
+    Ok((params, pk))
+}
+
+fn verifying_key(params: &Params<EqAffine>) -> Result<VerifyingKey<EqAffine>> {
+    plonk::keygen_vk(params, &TestsPassedCircuit::default())
+        .context("Failed to generate verifying key")
+}
+
+/// Prove that `test_log` (the output of the test run on `source_hash`) contains no failures
+///
+/// Returns the public inputs (with `proof` set to `proof_file_name`) and the proof bytes.
+pub fn prove(
+    commit: &str,
+    source_hash: &str,
+    test_log: &[u8],
+    proof_file_name: &str,
+) -> Result<(PublicInputs, Vec<u8>)> {
+    let summary = parse_test_log(&String::from_utf8_lossy(test_log))?;
+    if summary.failed > 0 {
+        bail!(
+            "Cannot prove a failing run: {} test(s) failed",
+            summary.failed
+        );
+    }
+    if summary.passed == 0 {
+        bail!("Cannot prove a run without any passing tests");
+    }
+
+    let source = source_field(source_hash)?;
+    let commit_field = hash_to_field(commit.as_bytes());
+    let results_digest = hash_to_field(test_log);
+    let commitment = circuit::commitment(
+        source,
+        commit_field,
+        results_digest,
+        summary.passed,
+        summary.failed,
+    );
+
+    let circuit = TestsPassedCircuit {
+        witness: Value::known([
+            source,
+            commit_field,
+            results_digest,
+            Fp::from(summary.passed),
+            Fp::from(summary.failed),
+        ]),
+    };
+
+    let (params, pk) = keys()?;
+    let instance = public_instance(source, commitment, commit_field, summary.passed);
+    let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+    plonk::create_proof(
+        &params,
+        &pk,
+        &[circuit],
+        &[&[&instance]],
+        &mut UnwrapErr(SysRng),
+        &mut transcript,
+    )
+    .context("Failed to create proof")?;
+
+    let public = PublicInputs {
+        format_version: FORMAT_VERSION,
+        backend: BACKEND.to_string(),
+        statement: Predicate::TestsPassed,
+        commit: commit.to_string(),
+        source_hash: source_hash.to_string(),
+        commitment: encode_field(commitment),
+        tests_passed: summary.passed,
+        proof: proof_file_name.to_string(),
+    };
+
+    Ok((public, transcript.finalize()))
+}
+
+/// Verify `proof` against the public inputs
+pub fn verify(public: &PublicInputs, proof: &[u8]) -> Result<()> {
+    if public.format_version != FORMAT_VERSION {
+        bail!(
+            "Unsupported proof format version: {}",
+            public.format_version
+        );
+    }
+    if public.backend != BACKEND {
+        bail!("Unsupported proof backend: {}", public.backend);
+    }
+    if public.statement != Predicate::TestsPassed {
+        bail!("Unsupported statement: {}", public.statement);
+    }
+
+    let instance = public_instance(
+        source_field(&public.source_hash)?,
+        decode_field(&public.commitment)?,
+        hash_to_field(public.commit.as_bytes()),
+        public.tests_passed,
+    );
+
+    let params = Params::new(K);
+    let vk = verifying_key(&params)?;
+    let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(proof);
+    plonk::verify_proof(
+        &params,
+        &vk,
+        SingleVerifier::new(&params),
+        &[&[&instance]],
+        &mut transcript,
+    )
+    .context("Proof verification failed")
+}
+
+fn public_instance(source: Fp, commitment: Fp, commit: Fp, passed: u64) -> Vec<Fp> {
+    let mut instance = vec![Fp::zero(); circuit::INSTANCES];
+    instance[circuit::SOURCE_ROW] = source;
+    instance[circuit::COMMITMENT_ROW] = commitment;
+    instance[circuit::COMMIT_ROW] = commit;
+    instance[circuit::PASSED_ROW] = Fp::from(passed);
+    instance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "2a4e2ba6cf3d1845875740a53ea6907f4dc520c4c84616ecefae279b39f4699b";
+    const PASSING_LOG: &[u8] =
+        b"test result: ok. 3 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out\n";
+
+    #[test]
+    fn prove_and_verify() {
+        let (public, proof) = prove("abc", SOURCE, PASSING_LOG, "test.proof").unwrap();
+        assert_eq!(public.tests_passed, 3);
+        verify(&public, &proof).unwrap();
+    }
+
+    #[test]
+    fn tampered_public_inputs_are_rejected() {
+        let (public, proof) = prove("abc", SOURCE, PASSING_LOG, "test.proof").unwrap();
+
+        let other_source = PublicInputs {
+            source_hash: "00".repeat(32),
+            ..public.clone()
+        };
+        assert!(verify(&other_source, &proof).is_err());
+
+        let other_commitment = PublicInputs {
+            commitment: encode_field(Fp::from(42)),
+            ..public.clone()
+        };
+        assert!(verify(&other_commitment, &proof).is_err());
+
+        let other_commit = PublicInputs {
+            commit: "def".into(),
+            ..public.clone()
+        };
+        assert!(verify(&other_commit, &proof).is_err());
+
+        let other_count = PublicInputs {
+            tests_passed: 300,
+            ..public
+        };
+        assert!(verify(&other_count, &proof).is_err());
+    }
+
+    #[test]
+    fn failing_runs_cannot_be_proven() {
+        let log = b"test result: FAILED. 2 passed; 1 failed; 0 ignored\n";
+        assert!(prove("abc", SOURCE, log, "test.proof").is_err());
+    }
+
+    #[test]
+    fn circuit_rejects_failures_even_with_matching_commitment() {
+        use halo2_proofs::dev::MockProver;
+
+        let source = source_field(SOURCE).unwrap();
+        let commit = hash_to_field(b"abc");
+        let digest = hash_to_field(b"log");
+        let commitment = circuit::commitment(source, commit, digest, 2, 1);
+        let circuit = TestsPassedCircuit {
+            witness: Value::known([source, commit, digest, Fp::from(2), Fp::from(1)]),
+        };
+
+        let instance = public_instance(source, commitment, commit, 2);
+        let prover = MockProver::run(K, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "zk-prover")]
+#[command(about = "Generate a zero-knowledge proof that a test run passed", long_about = None)]
+struct Args {
+    /// Git commit SHA the tests were run on
+    #[arg(long)]
+    commit: String,
+
+    /// Hex-encoded hash of the source tree the tests were run on
+    #[arg(long)]
+    source_hash: String,
+
+    /// Test output (libtest or cargo-nextest) to commit to
+    #[arg(long)]
+    test_log: PathBuf,
+
+    /// Directory to write `test.proof` and `public.json` into
+    #[arg(long)]
+    out_dir: PathBuf,
+}
+
+const PROOF_FILE: &str = "test.proof";
+const PUBLIC_FILE: &str = "public.json";
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let test_log = fs::read(&args.test_log)
+        .with_context(|| format!("Failed to read test log: {}", args.test_log.display()))?;
+
+    println!("🧮 Generating proof...");
+    let (public, proof) = zk_prover::prove(&args.commit, &args.source_hash, &test_log, PROOF_FILE)?;
+
+    fs::create_dir_all(&args.out_dir)
+        .with_context(|| format!("Failed to create {}", args.out_dir.display()))?;
+
+    let proof_path = args.out_dir.join(PROOF_FILE);
+    fs::write(&proof_path, &proof).context("Failed to write proof file")?;
+
+    let public_path = args.out_dir.join(PUBLIC_FILE);
+    let public_json =
+        serde_json::to_string_pretty(&public).context("Failed to serialize public inputs")?;
+    fs::write(&public_path, public_json).context("Failed to write public inputs")?;
+
+    println!("✓ Proof generated successfully: {}", proof_path.display());
+    println!("  Public inputs: {}", public_path.display());
+    println!("  Commit: {}", public.commit);
+    println!("  Tests passed: {}", public.tests_passed);
+    println!("  Commitment: {}", public.commitment);
+
+    Ok(())
+}
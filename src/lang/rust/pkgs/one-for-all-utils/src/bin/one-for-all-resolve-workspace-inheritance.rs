@@ -1,75 +1,209 @@
 use anyhow::Context;
-use serde::Deserialize;
 use std::{
     env,
     fs::File,
     io::{stdout, Read, Write},
     mem,
-    path::Path,
-    process::{Command, Stdio},
+    path::{Component, Path, PathBuf},
+    process,
     str::FromStr,
 };
 use toml_edit::{Item, Table};
 
 fn main() {
-    let mut args = env::args();
-
-    let _ = args.next(); // Skip our name
+    let mut lenient = false;
+    let mut cargo_toml = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--lenient" => lenient = true,
+            _ if cargo_toml.is_none() => cargo_toml = Some(arg),
+            _ => eprintln!("ignoring argument: {arg}"),
+        }
+    }
 
-    let cargo_toml = args
-        .next()
-        .expect("please specify a path to a Cargo.toml file");
+    let cargo_toml = cargo_toml.expect("please specify a path to a Cargo.toml file");
     let cargo_toml = Path::new(&cargo_toml);
 
-    args.for_each(|arg| eprintln!("ignoring argument: {arg}"));
-
-    env::set_current_dir(cargo_toml.parent().expect("can't cd into Cargo.toml dir"))
-        .expect("can't cd into Cargo.toml dir");
-
     if let Err(err) = resolve_and_print_cargo_toml(cargo_toml) {
+        if !lenient {
+            eprintln!("error: cannot resolve workspace inheritance: {err:?}");
+            process::exit(1);
+        }
+
+        // Leave the manifest as it was rather than printing nothing in its place
         eprintln!("ignoring error in resolving workspace inheritance: {err:?}");
+        if let Err(err) = print_unchanged(cargo_toml) {
+            eprintln!("error: {err:?}");
+            process::exit(1);
+        }
     }
 }
 
-#[derive(Deserialize)]
-struct CargoMetadata {
-    workspace_root: String,
+fn print_unchanged(cargo_toml: &Path) -> anyhow::Result<()> {
+    let mut buf = Vec::new();
+    File::open(cargo_toml)
+        .and_then(|mut file| file.read_to_end(&mut buf))
+        .with_context(|| format!("cannot read {}", cargo_toml.display()))?;
+    stdout()
+        .write_all(&buf)
+        .context("failed to print Cargo.toml")
 }
 
 fn resolve_and_print_cargo_toml(cargo_toml: &Path) -> anyhow::Result<()> {
-    let root_toml = Command::new("cargo")
-        .arg("metadata")
-        .arg("--no-deps")
-        .arg("--format-version")
-        .arg("1")
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .output()
-        .context("failed to get cargo metadata")
-        .and_then(|output| {
-            if output.status.success() {
-                serde_json::from_slice::<CargoMetadata>(&output.stdout)
-                    .map(|metadata| metadata.workspace_root)
-                    .context("cannot parse Cargo.toml")
-            } else {
-                anyhow::bail!("`cargo metadata` failed")
-            }
-        })?;
-
-    let root_toml = Path::new(&root_toml);
-    if !root_toml.exists() {
-        anyhow::bail!("cannot read workspace root Cargo.toml");
+    let cargo_toml_path = cargo_toml
+        .canonicalize()
+        .with_context(|| format!("cannot find {}", cargo_toml.display()))?;
+    let member_dir = cargo_toml_path
+        .parent()
+        .context("Cargo.toml has no parent directory")?;
+
+    let mut cargo_toml = parse_toml(&cargo_toml_path)?;
+    if let Some(workspace_root) = find_workspace_root(member_dir, &cargo_toml)? {
+        let paths = Paths {
+            workspace_root: &workspace_root,
+            member_dir,
+        };
+        let warnings = merge(
+            &mut cargo_toml,
+            &parse_toml(&workspace_root.join("Cargo.toml"))?,
+            paths,
+        )
+        .with_context(|| format!("cannot resolve {}", cargo_toml_path.display()))?;
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
     }
 
-    let mut cargo_toml = parse_toml(cargo_toml)?;
-    merge(&mut cargo_toml, &parse_toml(&root_toml.join("Cargo.toml"))?);
-
     stdout()
         .write_all(cargo_toml.to_string().as_bytes())
         .context("failed to print updated Cargo.toml")
 }
 
+/// Find the directory of the workspace `member` (living in `member_dir`) belongs to, the same
+/// way cargo does: `package.workspace` wins, otherwise the closest ancestor (including the
+/// member itself) with a `[workspace]` table which doesn't exclude the member.
+///
+/// Returns `None` for a crate which isn't part of any workspace.
+fn find_workspace_root(
+    member_dir: &Path,
+    member: &toml_edit::DocumentMut,
+) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(root) = workspace_pointer(member_dir, member)? {
+        return Ok(Some(root));
+    }
+    if member.get("workspace").is_some() {
+        return Ok(Some(member_dir.to_path_buf()));
+    }
+
+    for dir in member_dir.ancestors().skip(1) {
+        let manifest = dir.join("Cargo.toml");
+        if !manifest.is_file() {
+            continue;
+        }
+
+        let toml = parse_toml(&manifest)?;
+        if let Some(workspace) = toml.get("workspace").and_then(try_as_table_like) {
+            let relative = member_dir
+                .strip_prefix(dir)
+                .expect("ancestors are prefixes");
+            if !is_excluded(workspace, relative) {
+                return Ok(Some(dir.to_path_buf()));
+            }
+        } else if let Some(root) = workspace_pointer(dir, &toml)? {
+            // Like cargo, trust an ancestor package pointing at its workspace
+            return Ok(Some(root));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The workspace root a package's `package.workspace` key points at
+fn workspace_pointer(
+    dir: &Path,
+    manifest: &toml_edit::DocumentMut,
+) -> anyhow::Result<Option<PathBuf>> {
+    let Some(pointer) = manifest.get("package").and_then(|p| p.get("workspace")) else {
+        return Ok(None);
+    };
+
+    let pointer = pointer
+        .as_str()
+        .with_context(|| format!("`package.workspace` in {} is not a string", dir.display()))?;
+    let root = normalize(&dir.join(pointer));
+    let manifest = root.join("Cargo.toml");
+    if !manifest.is_file() {
+        anyhow::bail!(
+            "`package.workspace` in {} points at {}, which doesn't exist",
+            dir.display(),
+            manifest.display()
+        );
+    }
+
+    Ok(Some(root))
+}
+
+/// Whether a workspace leaves out the member at `relative` (to the workspace root): it has to be
+/// below an `exclude` entry without being listed in `members`
+fn is_excluded(workspace: &dyn toml_edit::TableLike, relative: &Path) -> bool {
+    let patterns = |key| {
+        workspace
+            .get(key)
+            .and_then(Item::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str())
+            .map(|pattern| normalize(Path::new(pattern)))
+            .collect::<Vec<_>>()
+    };
+
+    let excluded = patterns("exclude")
+        .iter()
+        .any(|pattern| relative.starts_with(pattern));
+    excluded
+        && !patterns("members")
+            .iter()
+            .any(|pattern| glob_matches(pattern, relative))
+}
+
+/// Match `path` against a `workspace.members` style glob (`*`, `?` and `**`)
+fn glob_matches(pattern: &Path, path: &Path) -> bool {
+    fn matches(pattern: &[String], path: &[String]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            Some((first, rest)) if first == "**" => {
+                (0..=path.len()).any(|skip| matches(rest, &path[skip..]))
+            }
+            Some((first, rest)) => path
+                .split_first()
+                .is_some_and(|(name, path)| component_matches(first, name) && matches(rest, path)),
+        }
+    }
+
+    let components = |path: &Path| {
+        path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    matches(&components(pattern), &components(path))
+}
+
+/// Match a single path component against a pattern with `*` and `?` wildcards
+fn component_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
 fn parse_toml(path: &Path) -> anyhow::Result<toml_edit::DocumentMut> {
     let mut buf = String::new();
     File::open(path)
@@ -80,39 +214,210 @@ fn parse_toml(path: &Path) -> anyhow::Result<toml_edit::DocumentMut> {
         .with_context(|| format!("cannot parse {}", path.display()))
 }
 
-/// Merge the workspace `root` toml into the specified crate's `cargo_toml`
-fn merge(cargo_toml: &mut toml_edit::DocumentMut, root: &toml_edit::DocumentMut) {
-    let w: &dyn toml_edit::TableLike =
-        if let Some(w) = root.get("workspace").and_then(try_as_table_like) {
-            w
+/// Where the workspace root and the crate being resolved live
+///
+/// Paths in the workspace root are relative to it, so any inherited path has to be rebased
+/// before it lands in the crate's `Cargo.toml`.
+#[derive(Clone, Copy)]
+struct Paths<'a> {
+    workspace_root: &'a Path,
+    member_dir: &'a Path,
+}
+
+impl Paths<'_> {
+    /// Rebase `path` (relative to the workspace root) so it is relative to the member directory
+    fn rebase(&self, path: &str) -> String {
+        if Path::new(path).is_absolute() {
+            return path.to_string();
+        }
+
+        let target = normalize(&self.workspace_root.join(path));
+        let member = normalize(self.member_dir);
+        let common = target
+            .components()
+            .zip(member.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut rebased = PathBuf::new();
+        member
+            .components()
+            .skip(common)
+            .for_each(|_| rebased.push(".."));
+        target
+            .components()
+            .skip(common)
+            .for_each(|c| rebased.push(c));
+
+        if rebased.as_os_str().is_empty() {
+            ".".to_string()
         } else {
-            // no "workspace" entry, nothing to merge
-            return;
-        };
+            // Cargo accepts `/` on every platform
+            rebased.to_string_lossy().replace('\\', "/")
+        }
+    }
+
+    /// Rebase `item` if it is a string, keeping its formatting
+    fn rebase_value(&self, item: &mut Item) {
+        if let Item::Value(toml_edit::Value::String(value)) = item {
+            let decor = value.decor().clone();
+            *value = toml_edit::Formatted::new(self.rebase(value.value()));
+            *value.decor_mut() = decor;
+        }
+    }
+}
+
+/// Lexically resolve `.` and `..` components (without touching the file system, since the
+/// paths may not exist yet)
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// The `package` keys a crate can inherit from `workspace.package`
+const INHERITABLE_PACKAGE_KEYS: [&str; 16] = [
+    "authors",
+    "categories",
+    "description",
+    "documentation",
+    "edition",
+    "exclude",
+    "homepage",
+    "include",
+    "keywords",
+    "license",
+    "license-file",
+    "publish",
+    "readme",
+    "repository",
+    "rust-version",
+    "version",
+];
+
+/// The first edition in which Cargo turns some inheritance warnings into errors
+const EDITION_2024: u16 = 2024;
+
+/// The keys a `workspace = true` dependency may set on top of the workspace's definition
+const DEPENDENCY_OVERRIDES: [&str; 5] = [
+    "features",
+    "optional",
+    "default-features",
+    "default_features",
+    "public",
+];
+
+/// Merge the workspace `root` toml into the specified crate's `cargo_toml`, the way Cargo does
+///
+/// Returns the warnings Cargo would print about the crate's inheritance, e.g. for keys it ignores.
+fn merge(
+    cargo_toml: &mut toml_edit::DocumentMut,
+    root: &toml_edit::DocumentMut,
+    paths: Paths,
+) -> anyhow::Result<Vec<String>> {
+    let no_workspace = Table::new();
+    let w = root
+        .get("workspace")
+        .and_then(try_as_table_like)
+        .unwrap_or(&no_workspace);
+    let mut warnings = Vec::new();
+    let edition = edition(cargo_toml, w);
 
     // https://doc.rust-lang.org/cargo/reference/workspaces.html#workspaces
-    let w_deps = w.get("dependencies");
-    for key in ["dependencies", "dev-dependencies", "build-dependencies"] {
-        if let Some((cargo_toml, root)) = cargo_toml.get_mut(key).zip(w_deps) {
-            try_merge_dependencies_tables(cargo_toml, root);
+    let w_deps = w.get("dependencies").and_then(try_as_table_like);
+    if let Some(w_deps) = w_deps {
+        check_workspace_dependencies(w_deps)?;
+    }
+    for key in [
+        "dependencies",
+        "dev-dependencies",
+        "dev_dependencies",
+        "build-dependencies",
+        "build_dependencies",
+    ] {
+        if let Some(cargo_toml) = cargo_toml.get_mut(key) {
+            try_merge_dependencies_tables(cargo_toml, key, w_deps, edition, paths, &mut warnings)?;
         };
 
         if let Some(targets) = cargo_toml.get_mut("target").and_then(try_as_table_like_mut) {
-            for (_, tp) in targets.iter_mut() {
-                if let Some((cargo_toml, root)) = tp.get_mut(key).zip(w_deps) {
-                    try_merge_dependencies_tables(cargo_toml, root);
+            for (target, tp) in targets.iter_mut() {
+                if let Some(cargo_toml) = tp.get_mut(key) {
+                    let table = format!("target.{}.{key}", target.get());
+                    try_merge_dependencies_tables(
+                        cargo_toml,
+                        &table,
+                        w_deps,
+                        edition,
+                        paths,
+                        &mut warnings,
+                    )?;
                 }
             }
         }
     }
 
-    if let Some((cargo_toml, root)) = cargo_toml.get_mut("package").zip(w.get("package")) {
-        try_merge_cargo_tables(cargo_toml, root);
+    if let Some(cargo_toml) = cargo_toml.get_mut("package") {
+        let root = w.get("package").and_then(try_as_table_like);
+        try_merge_cargo_tables(cargo_toml, root, paths, &mut warnings)?;
+    };
+
+    if let Some(cargo_toml) = cargo_toml.get_mut("lints") {
+        try_inherit_cargo_table(cargo_toml, w.get("lints"))?;
     };
 
-    if let Some((cargo_toml, root)) = cargo_toml.get_mut("lints").zip(w.get("lints")) {
-        try_inherit_cargo_table(cargo_toml, root);
+    Ok(warnings)
+}
+
+/// The crate's edition (following `edition.workspace = true`), 2015 if it doesn't set one
+///
+/// An edition that isn't a year is left for the `package` merge or Cargo itself to reject.
+fn edition(cargo_toml: &toml_edit::DocumentMut, workspace: &dyn toml_edit::TableLike) -> u16 {
+    let edition = cargo_toml.get("package").and_then(|p| p.get("edition"));
+    let edition = match edition {
+        Some(item) if inherits(item).unwrap_or(false) => {
+            workspace.get("package").and_then(|p| p.get("edition"))
+        }
+        edition => edition,
     };
+    edition
+        .and_then(Item::as_str)
+        .and_then(|edition| edition.parse().ok())
+        .unwrap_or(2015)
+}
+
+/// Reject the `workspace.dependencies` Cargo refuses to load
+fn check_workspace_dependencies(deps: &dyn toml_edit::TableLike) -> anyhow::Result<()> {
+    for (name, dep) in deps.iter() {
+        let flag = |key| {
+            try_as_table_like(dep)
+                .and_then(|t| t.get(key))
+                .and_then(Item::as_bool)
+                .unwrap_or_default()
+        };
+        if flag("workspace") {
+            anyhow::bail!(
+                "`workspace.dependencies.{name}` specified `{{ workspace = true }}`, but \
+                 workspace dependencies cannot do this"
+            );
+        }
+        if flag("optional") {
+            anyhow::bail!("{name} is optional, but workspace dependencies cannot be optional");
+        }
+        if flag("public") {
+            anyhow::bail!("{name} is public, but workspace dependencies cannot be public");
+        }
+    }
+    Ok(())
 }
 
 /// Return a [`toml_edit::TableLike`] representation of the [`Item`] (if any)
@@ -133,232 +438,442 @@ fn try_as_table_like_mut(item: &mut Item) -> Option<&mut dyn toml_edit::TableLik
     }
 }
 
-/// Inherit the specified `cargo_toml` from workspace `root` if the former is a table
-fn try_inherit_cargo_table(cargo_toml: &mut Item, root: &Item) {
-    let Some(t) = try_as_table_like_mut(cargo_toml) else {
-        return;
-    };
-    if t.get("workspace")
-        .and_then(Item::as_bool)
-        .unwrap_or_default()
-    {
-        t.remove("workspace");
-        let orig_val = mem::replace(cargo_toml, root.clone());
-        merge_items(cargo_toml, orig_val);
-    }
-}
-
-/// Merge the specified `cargo_toml` and workspace `root` if both are tables
-fn try_merge_cargo_tables(cargo_toml: &mut Item, root: &Item) {
-    let cargo_toml = try_as_table_like_mut(cargo_toml);
-    let root = try_as_table_like(root);
-
-    if let Some((cargo_toml, root)) = cargo_toml.zip(root) {
-        merge_cargo_tables(cargo_toml, root);
-    }
-}
-/// Merge the specified `cargo_toml` and workspace `root` tables
-fn merge_cargo_tables<T, U>(cargo_toml: &mut T, root: &U)
-where
-    T: toml_edit::TableLike + ?Sized,
-    U: toml_edit::TableLike + ?Sized,
-{
-    cargo_toml.iter_mut().for_each(|(k, v)| {
-        // Bail if:
-        // - cargo_toml isn't a table (otherwise `workspace = true` can't show up
-        // - the workspace root doesn't have this key
-        let (t, root_val) = match try_as_table_like_mut(&mut *v).zip(root.get(&k)) {
-            Some((t, root_val)) => (t, root_val),
-            _ => return,
-        };
+/// Inherit the specified `cargo_toml` (`[lints]`) from workspace `root` if it asks to
+fn try_inherit_cargo_table(cargo_toml: &mut Item, root: Option<&Item>) -> anyhow::Result<()> {
+    if !inherits(cargo_toml).context("invalid `lints`")? {
+        return Ok(());
+    }
+    if try_as_table_like(cargo_toml).is_some_and(|t| t.len() > 1) {
+        anyhow::bail!(
+            "cannot override `workspace.lints` in `lints`, either remove the overrides or \
+             `lints.workspace = true` and manually specify the lints"
+        );
+    }
+    let root = root
+        .context("`workspace.lints` was not defined")
+        .context("error inheriting `lints` from workspace root manifest's `workspace.lints`")?;
+
+    let orig_val = mem::replace(cargo_toml, root.clone());
+    if let (Item::Table(inherited), Item::Table(orig)) = (&mut *cargo_toml, &orig_val) {
+        // Keep the tables where the crate had them, not where the workspace root does
+        *inherited.decor_mut() = orig.decor().clone();
+        inherited.set_implicit(orig.is_implicit());
+        move_to(inherited, orig.position());
+    }
+    Ok(())
+}
 
-        if let Some(Item::Value(toml_edit::Value::Boolean(bool_value))) = t.get("workspace") {
-            if *bool_value.value() {
-                t.remove("workspace");
-                let orig_val = mem::replace(v, root_val.clone());
-                merge_items(v, orig_val);
-            }
+/// Give `table` and all tables below it the document `position`
+fn move_to(table: &mut Table, position: Option<usize>) {
+    if let Some(position) = position {
+        table.set_position(position);
+    }
+    for (_, item) in table.iter_mut() {
+        if let Item::Table(t) = item {
+            // Sub-tables come from the workspace root, with its comments between them
+            t.decor_mut().clear();
+            move_to(t, position);
         }
-    });
+    }
 }
 
-/// Merge the specified `cargo_toml` and workspace `root` if both are dependency tables
-fn try_merge_dependencies_tables(cargo_toml: &mut Item, root: &Item) {
-    let cargo_toml = try_as_table_like_mut(cargo_toml);
-    let root = try_as_table_like(root);
-
-    if let Some((cargo_toml, root)) = cargo_toml.zip(root) {
-        merge_dependencies_tables(cargo_toml, root);
+/// Merge the specified `cargo_toml` and workspace `root` if the former is a table
+fn try_merge_cargo_tables(
+    cargo_toml: &mut Item,
+    root: Option<&dyn toml_edit::TableLike>,
+    paths: Paths,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    match try_as_table_like_mut(cargo_toml) {
+        Some(cargo_toml) => merge_cargo_tables(cargo_toml, root, paths, warnings),
+        None => Ok(()),
     }
 }
 
-/// Merge the specified `cargo_toml` and workspace `root` dependencies tables
-fn merge_dependencies_tables<T, U>(cargo_toml: &mut T, root: &U)
-where
-    T: toml_edit::TableLike + ?Sized,
-    U: toml_edit::TableLike + ?Sized,
-{
-    use toml_edit::Value;
-
-    cargo_toml.iter_mut().for_each(|(mut k, v)| {
-        // NB: ignore any comments on dependencies as toml_edit appears to mangle them
-        // https://github.com/ipetkov/crane/issues/527
-        // https://github.com/toml-rs/toml/issues/691
-        k.leaf_decor_mut().clear();
-
-        // Bail if:
-        // - cargo_toml isn't a table (otherwise `workspace = true` can't show up
-        // - the workspace root doesn't have this key
-        let (t, root_val) = match try_as_table_like_mut(&mut *v).zip(root.get(&k)) {
-            Some((t, root_val)) => (t, root_val),
-            _ => return,
-        };
+/// Merge the specified `cargo_toml` and workspace `root` (`package`) tables
+fn merge_cargo_tables(
+    cargo_toml: &mut dyn toml_edit::TableLike,
+    root: Option<&dyn toml_edit::TableLike>,
+    paths: Paths,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    for (mut k, v) in cargo_toml.iter_mut() {
+        let key = k.get().to_string();
+        // Anything else (e.g. `package.metadata`) is the crate's own, even if it says `workspace`
+        if !INHERITABLE_PACKAGE_KEYS.contains(&key.as_str())
+            || !inherits(v).with_context(|| format!("invalid `package.{key}`"))?
+        {
+            continue;
+        }
+        let root_val = root
+            .and_then(|root| root.get(&key))
+            .with_context(|| format!("`workspace.package.{key}` was not defined"))
+            .with_context(|| {
+                format!(
+                    "error inheriting `{key}` from workspace root manifest's \
+                     `workspace.package.{key}`"
+                )
+            })?;
+
+        if let Some(t) = try_as_table_like(v) {
+            warnings.extend(
+                t.iter()
+                    .filter(|(unused, _)| *unused != "workspace")
+                    .map(|(unused, _)| format!("unused manifest key: package.{key}.{unused}")),
+            );
+        }
 
-        if let Some(Item::Value(toml_edit::Value::Boolean(bool_value))) = t.get("workspace") {
-            if *bool_value.value() {
-                t.remove("workspace");
-                let orig_val = mem::replace(
-                    v,
-                    match root_val.clone() {
-                        s @ Item::Value(Value::String(_)) => {
-                            let mut table = Table::new();
-                            table.insert("version", s);
-                            Item::Table(table)
-                        }
-                        v => v,
-                    },
-                );
-
-                merge_items(v, orig_val);
+        hoist_dotted_decor(&mut k, v);
+        let comment = trailing_comment(v);
+        *v = root_val.clone();
+        if let Some(value) = v.as_value_mut() {
+            // Laid out like the `{ workspace = true }` it replaces, not like the root
+            value.decor_mut().clear();
+            if let Some(comment) = comment {
+                value.decor_mut().set_suffix(comment);
             }
         }
-    });
+        // The only package fields which are paths (`readme` may also be a bool)
+        if matches!(key.as_str(), "readme" | "license-file") {
+            paths.rebase_value(v);
+        }
+    }
+    Ok(())
 }
 
-/// Recursively merge the `additional` item into the specified `dest`
-fn merge_items(dest: &mut Item, additional: Item) {
-    use toml_edit::Value;
+/// The comment trailing a `key = { workspace = true } # ...` or `key.workspace = true # ...`
+fn trailing_comment(item: &Item) -> Option<toml_edit::RawString> {
+    let own = item.as_value().map(|v| v.decor());
+    let workspace = try_as_table_like(item)
+        .and_then(|t| t.get("workspace"))
+        .and_then(Item::as_value)
+        .map(|v| v.decor());
+
+    own.into_iter()
+        .chain(workspace)
+        .filter_map(|decor| decor.suffix())
+        .find(|suffix| suffix.as_str().is_some_and(|s| s.contains('#')))
+        .cloned()
+}
 
-    match additional {
-        Item::Value(additional) => match additional {
-            Value::String(_)
-            | Value::Integer(_)
-            | Value::Float(_)
-            | Value::Boolean(_)
-            | Value::Datetime(_) => {
-                // Override dest completely for raw values
-                *dest = Item::Value(additional);
-            }
+/// Merge the specified `cargo_toml` and workspace `root` if the former is a dependency table
+fn try_merge_dependencies_tables(
+    cargo_toml: &mut Item,
+    table: &str,
+    root: Option<&dyn toml_edit::TableLike>,
+    edition: u16,
+    paths: Paths,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    match try_as_table_like_mut(cargo_toml) {
+        Some(cargo_toml) => {
+            merge_dependencies_tables(cargo_toml, table, root, edition, paths, warnings)
+        }
+        None => Ok(()),
+    }
+}
 
-            Value::Array(additional) => {
-                if let Item::Value(Value::Array(dest)) = dest {
-                    dest.extend(additional);
-                } else {
-                    // Override dest completely if types don't match
-                    *dest = Item::Value(Value::Array(additional));
-                }
-            }
+/// Merge the specified `cargo_toml` and workspace `root` dependencies tables
+fn merge_dependencies_tables(
+    cargo_toml: &mut dyn toml_edit::TableLike,
+    table: &str,
+    root: Option<&dyn toml_edit::TableLike>,
+    edition: u16,
+    paths: Paths,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    for (mut k, v) in cargo_toml.iter_mut() {
+        let name = k.get().to_string();
+        if !inherits(v).with_context(|| format!("invalid dependency `{table}.{name}`"))? {
+            continue;
+        }
+        let inheriting = || {
+            format!(
+                "error inheriting `{name}` from workspace root manifest's \
+                 `workspace.dependencies.{name}`"
+            )
+        };
+        let root_val = match root {
+            Some(root) => root.get(&name).with_context(|| {
+                format!("`dependency.{name}` was not found in `workspace.dependencies`")
+            }),
+            None => Err(anyhow::anyhow!("`workspace.dependencies` was not defined")),
+        }
+        .with_context(inheriting)?;
+
+        hoist_dotted_decor(&mut k, v);
+        inherit_dependency(&name, table, v, root_val, edition, paths, warnings)
+            .with_context(inheriting)?;
+
+        // Cargo checks the dependency it ends up with, which carries the workspace's keys
+        let deprecated =
+            try_as_table_like(root_val).is_some_and(|t| t.contains_key("default_features"));
+        if deprecated && edition >= EDITION_2024 {
+            anyhow::bail!(
+                "`default_features` is unsupported as of the 2024 edition; instead use \
+                 `default-features`\n(in the `{name}` dependency)"
+            );
+        }
+    }
+    Ok(())
+}
 
-            Value::InlineTable(additional) => {
-                merge_tables(dest, additional);
-            }
+/// In `key.workspace = true`, the comments and indentation in front of the line belong to
+/// `workspace`; move them to `key` before the dotted table is replaced
+fn hoist_dotted_decor(key: &mut toml_edit::KeyMut, item: &Item) {
+    let Item::Table(t) = item else {
+        return;
+    };
+    let prefix = t
+        .iter()
+        .next()
+        .filter(|_| t.is_dotted())
+        .and_then(|(first, _)| t.key(first))
+        .and_then(|first| first.leaf_decor().prefix())
+        .cloned();
+    if let Some(prefix) = prefix {
+        key.leaf_decor_mut().set_prefix(prefix);
+    }
+}
+
+/// Whether `item` is a table with `workspace = true`
+fn inherits(item: &Item) -> anyhow::Result<bool> {
+    match try_as_table_like(item).and_then(|t| t.get("workspace")) {
+        None => Ok(false),
+        Some(workspace) => match workspace.as_bool() {
+            Some(true) => Ok(true),
+            Some(false) => anyhow::bail!("`workspace` cannot be false"),
+            None => anyhow::bail!("`workspace` must be a boolean"),
         },
-        Item::Table(additional) => {
-            merge_tables(dest, additional);
-        }
-        Item::None => {}
-        Item::ArrayOfTables(additional) => {
-            if let Item::ArrayOfTables(dest) = dest {
-                dest.extend(additional);
-            } else {
-                // Override dest completely if types don't match
-                *dest = Item::ArrayOfTables(additional);
-            }
-        }
     }
 }
 
-use table_like_ext::merge_tables;
-mod table_like_ext {
-    //! Helper functions to merge values in any combination of the two [`TableLike`] items
-    //! found in [`toml_edit`]
+/// The `default-features` (or deprecated `default_features`) among a dependency's entries
+fn default_features<'a>(mut entries: impl Iterator<Item = (&'a str, &'a Item)>) -> Option<bool> {
+    entries
+        .find(|(k, _)| is_default_features(k))
+        .and_then(|(_, v)| v.as_bool())
+}
 
-    use toml_edit::{Item, TableLike};
+fn is_default_features(key: &str) -> bool {
+    matches!(key, "default-features" | "default_features")
+}
 
-    /// Recursively merge the `additional` table into `dest` (overwriting if `dest` is not a table)
-    pub(super) fn merge_tables<T>(dest: &mut Item, additional: T)
-    where
-        T: TableLikeExt,
-    {
-        match dest {
-            Item::Table(dest) => merge_table_like(dest, additional),
-            Item::Value(toml_edit::Value::InlineTable(dest)) => merge_table_like(dest, additional),
-            _ => {
-                // Override dest completely if types don't match, but also
-                // skip empty tables (i.e. if we had `key = { workspace = true }`
-                if !additional.is_empty() {
-                    *dest = additional.into_item();
-                }
+/// Replace the `workspace = true` of the dependency `dep` with the workspace's definition of it
+///
+/// The dependency is edited in place so its layout and comments survive: the inherited keys take
+/// the place of `workspace = true`, and inherited features are prepended to the crate's own.
+///
+/// https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#inheriting-a-dependency-from-a-workspace
+fn inherit_dependency(
+    name: &str,
+    table: &str,
+    dep: &mut Item,
+    root: &Item,
+    edition: u16,
+    paths: Paths,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    use toml_edit::{Key, Value};
+
+    // `dep.workspace = true` would become a run of `dep.version = ...` lines
+    let comment = trailing_comment(dep);
+    if let Item::Table(t) = dep {
+        if t.is_dotted() {
+            let mut inline = mem::take(t).into_inline_table();
+            inline.set_dotted(false);
+            if let Some(comment) = comment {
+                inline.decor_mut().set_suffix(comment);
             }
+            *dep = Item::Value(Value::InlineTable(inline));
         }
     }
 
-    /// Recursively merge two tables
-    fn merge_table_like<T, U>(dest: &mut T, additional: U)
-    where
-        T: TableLike,
-        U: TableLikeExt,
-    {
-        additional
-            .into_iter()
-            .map(U::map_iter_item)
-            .for_each(|(k, v)| match dest.get_mut(&k) {
-                Some(existing) => super::merge_items(existing, v),
-                None => {
-                    dest.insert(&k, v);
-                }
-            });
+    let mut inherited = match root {
+        s @ Item::Value(Value::String(_)) => vec![("version".to_string(), s.clone())],
+        root => try_as_table_like(root)
+            .map(|t| t.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+            .unwrap_or_default(),
+    };
+    for (k, v) in &mut inherited {
+        if let Some(value) = v.as_value_mut() {
+            value.decor_mut().clear();
+        }
+        if k == "path" {
+            paths.rebase_value(v);
+        }
     }
 
-    /// Generalized form of the item yielded by [`IntoIterator`] for the two [`TableLike`] types
-    /// in [`toml_edit`]
-    type CommonIterItem = (toml_edit::InternalString, Item);
-
-    /// Extension trait to iterate [`Item`]s from a [`TableLike`] item
-    pub(super) trait TableLikeExt: TableLike + IntoIterator {
-        /// Convert the iterator item to a common type
-        fn map_iter_item(item: Self::Item) -> CommonIterItem;
+    let mut own = take_entries(dep);
+    own.retain(|(key, _)| {
+        let used = key.get() == "workspace" || DEPENDENCY_OVERRIDES.contains(&key.get());
+        if !used {
+            warnings.push(format!("unused manifest key: {table}.{name}.{}", key.get()));
+        }
+        used
+    });
+    if let Some((key, _)) = own
+        .iter_mut()
+        .find(|(key, _)| key.get() == "default_features")
+    {
+        if edition >= EDITION_2024 {
+            // Still read from a `workspace = true` dependency, but not from the resolved one
+            let mut renamed = Key::new("default-features");
+            *renamed.leaf_decor_mut() = key.leaf_decor().clone();
+            *key = renamed;
+        } else {
+            warnings.push(format!(
+                "`default_features` is deprecated in favor of `default-features` and will not \
+                 work in the 2024 edition\n(in the `{name}` dependency)"
+            ));
+        }
+    }
 
-        /// Convert the table into an [`Item`]
-        fn into_item(self) -> Item;
+    // A crate can turn default features back on, but not off
+    let own_default = default_features(own.iter().map(|(k, v)| (k.get(), v)));
+    let root_default = default_features(inherited.iter().map(|(k, v)| (k.as_str(), v)));
+    match (own_default, root_default) {
+        (Some(false), Some(true) | None) if edition >= EDITION_2024 => {
+            anyhow::bail!(
+                "`default-features = false` cannot override workspace's `default-features`"
+            )
+        }
+        (Some(false), Some(true) | None) => {
+            let root_default = root_default.map_or("not specified", |_| "true");
+            warnings.push(format!(
+                "`default-features` is ignored for {name}, since `default-features` was \
+                 {root_default} for `workspace.dependencies.{name}`, this could become a hard \
+                 error in the future"
+            ));
+            own.retain(|(k, _)| !is_default_features(k.get()));
+        }
+        (Some(_), _) => inherited.retain(|(k, _)| !is_default_features(k)),
+        (None, _) => {}
     }
 
-    impl TableLikeExt for toml_edit::Table {
-        fn map_iter_item(item: Self::Item) -> CommonIterItem {
-            item
+    let (mut shared, mut inherited): (Vec<_>, Vec<_>) = inherited
+        .into_iter()
+        .partition(|(k, _)| own.iter().any(|(key, _)| key.get() == k));
+
+    let mut entries = Vec::new();
+    for (key, mut item) in own {
+        if key.get() != "workspace" {
+            if let Some(i) = shared.iter().position(|(k, _)| k == key.get()) {
+                merge_dependency_key(&mut item, shared.remove(i).1);
+            }
+            entries.push((key, item));
+            continue;
         }
 
-        fn into_item(self) -> Item {
-            Item::Table(self)
+        // The inherited keys take over the comments around `workspace = true`
+        let start = entries.len();
+        entries.extend(
+            mem::take(&mut inherited)
+                .into_iter()
+                .map(|(k, v)| (Key::new(k), v)),
+        );
+        if let Some((first, _)) = entries.get_mut(start) {
+            *first.leaf_decor_mut() = key.leaf_decor().clone();
+        }
+        if let Some(suffix) = item.as_value().and_then(|v| v.decor().suffix()).cloned() {
+            if let Some(value) = entries[start..]
+                .last_mut()
+                .and_then(|(_, v)| v.as_value_mut())
+            {
+                value.decor_mut().set_suffix(suffix);
+            }
         }
     }
 
-    impl TableLikeExt for toml_edit::InlineTable {
-        fn map_iter_item(item: Self::Item) -> CommonIterItem {
-            let (k, v) = item;
-            (k, Item::Value(v))
+    put_entries(dep, entries);
+    Ok(())
+}
+
+/// Merge the workspace's `root` value of a key the crate's dependency also sets
+fn merge_dependency_key(item: &mut Item, root: Item) {
+    use toml_edit::Value;
+
+    // Features are the union of both; otherwise (e.g. `optional = false`) the crate's own wins
+    let (Some(Value::Array(dest)), Item::Value(Value::Array(root))) = (item.as_value_mut(), root)
+    else {
+        return;
+    };
+
+    // Cargo keeps duplicates, so `cargo metadata` would list them twice too
+    let missing: Vec<_> = root.into_iter().collect();
+    // New elements are formatted like the crate's first one, so multi-line arrays stay that way
+    let decor = dest.get(0).map(|v| v.decor().clone());
+    let count = missing.len();
+    for (i, mut value) in missing.into_iter().enumerate() {
+        match &decor {
+            Some(decor) => *value.decor_mut() = decor.clone(),
+            None => value.decor_mut().clear(),
         }
+        dest.insert_formatted(i, value);
+    }
+    // ... except for the space after the comma, which the first element of `["a", "b"]` lacks
+    for value in dest.iter_mut().skip(1).take(count) {
+        if value.decor().prefix().and_then(|p| p.as_str()) == Some("") {
+            value.decor_mut().set_prefix(" ");
+        }
+    }
+}
+
+/// Remove and return the entries of a dependency table, keeping their formatting
+fn take_entries(dep: &mut Item) -> Vec<(toml_edit::Key, Item)> {
+    let Some(t) = try_as_table_like_mut(dep) else {
+        return Vec::new();
+    };
+    let keys: Vec<String> = t.iter().map(|(k, _)| k.to_string()).collect();
+    match dep {
+        Item::Table(t) => keys.iter().filter_map(|k| t.remove_entry(k)).collect(),
+        Item::Value(toml_edit::Value::InlineTable(t)) => keys
+            .iter()
+            .filter_map(|k| t.remove_entry(k))
+            .map(|(k, v)| (k, Item::Value(v)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
 
-        fn into_item(self) -> Item {
-            Item::Value(toml_edit::Value::InlineTable(self))
+/// Put `entries` back into the (emptied) dependency table
+fn put_entries(dep: &mut Item, entries: Vec<(toml_edit::Key, Item)>) {
+    match dep {
+        Item::Table(t) => {
+            for (k, v) in entries {
+                t.insert_formatted(&k, v);
+            }
+        }
+        Item::Value(toml_edit::Value::InlineTable(t)) => {
+            let last = entries.len().saturating_sub(1);
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if let Ok(mut v) = v.into_value() {
+                    // Entries may have moved, so whichever is last now gets the space before `}`
+                    // (inline tables can't hold comments, only whitespace)
+                    v.decor_mut().set_suffix(if i == last { " " } else { "" });
+                    t.insert_formatted(&k, v);
+                }
+            }
         }
+        _ => {}
     }
 }
 
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
-    use std::str::FromStr;
+    use std::{path::Path, str::FromStr};
+    use toml_edit::Item;
+
+    use super::Paths;
+
+    /// A member directly below the workspace root
+    fn paths() -> Paths<'static> {
+        Paths {
+            workspace_root: Path::new("/ws"),
+            member_dir: Path::new("/ws/member"),
+        }
+    }
 
     #[test]
     fn smoke() {
@@ -373,7 +888,7 @@ mod tests {
             exclude.workspace = true
             homepage.workspace = true
             include.workspace = true
-            keyword.workspace = true
+            keywords.workspace = true
             license.workspace = true
             license-file.workspace = true
             publish.workspace = true
@@ -439,7 +954,7 @@ mod tests {
             exclude = ["first exclusion", "second exclusion"]
             homepage = "some home page"
             include = ["first inclusion", "second inclusion"]
-            keyword = ["first keyword", "second keyword"]
+            keywords = ["first keyword", "second keyword"]
             license = "some license"
             license-file = "some license-file"
             publish = true
@@ -474,75 +989,73 @@ mod tests {
         // in cargo_toml
         let expected_toml_str = r#"
             [package]
-authors = ["first author", "second author"]
-categories = ["first category", "second category" ]
-description = "some description"
-documentation = "some doc url"
-edition = "2021"
-exclude = ["first exclusion", "second exclusion"]
-homepage = "some home page"
-include = ["first inclusion", "second inclusion"]
-keyword = ["first keyword", "second keyword"]
-license = "some license"
-license-file = "some license-file"
-publish = true
-readme = "some readme"
-repository = "some repository"
-rust-version = "some rust-version"
-version = "some version"
+            authors = ["first author", "second author"]
+            categories = ["first category", "second category" ]
+            description = "some description"
+            documentation = "some doc url"
+            edition = "2021"
+            exclude = ["first exclusion", "second exclusion"]
+            homepage = "some home page"
+            include = ["first inclusion", "second inclusion"]
+            keywords = ["first keyword", "second keyword"]
+            license = "some license"
+            license-file = "../some license-file"
+            publish = true
+            readme = "../some readme"
+            repository = "some repository"
+            rust-version = "some rust-version"
+            version = "some version"
 
             [dependencies]
-foo = { version = "foo-vers" }
-bar = { version = "bar-vers", default-features = false }
-baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
-qux = { version = "qux-vers", features = ["qux-feat","qux-additional"] }
-corge = { version = "corge-vers-override" , features = ["qux-feat"] }
-grault = { version = "grault-vers" }
-garply = "garply-vers"
-waldo = "waldo-vers"
-
-[dependencies.fred]
-version = "0.1.3"
-
-[dependencies.plugh]
-version = "0.2.4"
-optional = true 
+            # the `foo` dependency is most imporant, so it goes first
+            foo = { version = "foo-vers" }
+            bar = { version = "bar-vers", default-features = false }
+            baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
+            qux = { version = "qux-vers", features = ["qux-feat", "qux-additional"] }
+            corge = { version = "corge-vers", features = ["qux-feat"] }
+            grault = { version = "grault-vers" }
+            garply = "garply-vers"
+            waldo = "waldo-vers"
+            fred = { version = "0.1.3" }
+            plugh = { version = "0.2.4", optional = true }
 
             [target.'cfg(unix)'.dependencies]
-unix = { version = "unix-vers" , features = ["some"] }
-
-            [lints.rust]
-            unused_extern_crates = 'warn'
+            unix = { version = "unix-vers", features = ["some"] }
 
             [dev-dependencies]
-foo = { version = "foo-vers" }
-bar = { version = "bar-vers", default-features = false }
-baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
-qux = { version = "qux-vers", features = ["qux-feat","qux-additional"] }
-corge = { version = "corge-vers-override" , features = ["qux-feat"] }
-grault = { version = "grault-vers" }
-garply = "garply-vers"
-waldo = "waldo-vers"
-
-            [lints.clippy]
-            all = 'allow'
+            foo = { version = "foo-vers" }
+            bar = { version = "bar-vers", default-features = false }
+            baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
+            qux = { version = "qux-vers", features = ["qux-feat", "qux-additional"] }
+            corge = { version = "corge-vers", features = ["qux-feat"] }
+            grault = { version = "grault-vers" }
+            garply = "garply-vers"
+            waldo = "waldo-vers"
 
             [build-dependencies]
-foo = { version = "foo-vers" }
-bar = { version = "bar-vers", default-features = false }
-baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
-qux = { version = "qux-vers", features = ["qux-feat","qux-additional"] }
-corge = { version = "corge-vers-override" , features = ["qux-feat"] }
-grault = { version = "grault-vers" }
-garply = "garply-vers"
-waldo = "waldo-vers"
+            foo = { version = "foo-vers" }
+            bar = { version = "bar-vers", default-features = false }
+            baz = { version = "baz-vers", features = ["baz-feat", "baz-feat2"] }
+            qux = { version = "qux-vers", features = ["qux-feat", "qux-additional"] }
+            corge = { version = "corge-vers", features = ["qux-feat"] }
+            grault = { version = "grault-vers" }
+            garply = "garply-vers"
+            waldo = "waldo-vers"
 
             [features]
             # this feature is a demonstration that comments are preserved
             my_feature = []
+
+            [lints]
+
+[lints.rust]
+            unused_extern_crates = 'warn'
+
+[lints.clippy]
+            all = 'allow'
         "#;
 
-        super::merge(&mut cargo_toml, &root_toml);
+        super::merge(&mut cargo_toml, &root_toml, paths()).unwrap();
 
         assert_eq!(expected_toml_str, cargo_toml.to_string());
     }
@@ -551,7 +1064,7 @@ waldo = "waldo-vers"
     // https://github.com/toml-rs/toml/issues/691
     // https://github.com/ipetkov/crane/pull/583
     #[test]
-    fn dependency_comments_ignored() {
+    fn dependency_comments_preserved() {
         let mut cargo_toml = toml_edit::DocumentMut::from_str(
             r#"
             [package]
@@ -581,19 +1094,508 @@ waldo = "waldo-vers"
             name = "alloy-consensus"
 
             [dependencies]
+            # kzg
+            thiserror = { version = "1.0", optional = true }
+
+            # arbitrary
+            arbitrary = { version = "1.3", features = ["derive"], optional = true }
+        "#;
+
+        super::merge(&mut cargo_toml, &root_toml, paths()).unwrap();
+
+        assert_eq!(expected_toml_str, cargo_toml.to_string());
+    }
+
+    #[test]
+    fn rebase_paths() {
+        let paths = |member_dir| Paths {
+            workspace_root: Path::new("/ws"),
+            member_dir: Path::new(member_dir),
+        };
+
+        assert_eq!("../hello", paths("/ws/print").rebase("hello"));
+        assert_eq!("../hello", paths("/ws/print").rebase("./hello/"));
+        assert_eq!("../../hello", paths("/ws/crates/print").rebase("hello"));
+        assert_eq!("../world", paths("/ws/crates/print").rebase("crates/world"));
+        assert_eq!("..", paths("/ws/print").rebase("."));
+        assert_eq!(".", paths("/ws/print").rebase("print"));
+        assert_eq!("hello", paths("/ws").rebase("hello"));
+        assert_eq!(
+            "../../../shared",
+            paths("/ws/crates/print").rebase("../shared")
+        );
+        assert_eq!("/abs/hello", paths("/ws/print").rebase("/abs/hello"));
+    }
+
+    #[test]
+    fn inherited_paths_are_rebased_for_nested_members() {
+        let mut cargo_toml = toml_edit::DocumentMut::from_str(
+            r#"
+            [package]
+            name = "print"
+            readme.workspace = true
+            license-file.workspace = true
+
+            [dependencies]
+            hello = { workspace = true }
+            world = { workspace = true, features = ["loud"] }
+            local = { path = "../local" }
+            registry.workspace = true
+
+            [dev-dependencies]
+            shared.workspace = true
+        "#,
+        )
+        .unwrap();
+
+        let root_toml = toml_edit::DocumentMut::from_str(
+            r#"
+            [workspace]
+            members = ["crates/nested/print"]
 
-[dependencies.thiserror]
-version = "1.0"
-optional = true 
+            [workspace.package]
+            readme = "README.md"
+            license-file = "LICENSE"
+
+            [workspace.dependencies]
+            hello = { version = "*", path = "hello" }
+            world = { path = "./crates/world" }
+            shared = { path = "../shared" }
+            registry = "1.0"
+        "#,
+        )
+        .unwrap();
+
+        let expected_toml_str = r#"
+            [package]
+            name = "print"
+            readme = "../../../README.md"
+            license-file = "../../../LICENSE"
+
+            [dependencies]
+            hello = { version = "*", path = "../../../hello" }
+            world = { path = "../../world", features = ["loud"] }
+            local = { path = "../local" }
+            registry = { version = "1.0" }
 
-[dependencies.arbitrary]
-version = "1.3"
-features = ["derive"]
-optional = true 
+            [dev-dependencies]
+            shared = { path = "../../../../shared" }
         "#;
 
-        super::merge(&mut cargo_toml, &root_toml);
+        super::merge(
+            &mut cargo_toml,
+            &root_toml,
+            Paths {
+                workspace_root: Path::new("/ws"),
+                member_dir: Path::new("/ws/crates/nested/print"),
+            },
+        )
+        .unwrap();
 
         assert_eq!(expected_toml_str, cargo_toml.to_string());
     }
+
+    #[test]
+    fn boolean_readme_is_inherited_as_is() {
+        let mut cargo_toml = toml_edit::DocumentMut::from_str(
+            r#"
+            [package]
+            readme.workspace = true
+        "#,
+        )
+        .unwrap();
+        let root_toml = toml_edit::DocumentMut::from_str(
+            r#"
+            [workspace.package]
+            readme = false
+        "#,
+        )
+        .unwrap();
+
+        super::merge(&mut cargo_toml, &root_toml, paths()).unwrap();
+
+        assert_eq!(
+            Some(false),
+            cargo_toml["package"]["readme"].as_bool(),
+            "{cargo_toml}"
+        );
+    }
+
+    /// Lay out `files` (path, contents) in a fresh temporary directory
+    fn tree(files: &[(&str, &str)]) -> tempfile::TempDir {
+        // Canonical, so paths found by walking up compare equal to it
+        let dir = tempfile::tempdir_in(std::env::temp_dir().canonicalize().unwrap()).unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn root_of(dir: &Path, member: &str) -> Option<std::path::PathBuf> {
+        let member_dir = dir.join(member);
+        let toml = super::parse_toml(&member_dir.join("Cargo.toml")).unwrap();
+        super::find_workspace_root(&member_dir, &toml).unwrap()
+    }
+
+    #[test]
+    fn workspace_root_is_found_by_walking_up() {
+        let tmp = tree(&[
+            (
+                "Cargo.toml",
+                r#"
+                    [workspace]
+                    members = ["crates/*", "vendored/kept"]
+                    exclude = ["vendored", "examples"]
+                    "#,
+            ),
+            ("crates/a/Cargo.toml", "[package]\nname = \"a\"\n"),
+            ("vendored/kept/Cargo.toml", "[package]\nname = \"kept\"\n"),
+            ("vendored/other/Cargo.toml", "[package]\nname = \"other\"\n"),
+            (
+                "examples/own/Cargo.toml",
+                "[package]\nname = \"own\"\n\n[workspace]\n",
+            ),
+        ]);
+
+        let dir = tmp.path();
+
+        assert_eq!(Some(dir.to_path_buf()), root_of(dir, "crates/a"));
+        assert_eq!(Some(dir.to_path_buf()), root_of(dir, "vendored/kept"));
+        assert_eq!(None, root_of(dir, "vendored/other"));
+        assert_eq!(Some(dir.join("examples/own")), root_of(dir, "examples/own"));
+        assert_eq!(Some(dir.to_path_buf()), root_of(dir, ""));
+    }
+
+    #[test]
+    fn package_workspace_points_at_the_root() {
+        let tmp = tree(&[
+            (
+                "ws/Cargo.toml",
+                "[workspace]\nmembers = [\"../elsewhere\"]\n",
+            ),
+            (
+                "elsewhere/Cargo.toml",
+                "[package]\nname = \"elsewhere\"\nworkspace = \"../ws\"\n",
+            ),
+            (
+                "elsewhere/nested/Cargo.toml",
+                "[package]\nname = \"nested\"\n",
+            ),
+            (
+                "broken/Cargo.toml",
+                "[package]\nname = \"broken\"\nworkspace = \"../missing\"\n",
+            ),
+        ]);
+
+        let dir = tmp.path();
+
+        assert_eq!(Some(dir.join("ws")), root_of(dir, "elsewhere"));
+        assert_eq!(Some(dir.join("ws")), root_of(dir, "elsewhere/nested"));
+
+        let broken = dir.join("broken");
+        let toml = super::parse_toml(&broken.join("Cargo.toml")).unwrap();
+        assert!(super::find_workspace_root(&broken, &toml).is_err());
+    }
+
+    #[test]
+    fn members_globs() {
+        let glob =
+            |pattern: &str, path: &str| super::glob_matches(Path::new(pattern), Path::new(path));
+
+        assert!(glob("crates/*", "crates/a"));
+        assert!(!glob("crates/*", "crates/a/b"));
+        assert!(glob("crates/**", "crates/a/b"));
+        assert!(glob("**/b", "crates/a/b"));
+        assert!(glob("crates/lib-?", "crates/lib-x"));
+        assert!(!glob("crates/lib-?", "crates/lib-xy"));
+        assert!(glob("crates/*-sys", "crates/foo-sys"));
+        assert!(!glob("crates/*-sys", "crates/foo"));
+    }
+
+    /// Resolve each `testdata/golden/<case>/member.toml` against the `workspace.toml` next to
+    /// it and compare with `expected.toml`; set `UPDATE_GOLDEN=1` to rewrite the expectations
+    #[test]
+    fn golden() {
+        for case in cases("golden") {
+            let read = |name: &str| std::fs::read_to_string(case.join(name)).unwrap();
+            let mut cargo_toml = toml_edit::DocumentMut::from_str(&read("member.toml")).unwrap();
+            let root_toml = toml_edit::DocumentMut::from_str(&read("workspace.toml")).unwrap();
+
+            super::merge(
+                &mut cargo_toml,
+                &root_toml,
+                Paths {
+                    workspace_root: Path::new("/ws"),
+                    member_dir: Path::new("/ws/crates/member"),
+                },
+            )
+            .unwrap();
+
+            let expected = case.join("expected.toml");
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                std::fs::write(&expected, cargo_toml.to_string()).unwrap();
+                continue;
+            }
+            assert_eq!(
+                std::fs::read_to_string(&expected).unwrap(),
+                cargo_toml.to_string(),
+                "{}",
+                case.display()
+            );
+        }
+    }
+
+    /// The case directories of `testdata/<suite>`
+    fn cases(suite: &str) -> Vec<std::path::PathBuf> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(suite);
+        let mut cases: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        cases.sort();
+        assert!(!cases.is_empty());
+        cases
+    }
+
+    /// Lay out a `testdata` case as a real workspace, with the member at `crates/member` and a
+    /// stub crate for each path dependency of the workspace
+    fn workspace_of(case: &Path) -> tempfile::TempDir {
+        let read = |name: &str| std::fs::read_to_string(case.join(name)).unwrap();
+        let (workspace, member) = (read("workspace.toml"), read("member.toml"));
+
+        let mut files = vec![
+            ("Cargo.toml".to_string(), workspace.clone()),
+            ("crates/member/Cargo.toml".to_string(), member),
+            ("crates/member/src/lib.rs".to_string(), String::new()),
+        ];
+        let root = toml_edit::DocumentMut::from_str(&workspace).unwrap();
+        let deps = root
+            .get("workspace")
+            .and_then(|w| w.get("dependencies"))
+            .and_then(super::try_as_table_like);
+        for (key, dep) in deps.into_iter().flat_map(|deps| deps.iter()) {
+            let Some(path) = dep.get("path").and_then(Item::as_str) else {
+                continue;
+            };
+            let name = dep.get("package").and_then(Item::as_str).unwrap_or(key);
+            let version = dep
+                .get("version")
+                .and_then(Item::as_str)
+                .map_or("0.1.0", |v| v.trim_start_matches(['=', '^', '~']));
+            files.push((
+                format!("{path}/Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n"),
+            ));
+            files.push((format!("{path}/src/lib.rs"), String::new()));
+        }
+
+        let files: Vec<_> = files
+            .iter()
+            .map(|(p, c)| (p.as_str(), c.as_str()))
+            .collect();
+        tree(&files)
+    }
+
+    /// What `cargo metadata` says about the package at `crates/member`, or why it refused
+    fn cargo_metadata(dir: &Path) -> Result<serde_json::Value, String> {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let output = std::process::Command::new(cargo)
+            .args([
+                "metadata",
+                "--no-deps",
+                "--offline",
+                "--format-version",
+                "1",
+            ])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+
+        let manifest = dir.join("crates/member/Cargo.toml");
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        Ok(metadata["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|package| package["manifest_path"] == manifest.to_str().unwrap())
+            .cloned()
+            .unwrap())
+    }
+
+    /// Every `testdata` case resolves to a manifest `cargo metadata` reads exactly like the
+    /// original, and the resolver rejects whatever cargo rejects
+    #[test]
+    fn conformance() {
+        for case in cases("golden").into_iter().chain(cases("conformance")) {
+            let tmp = workspace_of(&case);
+            let dir = tmp.path();
+            let member_dir = dir.join("crates/member");
+            let cargo_toml_path = member_dir.join("Cargo.toml");
+
+            let expected = cargo_metadata(dir);
+            let mut cargo_toml = super::parse_toml(&cargo_toml_path).unwrap();
+            let resolved = super::merge(
+                &mut cargo_toml,
+                &super::parse_toml(&dir.join("Cargo.toml")).unwrap(),
+                Paths {
+                    workspace_root: dir,
+                    member_dir: &member_dir,
+                },
+            );
+
+            match (expected, resolved) {
+                (Ok(expected), Ok(_)) => {
+                    std::fs::write(&cargo_toml_path, cargo_toml.to_string()).unwrap();
+                    let actual = cargo_metadata(dir).unwrap();
+                    assert_eq!(expected, actual, "{}", case.display());
+                }
+                (Err(_), Err(_)) => {}
+                (Ok(_), Err(err)) => panic!("{}: cargo accepts it, but: {err:?}", case.display()),
+                (Err(err), Ok(_)) => panic!("{}: cargo rejects it, but: {err}", case.display()),
+            }
+        }
+    }
+
+    /// Resolve `testdata/conformance/<case>` without touching the file system
+    fn resolve_case(case: &str) -> anyhow::Result<(String, Vec<String>)> {
+        let case = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/conformance")
+            .join(case);
+        let mut cargo_toml = super::parse_toml(&case.join("member.toml")).unwrap();
+        let warnings = super::merge(
+            &mut cargo_toml,
+            &super::parse_toml(&case.join("workspace.toml")).unwrap(),
+            Paths {
+                workspace_root: Path::new("/ws"),
+                member_dir: Path::new("/ws/crates/member"),
+            },
+        )?;
+        Ok((cargo_toml.to_string(), warnings))
+    }
+
+    #[test]
+    fn default_features_follow_cargo() {
+        let (cargo_toml, warnings) = resolve_case("default-features").unwrap();
+
+        let deps = toml_edit::DocumentMut::from_str(&cargo_toml).unwrap()["dependencies"].clone();
+        let deps: Vec<_> = deps
+            .as_table()
+            .unwrap()
+            .iter()
+            .map(|(name, dep)| {
+                format!(
+                    "{name} = {}",
+                    dep.as_inline_table().unwrap().to_string().trim()
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                r#"off-on = { version = "1.0", default-features = true }"#,
+                r#"on-off = { version = "1.0", default-features = true }"#,
+                r#"simple-off = { version = "1.0" }"#,
+                r#"table-off = { version = "1.0", features = ["a"] }"#,
+                r#"off-off = { version = "1.0", default-features = false, features = ["a", "c"] }"#,
+                r#"features = { version = "1.0", features = ["a", "b", "b", "c"] }"#,
+                r#"optional = { version = "1.0", optional = true }"#,
+            ],
+            deps
+        );
+
+        assert_eq!(
+            vec![
+                "`default-features` is ignored for on-off, since `default-features` was true \
+                 for `workspace.dependencies.on-off`, this could become a hard error in the future",
+                "`default-features` is ignored for simple-off, since `default-features` was not \
+                 specified for `workspace.dependencies.simple-off`, this could become a hard error \
+                 in the future",
+                "`default_features` is deprecated in favor of `default-features` and will not \
+                 work in the 2024 edition\n(in the `table-off` dependency)",
+                "`default-features` is ignored for table-off, since `default-features` was not \
+                 specified for `workspace.dependencies.table-off`, this could become a hard error \
+                 in the future",
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn edition_2024_renames_the_crates_own_default_features() {
+        let (cargo_toml, warnings) = resolve_case("edition-2024").unwrap();
+
+        assert!(
+            cargo_toml.contains(r#"underscore-on = { version = "1.0", default-features = true }"#),
+            "{cargo_toml}"
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn unused_keys_are_dropped_with_a_warning() {
+        let (cargo_toml, warnings) = resolve_case("unused-keys").unwrap();
+
+        assert!(
+            cargo_toml.contains(r#"serde = { version = "1.0", features = ["derive", "rc"] }"#),
+            "{cargo_toml}"
+        );
+        assert_eq!(
+            vec![
+                "unused manifest key: dependencies.serde.version",
+                "unused manifest key: dependencies.serde.package",
+                "unused manifest key: package.version.also",
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn invalid_inheritance_is_rejected() {
+        for (case, error) in [
+            (
+                "invalid-missing-dependency",
+                "error inheriting `serde_json` from workspace root manifest's \
+                 `workspace.dependencies.serde_json`: `dependency.serde_json` was not found in \
+                 `workspace.dependencies`",
+            ),
+            (
+                "invalid-workspace-false",
+                "invalid `package.version`: `workspace` cannot be false",
+            ),
+            (
+                "invalid-optional-in-workspace",
+                "serde is optional, but workspace dependencies cannot be optional",
+            ),
+            (
+                "invalid-lints-override",
+                "cannot override `workspace.lints` in `lints`, either remove the overrides or \
+                 `lints.workspace = true` and manually specify the lints",
+            ),
+            (
+                "invalid-2024-default-features",
+                "error inheriting `serde` from workspace root manifest's \
+                 `workspace.dependencies.serde`: `default-features = false` cannot override \
+                 workspace's `default-features`",
+            ),
+            (
+                "invalid-2024-underscore-default-features",
+                "`default_features` is unsupported as of the 2024 edition; instead use \
+                 `default-features`\n(in the `serde` dependency)",
+            ),
+            (
+                "invalid-missing-package-key",
+                "error inheriting `license` from workspace root manifest's \
+                 `workspace.package.license`: `workspace.package.license` was not defined",
+            ),
+        ] {
+            let err = resolve_case(case).unwrap_err();
+            assert_eq!(error, format!("{err:#}"), "{case}");
+        }
+    }
 }